@@ -0,0 +1,300 @@
+use std::collections::HashSet;
+use std::fs;
+use std::path::PathBuf;
+use std::time::Duration;
+
+use base64::Engine;
+use serde::Serialize;
+use serde_json::Value;
+use sha2::{Digest, Sha256};
+use tauri::{AppHandle, Manager};
+
+use crate::client::HttpClient;
+use crate::{storage, Message};
+
+// 消息中以 attachment://<id> 引用附件，发送前再替换为 base64 data URL，
+// 避免把大图直接存进会话 JSON
+const SCHEME: &str = "attachment://";
+
+// 只有这些字段里的 attachment:// 才是附件引用，用户正文中出现同样的文字原样保留
+const REFERENCE_FIELDS: &[(&str, &str)] = &[("image_url", "url"), ("input_audio", "data")];
+
+// 刚存入、还没随会话保存的附件不算孤立
+const ORPHAN_MIN_AGE: Duration = Duration::from_secs(24 * 60 * 60);
+
+// 附件按内容的 SHA-256 命名，相同内容只存一份
+fn attachments_dir(app_handle: &AppHandle) -> Result<PathBuf, String> {
+    let dir = app_handle
+        .path()
+        .app_data_dir()
+        .map_err(|e| format!("Failed to resolve app data dir: {}", e))?
+        .join("attachments");
+    fs::create_dir_all(&dir).map_err(|e| format!("Failed to create attachments dir: {}", e))?;
+    Ok(dir)
+}
+
+fn attachment_path(app_handle: &AppHandle, id: &str) -> Result<PathBuf, String> {
+    if id.is_empty() || !id.chars().all(|c| c.is_ascii_hexdigit()) {
+        return Err(format!("Invalid attachment id: {}", id));
+    }
+    Ok(attachments_dir(app_handle)?.join(id))
+}
+
+// 根据文件头识别常见图片格式
+fn mime_type(bytes: &[u8]) -> &'static str {
+    if bytes.starts_with(b"\x89PNG\r\n\x1a\n") {
+        "image/png"
+    } else if bytes.starts_with(b"\xff\xd8\xff") {
+        "image/jpeg"
+    } else if bytes.starts_with(b"GIF87a") || bytes.starts_with(b"GIF89a") {
+        "image/gif"
+    } else if bytes.len() >= 12 && &bytes[..4] == b"RIFF" && &bytes[8..12] == b"WEBP" {
+        "image/webp"
+    } else {
+        "application/octet-stream"
+    }
+}
+
+// 服务端对音频输入的大小限制通常在 25MB 左右
+const MAX_AUDIO_BYTES: u64 = 25 * 1024 * 1024;
+
+fn audio_format(bytes: &[u8]) -> Option<&'static str> {
+    if bytes.len() >= 12 && &bytes[..4] == b"RIFF" && &bytes[8..12] == b"WAVE" {
+        Some("wav")
+    } else if bytes.starts_with(b"ID3")
+        || (bytes.len() >= 2 && bytes[0] == 0xff && bytes[1] & 0xe0 == 0xe0)
+    {
+        Some("mp3")
+    } else {
+        None
+    }
+}
+
+fn references(content: &Value) -> impl Iterator<Item = (&str, &str)> {
+    content.as_array().into_iter().flatten().filter_map(|part| {
+        REFERENCE_FIELDS.iter().find_map(|&(outer, inner)| {
+            let id = part
+                .get(outer)?
+                .get(inner)?
+                .as_str()?
+                .strip_prefix(SCHEME)?;
+            Some((outer, id))
+        })
+    })
+}
+
+fn collect_references(content: &Value, ids: &mut HashSet<String>) {
+    ids.extend(references(content).map(|(_, id)| id.to_string()));
+}
+
+fn inline_references(app_handle: &AppHandle, content: &mut Value) -> Result<(), String> {
+    for part in content.as_array_mut().into_iter().flatten() {
+        for &(outer, inner) in REFERENCE_FIELDS {
+            let Some(Value::String(text)) = part.get_mut(outer).and_then(|v| v.get_mut(inner))
+            else {
+                continue;
+            };
+            let Some(id) = text.strip_prefix(SCHEME) else {
+                continue;
+            };
+            let bytes = fs::read(attachment_path(app_handle, id)?)
+                .map_err(|e| format!("Failed to read attachment {}: {}", id, e))?;
+            let encoded = base64::engine::general_purpose::STANDARD.encode(&bytes);
+            // input_audio.data 是不带前缀的 base64
+            *text = match outer {
+                "input_audio" => encoded,
+                _ => format!("data:{};base64,{}", mime_type(&bytes), encoded),
+            };
+        }
+    }
+    Ok(())
+}
+
+// 写到一半的临时文件和新近存入的附件都不删
+fn is_orphan(name: &str, referenced: &HashSet<String>, age: Duration) -> bool {
+    !name.ends_with(".tmp") && !referenced.contains(name) && age >= ORPHAN_MIN_AGE
+}
+
+#[tauri::command]
+pub fn store_attachment(bytes: Vec<u8>, app_handle: AppHandle) -> Result<String, String> {
+    let id = format!("{:x}", Sha256::digest(&bytes));
+    let path = attachment_path(&app_handle, &id)?;
+    if !path.exists() {
+        let tmp = path.with_extension("tmp");
+        fs::write(&tmp, &bytes).map_err(|e| format!("Failed to write attachment: {}", e))?;
+        fs::rename(&tmp, &path).map_err(|e| format!("Failed to write attachment: {}", e))?;
+    }
+    Ok(format!("{}{}", SCHEME, id))
+}
+
+// 把消息中的附件引用替换为 base64 内容，在发送给服务端前调用
+#[tauri::command]
+pub fn resolve_attachments(
+    messages: Vec<Message>,
+    app_handle: AppHandle,
+) -> Result<Vec<Message>, String> {
+    let mut messages = messages;
+    for message in &mut messages {
+        inline_references(&app_handle, &mut message.content)?;
+    }
+    Ok(messages)
+}
+
+// 删除没有被任何已保存会话引用、且存入超过 ORPHAN_MIN_AGE 的附件，返回删除的数量
+#[tauri::command]
+pub fn delete_orphan_attachments(app_handle: AppHandle) -> Result<usize, String> {
+    let mut referenced = HashSet::new();
+    for conversation in storage::load_all(&app_handle)? {
+        for message in &conversation.messages {
+            collect_references(&message.content, &mut referenced);
+        }
+    }
+
+    let dir = attachments_dir(&app_handle)?;
+    let entries =
+        fs::read_dir(&dir).map_err(|e| format!("Failed to read attachments dir: {}", e))?;
+    let mut deleted = 0;
+    for entry in entries.flatten() {
+        let name = entry.file_name().to_string_lossy().to_string();
+        // 读不到修改时间时按刚写入处理
+        let age = entry
+            .metadata()
+            .and_then(|m| m.modified())
+            .ok()
+            .and_then(|modified| modified.elapsed().ok())
+            .unwrap_or_default();
+        if !is_orphan(&name, &referenced, age) {
+            continue;
+        }
+        match fs::remove_file(entry.path()) {
+            Ok(()) => deleted += 1,
+            Err(e) => log::warn!("Failed to delete attachment {}: {}", name, e),
+        }
+    }
+    Ok(deleted)
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ImageMeta {
+    pub content_type: String,
+    // 服务端未返回 Content-Length 时为空
+    pub size: Option<u64>,
+}
+
+// 发送前确认图片 URL 可访问且确实是图片，避免到服务端才报多模态错误
+#[tauri::command]
+pub async fn validate_image_url(
+    url: String,
+    http: tauri::State<'_, HttpClient>,
+) -> Result<ImageMeta, String> {
+    let client = http.client_for(&url)?;
+    let send = |method: reqwest::Method| {
+        client
+            .request(method, &url)
+            .timeout(std::time::Duration::from_secs(10))
+            .send()
+    };
+
+    let mut response = send(reqwest::Method::HEAD)
+        .await
+        .map_err(|e| format!("Failed to reach image URL {}: {}", url, e))?;
+    // 部分图床不支持 HEAD，改用 GET 只读响应头
+    if response.status() == reqwest::StatusCode::METHOD_NOT_ALLOWED {
+        response = send(reqwest::Method::GET)
+            .await
+            .map_err(|e| format!("Failed to reach image URL {}: {}", url, e))?;
+    }
+    if !response.status().is_success() {
+        return Err(format!(
+            "Image URL {} returned status {}",
+            url,
+            response.status()
+        ));
+    }
+
+    let content_type = response
+        .headers()
+        .get(reqwest::header::CONTENT_TYPE)
+        .and_then(|v| v.to_str().ok())
+        .unwrap_or_default()
+        .to_lowercase();
+    if !content_type.starts_with("image/") {
+        return Err(format!(
+            "URL {} is not an image (content type: {})",
+            url,
+            if content_type.is_empty() {
+                "unknown"
+            } else {
+                &content_type
+            }
+        ));
+    }
+
+    // HEAD 响应没有响应体，content_length() 会是 0，直接读响应头
+    let size = response
+        .headers()
+        .get(reqwest::header::CONTENT_LENGTH)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.parse().ok());
+
+    Ok(ImageMeta { content_type, size })
+}
+
+// 读取本地音频文件，生成可直接放进消息的 input_audio 内容
+#[tauri::command]
+pub fn encode_audio(path: String) -> Result<Value, String> {
+    let size = fs::metadata(&path)
+        .map_err(|e| format!("Failed to read audio file {}: {}", path, e))?
+        .len();
+    if size > MAX_AUDIO_BYTES {
+        return Err(format!(
+            "Audio file {} is {} bytes, exceeding the limit of {} bytes",
+            path, size, MAX_AUDIO_BYTES
+        ));
+    }
+
+    let bytes =
+        fs::read(&path).map_err(|e| format!("Failed to read audio file {}: {}", path, e))?;
+    let format = audio_format(&bytes)
+        .ok_or_else(|| format!("Unsupported audio format (expected wav or mp3): {}", path))?;
+
+    Ok(serde_json::json!({
+        "type": "input_audio",
+        "input_audio": {
+            "data": base64::engine::general_purpose::STANDARD.encode(&bytes),
+            "format": format,
+        },
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn only_media_fields_are_references() {
+        let content = json!([
+            { "type": "text", "text": "attachment://abc" },
+            { "type": "image_url", "image_url": { "url": "attachment://01ab" } },
+            { "type": "input_audio", "input_audio": { "data": "attachment://02cd", "format": "wav" } },
+            { "type": "image_url", "image_url": { "url": "https://example.com/a.png" } },
+        ]);
+        assert_eq!(
+            references(&content).collect::<Vec<_>>(),
+            [("image_url", "01ab"), ("input_audio", "02cd")]
+        );
+        // 纯文本消息里的同样文字不是引用
+        assert_eq!(references(&json!("attachment://abc")).count(), 0);
+    }
+
+    #[test]
+    fn recent_and_temporary_files_are_not_orphans() {
+        let referenced = HashSet::from(["used".to_string()]);
+        let old = ORPHAN_MIN_AGE + Duration::from_secs(1);
+        assert!(is_orphan("unused", &referenced, old));
+        assert!(!is_orphan("used", &referenced, old));
+        assert!(!is_orphan("unused", &referenced, Duration::from_secs(5)));
+        assert!(!is_orphan("unused.tmp", &referenced, old));
+    }
+}
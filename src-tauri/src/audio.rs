@@ -0,0 +1,123 @@
+use base64::Engine;
+use futures_util::stream::StreamExt;
+use serde::{Deserialize, Serialize};
+use tauri::{Emitter, Manager};
+
+use crate::client::{self, HttpClient};
+
+#[derive(Debug, Clone, Serialize)]
+pub struct SpeechChunk {
+    // base64 编码的音频片段，按到达顺序拼接即可播放
+    pub data: String,
+    pub done: bool,
+}
+
+// 调用 /audio/speech 朗读文本。音频边下载边通过 tts-chunk 事件发送，长文本无需等全部生成；
+// 命令最终仍返回完整的音频字节
+#[tauri::command]
+pub async fn text_to_speech(
+    base_url: String,
+    api_key: String,
+    model: String,
+    input: String,
+    voice: String,
+    format: Option<String>,
+    app_handle: tauri::AppHandle,
+) -> Result<Vec<u8>, String> {
+    let url = format!("{}/audio/speech", base_url);
+    let client = app_handle.state::<HttpClient>().client_for(&url)?;
+
+    let response = client
+        .post(&url)
+        .header("Content-Type", "application/json")
+        .header("Authorization", format!("Bearer {}", api_key))
+        .json(&serde_json::json!({
+            "model": model,
+            "input": input,
+            "voice": voice,
+            "response_format": format.unwrap_or_else(|| "mp3".to_string()),
+        }))
+        .send()
+        .await
+        .map_err(|e| format!("Failed to send request: {}", e))?;
+    let response = client::check_supported(response, &base_url, "Text to speech").await?;
+
+    let mut audio = Vec::new();
+    let mut stream = response.bytes_stream();
+    while let Some(chunk) = stream.next().await {
+        let chunk = chunk.map_err(|e| format!("Failed to read audio: {}", e))?;
+        let _ = app_handle.emit(
+            "tts-chunk",
+            SpeechChunk {
+                data: base64::engine::general_purpose::STANDARD.encode(&chunk),
+                done: false,
+            },
+        );
+        audio.extend_from_slice(&chunk);
+    }
+    let _ = app_handle.emit(
+        "tts-chunk",
+        SpeechChunk {
+            data: String::new(),
+            done: true,
+        },
+    );
+    Ok(audio)
+}
+
+#[derive(Debug, Deserialize)]
+struct TranscriptionResponse {
+    text: String,
+}
+
+// 以 multipart 表单上传音频到 /audio/transcriptions，返回识别出的文本
+#[tauri::command]
+pub async fn transcribe_audio(
+    base_url: String,
+    api_key: String,
+    model: String,
+    path: String,
+    language: Option<String>,
+    prompt: Option<String>,
+    http: tauri::State<'_, HttpClient>,
+) -> Result<String, String> {
+    let url = format!("{}/audio/transcriptions", base_url);
+    let client = http.client_for(&url)?;
+
+    let bytes =
+        std::fs::read(&path).map_err(|e| format!("Failed to read audio file {}: {}", path, e))?;
+    let file_name = std::path::Path::new(&path)
+        .file_name()
+        .map(|name| name.to_string_lossy().to_string())
+        .unwrap_or_else(|| "audio".to_string());
+
+    let mut form = reqwest::multipart::Form::new()
+        .text("model", model)
+        .text("response_format", "json")
+        .part(
+            "file",
+            reqwest::multipart::Part::bytes(bytes).file_name(file_name),
+        );
+    // language 为 ISO-639-1 代码；prompt 可提供专有名词等上下文提高准确率
+    if let Some(language) = language {
+        form = form.text("language", language);
+    }
+    if let Some(prompt) = prompt {
+        form = form.text("prompt", prompt);
+    }
+
+    let response = client
+        .post(&url)
+        .header("Authorization", format!("Bearer {}", api_key))
+        .multipart(form)
+        .send()
+        .await
+        .map_err(|e| format!("Failed to send request: {}", e))?;
+    let response = client::check_supported(response, &base_url, "Transcription").await?;
+
+    response
+        .json::<TranscriptionResponse>()
+        .await
+        .map(|parsed| parsed.text)
+        .map_err(|e| format!("Failed to parse response: {}", e))
+}
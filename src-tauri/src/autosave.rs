@@ -0,0 +1,279 @@
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::Duration;
+
+use serde::Serialize;
+use tauri::{AppHandle, Manager};
+
+use crate::storage::{self, Conversation};
+use crate::Message;
+
+// 同一会话在这段时间内的多次更新只写入最后一次
+const DEBOUNCE: Duration = Duration::from_millis(500);
+const TITLE_CHARS: usize = 30;
+
+// 聊天命令开启 autosave 时，在 Rust 侧保存每轮对话，不再依赖前端记得调用 save_conversation
+#[derive(Default)]
+pub struct Autosave {
+    // 会话 id -> 尚未写入的最近一次更新。写入时持有锁，flush 据此等待进行中的写入
+    pending: Mutex<HashMap<String, PendingWrite>>,
+}
+
+struct PendingWrite {
+    generation: u64,
+    messages: Vec<Message>,
+    resumable: bool,
+}
+
+fn default_title(messages: &[Message]) -> String {
+    let text = messages
+        .iter()
+        .find(|m| m.role == "user")
+        .map(|m| crate::message_text(&m.content))
+        .unwrap_or_default();
+    let title: String = text.trim().chars().take(TITLE_CHARS).collect();
+    if title.is_empty() {
+        "New conversation".to_string()
+    } else {
+        title
+    }
+}
+
+// 已有会话保留标题、标签和创建时间
+fn updated(
+    existing: Option<Conversation>,
+    id: &str,
+    messages: Vec<Message>,
+    resumable: bool,
+    now: u64,
+) -> Conversation {
+    match existing {
+        Some(mut conversation) => {
+            conversation.messages = messages;
+            conversation.updated_at = now;
+            conversation.resumable = resumable;
+            conversation
+        }
+        None => Conversation {
+            id: id.to_string(),
+            title: default_title(&messages),
+            messages,
+            created_at: now,
+            updated_at: now,
+            tags: Vec::new(),
+            resumable,
+            locked_model: None,
+            locked_provider: None,
+        },
+    }
+}
+
+fn persist(
+    app_handle: &AppHandle,
+    id: &str,
+    messages: Vec<Message>,
+    resumable: bool,
+) -> Result<(), String> {
+    let existing = if storage::exists(app_handle, id)? {
+        Some(storage::load(app_handle, id)?)
+    } else {
+        None
+    };
+    let conversation = updated(existing, id, messages, resumable, storage::now_millis());
+    storage::save(app_handle, &conversation)
+}
+
+fn with_reply(messages: &[Message], reply: &str) -> Vec<Message> {
+    let mut messages = messages.to_vec();
+    messages.push(Message {
+        role: "assistant".to_string(),
+        content: serde_json::Value::String(reply.to_string()),
+        pinned: None,
+    });
+    messages
+}
+
+// 流式过程中定期立即写入已生成的部分并标记为可继续，程序崩溃后也不会丢失
+pub fn checkpoint(app_handle: &AppHandle, id: &str, messages: &[Message], partial: &str) {
+    if let Err(e) = persist(app_handle, id, with_reply(messages, partial), true) {
+        log::warn!("Checkpoint of conversation {} failed: {}", id, e);
+    }
+}
+
+fn write(app_handle: &AppHandle, id: &str, update: PendingWrite) {
+    if let Err(e) = persist(app_handle, id, update.messages, update.resumable) {
+        log::warn!("Autosave of conversation {} failed: {}", id, e);
+    }
+}
+
+// 记录本轮请求的消息加上新的助手回复，延迟写入
+pub fn schedule(app_handle: &AppHandle, id: &str, messages: &[Message], reply: &str) {
+    let generation = {
+        let autosave = app_handle.state::<Autosave>();
+        let mut pending = autosave.pending.lock().unwrap();
+        let generation = pending.get(id).map_or(0, |p| p.generation + 1);
+        pending.insert(
+            id.to_string(),
+            PendingWrite {
+                generation,
+                messages: with_reply(messages, reply),
+                resumable: false,
+            },
+        );
+        generation
+    };
+
+    let app_handle = app_handle.clone();
+    let id = id.to_string();
+    tauri::async_runtime::spawn(async move {
+        tokio::time::sleep(DEBOUNCE).await;
+        let autosave = app_handle.state::<Autosave>();
+        let mut pending = autosave.pending.lock().unwrap();
+        // 期间又有新的更新，交给后面的任务写入；已被 flush 写入时条目也不在了
+        if pending.get(&id).map(|p| p.generation) != Some(generation) {
+            return;
+        }
+        if let Some(update) = pending.remove(&id) {
+            write(&app_handle, &id, update);
+        }
+    });
+}
+
+// 被取消或出错的回复不做延迟，立即写入并保留可继续标记：
+// 多半是用户正在退出或连接已断，等不到延迟结束
+pub fn save_partial(app_handle: &AppHandle, id: &str, messages: &[Message], partial: &str) {
+    let autosave = app_handle.state::<Autosave>();
+    let mut pending = autosave.pending.lock().unwrap();
+    // 之前计划的写入已经过时
+    pending.remove(id);
+    write(
+        app_handle,
+        id,
+        PendingWrite {
+            generation: 0,
+            messages: with_reply(messages, partial),
+            resumable: true,
+        },
+    );
+}
+
+// 退出前立即写入所有尚在延迟中的更新；进行中的写入持有锁，这里会等它完成
+pub fn flush(app_handle: &AppHandle) {
+    let autosave = app_handle.state::<Autosave>();
+    let mut pending = autosave.pending.lock().unwrap();
+    for (id, update) in pending.drain() {
+        write(app_handle, &id, update);
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ResumableStream {
+    pub conversation_id: String,
+    pub title: String,
+    pub partial_content: String,
+    pub updated_at: u64,
+}
+
+impl ResumableStream {
+    fn from_conversation(conversation: Conversation) -> Option<Self> {
+        if !conversation.resumable {
+            return None;
+        }
+        Some(ResumableStream {
+            partial_content: conversation
+                .messages
+                .last()
+                .filter(|m| m.role == "assistant")
+                .map(|m| crate::message_text(&m.content))
+                .unwrap_or_default(),
+            conversation_id: conversation.id,
+            title: conversation.title,
+            updated_at: conversation.updated_at,
+        })
+    }
+}
+
+// 启动后列出上次未生成完的回复，前端据此提供“继续生成”
+#[tauri::command]
+pub async fn get_resumable_streams(app_handle: AppHandle) -> Result<Vec<ResumableStream>, String> {
+    Ok(storage::load_all(&app_handle)?
+        .into_iter()
+        .filter_map(ResumableStream::from_conversation)
+        .collect())
+}
+
+// 用户放弃继续生成时清除标记
+#[tauri::command]
+pub async fn dismiss_resumable_stream(id: String, app_handle: AppHandle) -> Result<(), String> {
+    let mut conversation = storage::load(&app_handle, &id)?;
+    conversation.resumable = false;
+    storage::save(&app_handle, &conversation)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn user(content: &str) -> Message {
+        Message {
+            role: "user".to_string(),
+            content: serde_json::Value::String(content.to_string()),
+            pinned: None,
+        }
+    }
+
+    // 模拟程序退出再启动：会话写入磁盘后重新读取
+    fn restart(conversation: &Conversation) -> Conversation {
+        serde_json::from_str(&serde_json::to_string(conversation).unwrap()).unwrap()
+    }
+
+    #[test]
+    fn checkpoint_survives_restart_as_resumable() {
+        let messages = [user("Write a poem about the sea")];
+        let saved = updated(None, "c1", with_reply(&messages, "The waves"), true, 100);
+        let stream = ResumableStream::from_conversation(restart(&saved)).unwrap();
+        assert_eq!(stream.conversation_id, "c1");
+        assert_eq!(stream.title, "Write a poem about the sea");
+        assert_eq!(stream.partial_content, "The waves");
+        assert_eq!(stream.updated_at, 100);
+    }
+
+    #[test]
+    fn completed_reply_clears_resumable_flag() {
+        let messages = [user("hi")];
+        let checkpoint = updated(None, "c1", with_reply(&messages, "hel"), true, 100);
+        let mut existing = restart(&checkpoint);
+        existing.title = "Greeting".to_string();
+        existing.tags = vec!["work".to_string()];
+
+        let done = updated(
+            Some(existing),
+            "c1",
+            with_reply(&messages, "hello"),
+            false,
+            200,
+        );
+        assert!(ResumableStream::from_conversation(restart(&done)).is_none());
+        assert_eq!(done.title, "Greeting");
+        assert_eq!(done.tags, ["work"]);
+        assert_eq!((done.created_at, done.updated_at), (100, 200));
+        assert_eq!(done.messages.len(), 2);
+    }
+
+    #[test]
+    fn files_without_flag_are_not_resumable() {
+        let saved = updated(None, "c1", vec![user("hi")], true, 100);
+        let mut value = serde_json::to_value(&saved).unwrap();
+        value.as_object_mut().unwrap().remove("resumable");
+        let loaded: Conversation = serde_json::from_value(value).unwrap();
+        assert!(ResumableStream::from_conversation(loaded).is_none());
+    }
+
+    #[test]
+    fn resumable_without_assistant_reply_has_empty_partial() {
+        // 第一个分片到达前就退出
+        let saved = updated(None, "c1", vec![user("hi")], true, 100);
+        let stream = ResumableStream::from_conversation(saved).unwrap();
+        assert_eq!(stream.partial_content, "");
+    }
+}
@@ -0,0 +1,160 @@
+use std::collections::HashMap;
+use std::fs;
+use std::path::PathBuf;
+use std::sync::RwLock;
+
+use serde::Serialize;
+use tauri::{AppHandle, Manager};
+
+use crate::client::HttpClient;
+use crate::messages::estimate_tokens;
+use crate::model_alias::canonicalize_model;
+use crate::provider::Provider;
+use crate::{build_request_body, send_chat_request, ChatOptions, Message};
+
+#[derive(Debug, Clone, Serialize)]
+pub struct CalibrationResult {
+    pub model: String,
+    // 服务端报告的 prompt_tokens
+    pub provider_tokens: u32,
+    // 未校准的本地估算值
+    pub estimated_tokens: usize,
+    // provider_tokens / estimated_tokens，之后 count_tokens 按这个系数修正
+    pub correction_factor: f64,
+    // 未校准估算的相对误差（百分比），正数表示低估
+    pub error_percent: f64,
+}
+
+// 模型名 -> 修正系数，启动时从磁盘加载
+#[derive(Default)]
+pub struct TokenCalibration(RwLock<HashMap<String, f64>>);
+
+fn calibration_path(app_handle: &AppHandle) -> Result<PathBuf, String> {
+    let dir = app_handle
+        .path()
+        .app_data_dir()
+        .map_err(|e| format!("Failed to resolve app data dir: {}", e))?;
+    fs::create_dir_all(&dir).map_err(|e| format!("Failed to create app data dir: {}", e))?;
+    Ok(dir.join("token_calibration.json"))
+}
+
+fn save(app_handle: &AppHandle, factors: &HashMap<String, f64>) -> Result<(), String> {
+    let path = calibration_path(app_handle)?;
+    let text = serde_json::to_string_pretty(factors)
+        .map_err(|e| format!("Failed to serialize token calibration: {}", e))?;
+    let tmp = path.with_extension("json.tmp");
+    fs::write(&tmp, text).map_err(|e| format!("Failed to write token calibration: {}", e))?;
+    fs::rename(&tmp, &path).map_err(|e| format!("Failed to write token calibration: {}", e))
+}
+
+// 系数按规范模型名保存和查找，大小写、厂商前缀不同的写法和别名共用一个系数。
+// 按直连官方接口的写法规范化，始终不带厂商前缀
+fn calibration_key(model: &str) -> String {
+    canonicalize_model(Provider::OpenAi, model.trim().to_string())
+}
+
+pub fn init(app_handle: &AppHandle) {
+    let factors = calibration_path(app_handle)
+        .ok()
+        .and_then(|path| fs::read_to_string(path).ok())
+        .and_then(|text| serde_json::from_str::<HashMap<String, f64>>(&text).ok());
+    if let Some(factors) = factors {
+        *app_handle.state::<TokenCalibration>().0.write().unwrap() = factors;
+    }
+}
+
+// 按模型的修正系数校准后的 token 估算，未校准的模型直接使用粗略估算
+pub fn count(app_handle: &AppHandle, model: &str, messages: &[Message]) -> usize {
+    let estimated = estimate_tokens(messages);
+    let calibration = app_handle.state::<TokenCalibration>();
+    let factor = calibration
+        .0
+        .read()
+        .unwrap()
+        .get(&calibration_key(model))
+        .copied();
+    match factor {
+        Some(factor) => (estimated as f64 * factor).round() as usize,
+        None => estimated,
+    }
+}
+
+#[tauri::command]
+pub fn count_tokens(messages: Vec<Message>, model: String, app_handle: AppHandle) -> usize {
+    count(&app_handle, &model, &messages)
+}
+
+// 用一段样本文本请求一次（只生成 1 个 token），比较服务端报告的 prompt_tokens 与本地估算，
+// 记录该模型的修正系数
+#[tauri::command]
+pub async fn calibrate_tokenizer(
+    mut base_url: String,
+    api_key: String,
+    mut model: String,
+    sample: String,
+    app_handle: AppHandle,
+) -> Result<CalibrationResult, String> {
+    if sample.trim().is_empty() {
+        return Err("Calibration sample cannot be empty".to_string());
+    }
+    let options = ChatOptions {
+        max_tokens: Some(1),
+        ..Default::default()
+    };
+    let options = ChatOptions::resolve(Some(options), &mut base_url, &mut model, &app_handle)?;
+    let url = format!("{}/chat/completions", base_url);
+    let client = app_handle.state::<HttpClient>().client_for(&url)?;
+    let messages = [Message {
+        role: "user".to_string(),
+        content: serde_json::Value::String(sample),
+        pinned: None,
+    }];
+    let request_body = build_request_body(&model, &messages, false, false, &options)?;
+    let response = send_chat_request(
+        &client,
+        &url,
+        &api_key,
+        &request_body,
+        &options,
+        &app_handle,
+    )
+    .await?;
+    let provider_tokens = response
+        .usage
+        .map(|usage| usage.prompt_tokens)
+        .filter(|&tokens| tokens > 0)
+        .ok_or_else(|| format!("{} did not report prompt_tokens", model))?;
+
+    let estimated_tokens = estimate_tokens(&messages);
+    let correction_factor = provider_tokens as f64 / estimated_tokens as f64;
+    let calibration = app_handle.state::<TokenCalibration>();
+    let mut factors = calibration.0.write().unwrap();
+    factors.insert(calibration_key(&model), correction_factor);
+    save(&app_handle, &factors)?;
+
+    Ok(CalibrationResult {
+        model,
+        provider_tokens,
+        estimated_tokens,
+        correction_factor,
+        error_percent: (correction_factor - 1.0) * 100.0,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn spellings_of_one_model_share_a_key() {
+        let key = calibration_key("gpt-4o");
+        for spelling in ["GPT-4o", "openai/gpt-4o", " gpt-4o "] {
+            assert_eq!(calibration_key(spelling), key, "{}", spelling);
+        }
+    }
+
+    #[test]
+    fn unknown_models_keep_their_name() {
+        assert_eq!(calibration_key("my-finetune"), "my-finetune");
+    }
+}
@@ -0,0 +1,224 @@
+use std::collections::HashMap;
+use std::sync::Mutex;
+
+use serde::{Deserialize, Serialize};
+
+use crate::client::{self, HttpClient};
+use crate::sse::SseParser;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Capabilities {
+    pub supports_thinking: bool,
+    pub supports_tools: bool,
+    pub supports_usage_in_stream: bool,
+    pub supports_vision: bool,
+}
+
+// 探测结果按 base_url + model 缓存，避免重复发送探测请求。
+// 只缓存每一项都有确定结果的探测，否则下次重新探测
+#[derive(Default)]
+pub struct CapabilityCache(Mutex<HashMap<String, Capabilities>>);
+
+// 1x1 像素的 PNG，用于探测是否支持图片输入
+const PROBE_IMAGE: &str = "data:image/png;base64,iVBORw0KGgoAAAANSUhEUgAAAAEAAAABCAQAAAC1HAwCAAAAC0lEQVR42mNkYAAAAAYAAjCB0C8AAAAASUVORK5CYII=";
+
+async fn probe(
+    client: &reqwest::Client,
+    url: &str,
+    api_key: &str,
+    body: serde_json::Value,
+) -> Result<reqwest::Response, String> {
+    client
+        .post(url)
+        .header("Content-Type", "application/json")
+        .header("Authorization", format!("Bearer {}", api_key))
+        .json(&body)
+        .send()
+        .await
+        .map_err(|e| format!("Failed to send request: {}", e))
+}
+
+// 单项探测的结果。基础请求已成功，400/404/422 可以归因于探测的特性；
+// 限流、5xx 等可能只是暂时的，结果未知
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Probe {
+    Supported,
+    Unsupported,
+    Unknown,
+}
+
+impl Probe {
+    fn from_status(status: reqwest::StatusCode) -> Self {
+        if status.is_success() {
+            return Probe::Supported;
+        }
+        match status.as_u16() {
+            400 | 404 | 422 => Probe::Unsupported,
+            _ => Probe::Unknown,
+        }
+    }
+
+    fn is_definitive(self) -> bool {
+        self != Probe::Unknown
+    }
+}
+
+// 发送一个极小的请求，返回服务端是否接受
+async fn probe_accepted(
+    client: &reqwest::Client,
+    url: &str,
+    api_key: &str,
+    body: serde_json::Value,
+) -> Result<Probe, String> {
+    Ok(Probe::from_status(
+        probe(client, url, api_key, body).await?.status(),
+    ))
+}
+
+async fn probe_usage_in_stream(
+    client: &reqwest::Client,
+    url: &str,
+    api_key: &str,
+    mut body: serde_json::Value,
+) -> Result<Probe, String> {
+    body["stream"] = serde_json::json!(true);
+    body["stream_options"] = serde_json::json!({ "include_usage": true });
+
+    let response = client::stream_request(client.post(url))
+        .header("Content-Type", "application/json")
+        .header("Authorization", format!("Bearer {}", api_key))
+        .json(&body)
+        .send()
+        .await
+        .map_err(|e| format!("Failed to send request: {}", e))?;
+    if !response.status().is_success() {
+        return Ok(Probe::from_status(response.status()));
+    }
+    let bytes = response
+        .bytes()
+        .await
+        .map_err(|e| format!("Failed to read response: {}", e))?;
+
+    let mut parser = SseParser::default();
+    let mut events = parser.feed(&bytes);
+    events.extend(parser.finish());
+
+    let has_usage = events.iter().any(|event| {
+        serde_json::from_str::<serde_json::Value>(&event.data)
+            .map(|json| json["usage"].is_object())
+            .unwrap_or(false)
+    });
+    Ok(if has_usage {
+        Probe::Supported
+    } else {
+        Probe::Unsupported
+    })
+}
+
+#[tauri::command]
+pub async fn detect_capabilities(
+    base_url: String,
+    api_key: String,
+    model: String,
+    cache: tauri::State<'_, CapabilityCache>,
+    http: tauri::State<'_, HttpClient>,
+) -> Result<Capabilities, String> {
+    let cache_key = format!("{}|{}", base_url, model);
+    if let Some(capabilities) = cache.0.lock().unwrap().get(&cache_key) {
+        return Ok(capabilities.clone());
+    }
+
+    let url = format!("{}/chat/completions", base_url);
+    let client = http.client_for(&url)?;
+    let base_body = serde_json::json!({
+        "model": model,
+        "messages": [{ "role": "user", "content": "hi" }],
+        "max_tokens": 1,
+        "stream": false,
+    });
+
+    // 先确认最基础的请求可用，否则后续探测结果没有意义
+    let baseline = probe(&client, &url, &api_key, base_body.clone()).await?;
+    if !baseline.status().is_success() {
+        return Err(client::api_error(baseline).await);
+    }
+
+    let mut thinking_body = base_body.clone();
+    thinking_body["thinking"] = serde_json::json!({ "type": "enabled" });
+
+    let mut tools_body = base_body.clone();
+    tools_body["tools"] = serde_json::json!([{
+        "type": "function",
+        "function": {
+            "name": "noop",
+            "description": "Does nothing",
+            "parameters": { "type": "object", "properties": {} }
+        }
+    }]);
+
+    let mut vision_body = base_body.clone();
+    vision_body["messages"] = serde_json::json!([{
+        "role": "user",
+        "content": [
+            { "type": "text", "text": "hi" },
+            { "type": "image_url", "image_url": { "url": PROBE_IMAGE } }
+        ]
+    }]);
+
+    let (thinking, tools, usage, vision) = tokio::join!(
+        probe_accepted(&client, &url, &api_key, thinking_body),
+        probe_accepted(&client, &url, &api_key, tools_body),
+        probe_usage_in_stream(&client, &url, &api_key, base_body),
+        probe_accepted(&client, &url, &api_key, vision_body),
+    );
+
+    let probes = [thinking?, tools?, usage?, vision?];
+    let capabilities = Capabilities {
+        supports_thinking: probes[0] == Probe::Supported,
+        supports_tools: probes[1] == Probe::Supported,
+        supports_usage_in_stream: probes[2] == Probe::Supported,
+        supports_vision: probes[3] == Probe::Supported,
+    };
+
+    if probes.iter().all(|probe| probe.is_definitive()) {
+        cache
+            .0
+            .lock()
+            .unwrap()
+            .insert(cache_key, capabilities.clone());
+    }
+    Ok(capabilities)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use reqwest::StatusCode;
+
+    #[test]
+    fn rejections_are_definitive() {
+        for status in [
+            StatusCode::BAD_REQUEST,
+            StatusCode::NOT_FOUND,
+            StatusCode::UNPROCESSABLE_ENTITY,
+        ] {
+            assert_eq!(Probe::from_status(status), Probe::Unsupported);
+        }
+        assert_eq!(Probe::from_status(StatusCode::OK), Probe::Supported);
+    }
+
+    #[test]
+    fn transient_failures_are_unknown() {
+        for status in [
+            StatusCode::TOO_MANY_REQUESTS,
+            StatusCode::INTERNAL_SERVER_ERROR,
+            StatusCode::BAD_GATEWAY,
+            StatusCode::SERVICE_UNAVAILABLE,
+            StatusCode::GATEWAY_TIMEOUT,
+        ] {
+            let probe = Probe::from_status(status);
+            assert_eq!(probe, Probe::Unknown);
+            assert!(!probe.is_definitive());
+        }
+    }
+}
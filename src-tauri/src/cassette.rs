@@ -0,0 +1,200 @@
+use std::collections::{BTreeMap, HashMap, VecDeque};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+use futures_util::stream::{self, StreamExt};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use tauri::{AppHandle, Manager};
+
+use crate::messages::canonical_json;
+
+// 一次完整的请求 / 响应。body 为解压后的完整响应体，流式请求即原始 SSE 文本
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Interaction {
+    pub request_hash: String,
+    pub url: String,
+    pub request_body: serde_json::Value,
+    pub status: u16,
+    pub headers: BTreeMap<String, String>,
+    pub body: String,
+}
+
+#[derive(Default)]
+enum Mode {
+    #[default]
+    Off,
+    Record(PathBuf),
+    // 同一请求录制了多次时按顺序回放，最后一条之后一直返回最后一条
+    Replay(HashMap<String, VecDeque<Interaction>>),
+}
+
+// 录制 / 回放模式（VCR 风格）：录制时把聊天请求及其响应追加到 cassette 文件，
+// 回放时按请求哈希从文件中取出响应，不访问网络
+#[derive(Default)]
+pub struct CassetteState(Mutex<Mode>);
+
+// 哈希只包含地址和请求体，不含 API key 等请求头
+fn request_hash(url: &str, request_body: &serde_json::Value) -> String {
+    let digest = Sha256::digest(format!("{}\n{}", url, canonical_json(request_body)).as_bytes());
+    digest.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+fn load_cassette(path: &Path) -> Result<Vec<Interaction>, String> {
+    if !path.exists() {
+        return Ok(Vec::new());
+    }
+    let text = fs::read_to_string(path).map_err(|e| format!("Failed to read cassette: {}", e))?;
+    serde_json::from_str(&text).map_err(|e| format!("Failed to parse cassette: {}", e))
+}
+
+fn append(path: &Path, interaction: Interaction) -> Result<(), String> {
+    let mut interactions = load_cassette(path)?;
+    interactions.push(interaction);
+    let text = serde_json::to_string_pretty(&interactions)
+        .map_err(|e| format!("Failed to serialize cassette: {}", e))?;
+    let tmp = path.with_extension("json.tmp");
+    fs::write(&tmp, text).map_err(|e| format!("Failed to write cassette: {}", e))?;
+    fs::rename(&tmp, path).map_err(|e| format!("Failed to write cassette: {}", e))
+}
+
+fn to_response(interaction: &Interaction) -> Result<reqwest::Response, String> {
+    let mut builder = http::Response::builder().status(interaction.status);
+    for (name, value) in &interaction.headers {
+        builder = builder.header(name, value);
+    }
+    builder
+        .body(interaction.body.clone())
+        .map(reqwest::Response::from)
+        .map_err(|e| format!("Failed to build recorded response: {}", e))
+}
+
+// 录制时边转发边收集响应体，读完后写入 cassette；中途取消的流不会被录制
+fn record(
+    path: PathBuf,
+    mut interaction: Interaction,
+    response: reqwest::Response,
+) -> Result<reqwest::Response, String> {
+    let mut builder = http::Response::builder().status(response.status());
+    for (name, value) in response.headers() {
+        // 录制的是解压后的内容
+        if name == reqwest::header::CONTENT_ENCODING || name == reqwest::header::CONTENT_LENGTH {
+            continue;
+        }
+        if let Ok(text) = value.to_str() {
+            interaction
+                .headers
+                .insert(name.to_string(), text.to_string());
+        }
+        builder = builder.header(name, value);
+    }
+    interaction.status = response.status().as_u16();
+
+    let collected = Arc::new(Mutex::new(Vec::new()));
+    let sink = collected.clone();
+    let body = response
+        .bytes_stream()
+        .inspect(move |chunk| {
+            if let Ok(chunk) = chunk {
+                sink.lock().unwrap().extend_from_slice(chunk);
+            }
+        })
+        .chain(
+            stream::once(async move {
+                interaction.body = String::from_utf8_lossy(&collected.lock().unwrap()).into_owned();
+                if let Err(e) = append(&path, interaction) {
+                    log::warn!("{}", e);
+                }
+            })
+            .filter_map(|_| async { None }),
+        );
+    builder
+        .body(reqwest::Body::wrap_stream(body))
+        .map(reqwest::Response::from)
+        .map_err(|e| format!("Failed to build recorded response: {}", e))
+}
+
+// 聊天请求统一从这里发出：关闭时直接发送，录制时转发并记录，回放时不访问网络
+pub async fn send(
+    app_handle: &AppHandle,
+    url: &str,
+    request_body: &serde_json::Value,
+    request_builder: reqwest::RequestBuilder,
+) -> Result<reqwest::Response, String> {
+    let hash = request_hash(url, request_body);
+    let record_path = {
+        let state = app_handle.state::<CassetteState>();
+        let mut mode = state.0.lock().unwrap();
+        match &mut *mode {
+            Mode::Off => None,
+            Mode::Record(path) => Some(path.clone()),
+            Mode::Replay(interactions) => {
+                let queue = interactions
+                    .get_mut(&hash)
+                    .ok_or_else(|| format!("No recorded interaction for request {}", hash))?;
+                let interaction = if queue.len() > 1 {
+                    queue.pop_front()
+                } else {
+                    queue.front().cloned()
+                };
+                return interaction
+                    .as_ref()
+                    .ok_or_else(|| format!("No recorded interaction for request {}", hash))
+                    .and_then(to_response);
+            }
+        }
+    };
+
+    let response = request_builder
+        .send()
+        .await
+        .map_err(|e| format!("Failed to send request: {}", e))?;
+    let Some(path) = record_path else {
+        return Ok(response);
+    };
+    let interaction = Interaction {
+        request_hash: hash,
+        url: url.to_string(),
+        request_body: request_body.clone(),
+        status: 0,
+        headers: BTreeMap::new(),
+        body: String::new(),
+    };
+    record(path, interaction, response)
+}
+
+// 之后的聊天请求及响应追加录制到 path（JSON 数组）
+#[tauri::command]
+pub fn set_record_mode(path: String, app_handle: AppHandle) -> Result<(), String> {
+    let path = PathBuf::from(path);
+    // 提前检查文件格式，避免录制到一半才发现无法追加
+    load_cassette(&path)?;
+    *app_handle.state::<CassetteState>().0.lock().unwrap() = Mode::Record(path);
+    Ok(())
+}
+
+// 之后的聊天请求从 path 中按请求哈希取响应，返回录制的条目数
+#[tauri::command]
+pub fn set_replay_mode(path: String, app_handle: AppHandle) -> Result<usize, String> {
+    let path = PathBuf::from(path);
+    if !path.exists() {
+        return Err(format!("Cassette not found: {}", path.display()));
+    }
+    let recorded = load_cassette(&path)?;
+    let count = recorded.len();
+    let mut interactions: HashMap<String, VecDeque<Interaction>> = HashMap::new();
+    for interaction in recorded {
+        interactions
+            .entry(interaction.request_hash.clone())
+            .or_default()
+            .push_back(interaction);
+    }
+    *app_handle.state::<CassetteState>().0.lock().unwrap() = Mode::Replay(interactions);
+    Ok(count)
+}
+
+#[tauri::command]
+pub fn disable_cassette(app_handle: AppHandle) {
+    *app_handle.state::<CassetteState>().0.lock().unwrap() = Mode::Off;
+}
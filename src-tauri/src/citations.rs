@@ -0,0 +1,88 @@
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+// 搜索增强类服务返回的引用来源，统一成同一结构
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Citation {
+    pub url: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub title: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub snippet: Option<String>,
+}
+
+fn text(value: &Value, field: &str) -> Option<String> {
+    value[field]
+        .as_str()
+        .filter(|s| !s.is_empty())
+        .map(str::to_string)
+}
+
+// 纯 URL 字符串或带 url / title / snippet 的对象
+fn parse_entry(entry: &Value) -> Option<Citation> {
+    if let Some(url) = entry.as_str() {
+        return Some(Citation {
+            url: url.to_string(),
+            title: None,
+            snippet: None,
+        });
+    }
+    Some(Citation {
+        url: text(entry, "url")?,
+        title: text(entry, "title"),
+        snippet: text(entry, "snippet").or_else(|| text(entry, "content")),
+    })
+}
+
+fn push_unique(citations: &mut Vec<Citation>, citation: Citation) {
+    match citations.iter_mut().find(|c| c.url == citation.url) {
+        // 同一来源出现多次时补全缺失的标题和摘要
+        Some(existing) => {
+            if existing.title.is_none() {
+                existing.title = citation.title;
+            }
+            if existing.snippet.is_none() {
+                existing.snippet = citation.snippet;
+            }
+        }
+        None => citations.push(citation),
+    }
+}
+
+// 支持的形式：顶层 citations（Perplexity，字符串或对象数组）、顶层 search_results，
+// 以及 choices 中 message / delta 的 annotations（type 为 url_citation）
+pub fn extract(response: &Value) -> Option<Vec<Citation>> {
+    let mut citations = Vec::new();
+
+    for field in ["citations", "search_results"] {
+        if let Some(entries) = response[field].as_array() {
+            for citation in entries.iter().filter_map(parse_entry) {
+                push_unique(&mut citations, citation);
+            }
+        }
+    }
+
+    for choice in response["choices"].as_array().into_iter().flatten() {
+        let annotations = choice["message"]["annotations"]
+            .as_array()
+            .or_else(|| choice["delta"]["annotations"].as_array());
+        for annotation in annotations.into_iter().flatten() {
+            if annotation["type"] == "url_citation" {
+                if let Some(citation) = parse_entry(&annotation["url_citation"]) {
+                    push_unique(&mut citations, citation);
+                }
+            }
+        }
+    }
+
+    (!citations.is_empty()).then_some(citations)
+}
+
+// 流式时各分片的引用合并到一起，有新增或补全时返回 true
+pub fn merge(into: &mut Vec<Citation>, found: Vec<Citation>) -> bool {
+    let before = into.clone();
+    for citation in found {
+        push_unique(into, citation);
+    }
+    *into != before
+}
@@ -0,0 +1,468 @@
+use std::io::Write;
+use std::sync::{Arc, RwLock};
+
+use futures_util::stream::StreamExt;
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ClientConfig {
+    // 非流式响应启用 gzip / brotli / deflate 压缩协商
+    pub compression: bool,
+}
+
+impl Default for ClientConfig {
+    fn default() -> Self {
+        ClientConfig { compression: true }
+    }
+}
+
+// 允许 / 禁止访问的主机，支持 "*.example.com" 通配子域名；允许列表为空表示不限制
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct HostPolicy {
+    pub allowed: Vec<String>,
+    pub denied: Vec<String>,
+}
+
+fn host_matches(pattern: &str, host: &str) -> bool {
+    match pattern.strip_prefix("*.") {
+        Some(domain) => host == domain || host.ends_with(&format!(".{}", domain)),
+        None => host == pattern,
+    }
+}
+
+impl HostPolicy {
+    // 禁止列表优先于允许列表
+    pub fn check(&self, url: &str) -> Result<(), String> {
+        let parsed = reqwest::Url::parse(url).map_err(|e| format!("Invalid URL {}: {}", url, e))?;
+        let host = parsed
+            .host_str()
+            .ok_or_else(|| format!("URL has no host: {}", url))?
+            .to_lowercase();
+
+        if self.denied.iter().any(|p| host_matches(p, &host)) {
+            return Err(format!("Host {} is blocked by the host policy", host));
+        }
+        if !self.allowed.is_empty() && !self.allowed.iter().any(|p| host_matches(p, &host)) {
+            return Err(format!("Host {} is not in the allowed hosts list", host));
+        }
+        Ok(())
+    }
+}
+
+fn normalize_hosts(hosts: Vec<String>) -> Vec<String> {
+    hosts
+        .into_iter()
+        .map(|h| h.trim().to_lowercase())
+        .filter(|h| !h.is_empty())
+        .collect()
+}
+
+// 全局共享的 reqwest 客户端，复用连接池；配置变更时整体重建
+pub struct HttpClient {
+    inner: RwLock<(ClientConfig, reqwest::Client)>,
+    policy: Arc<RwLock<HostPolicy>>,
+}
+
+impl HttpClient {
+    pub fn new(config: ClientConfig) -> Result<Self, String> {
+        let policy = Arc::new(RwLock::new(HostPolicy::default()));
+        let client = build_client(&config, &policy)?;
+        Ok(HttpClient {
+            inner: RwLock::new((config, client)),
+            policy,
+        })
+    }
+
+    // 所有出站请求都通过这里获取客户端，先按主机策略检查目标地址。
+    // reqwest::Client 内部是 Arc，clone 开销很小
+    pub fn client_for(&self, url: &str) -> Result<reqwest::Client, String> {
+        self.policy.read().unwrap().check(url)?;
+        Ok(self.inner.read().unwrap().1.clone())
+    }
+
+    // 配置和主机策略相同、但连接池独立的新客户端，用于测量建立连接的耗时
+    pub fn isolated_client_for(&self, url: &str) -> Result<reqwest::Client, String> {
+        self.policy.read().unwrap().check(url)?;
+        let config = self.config();
+        build_client(&config, &self.policy)
+    }
+
+    pub fn config(&self) -> ClientConfig {
+        self.inner.read().unwrap().0.clone()
+    }
+
+    pub fn reconfigure(&self, config: ClientConfig) -> Result<(), String> {
+        let client = build_client(&config, &self.policy)?;
+        *self.inner.write().unwrap() = (config, client);
+        Ok(())
+    }
+}
+
+fn build_client(
+    config: &ClientConfig,
+    policy: &Arc<RwLock<HostPolicy>>,
+) -> Result<reqwest::Client, String> {
+    // 重定向的目标同样要经过主机策略检查，避免借跳转绕过限制
+    let policy = Arc::clone(policy);
+    let redirect = reqwest::redirect::Policy::custom(move |attempt| {
+        if let Err(e) = policy.read().unwrap().check(attempt.url().as_str()) {
+            attempt.error(e)
+        } else if attempt.previous().len() >= 10 {
+            attempt.stop()
+        } else {
+            attempt.follow()
+        }
+    });
+
+    reqwest::Client::builder()
+        .redirect(redirect)
+        .gzip(config.compression)
+        .brotli(config.compression)
+        .deflate(config.compression)
+        .build()
+        .map_err(|e| format!("Failed to build HTTP client: {}", e))
+}
+
+// 流式请求显式要求不压缩：不少网关对压缩的 SSE 会攒满缓冲区才下发，破坏实时性。
+// 即使服务端仍返回压缩流，客户端开启压缩时也会透明解压，关闭时由 stream_body 解压 gzip。
+pub fn stream_request(builder: reqwest::RequestBuilder) -> reqwest::RequestBuilder {
+    builder.header("Accept-Encoding", "identity")
+}
+
+// 流式响应体。关闭压缩协商时 reqwest 不会解压，部分代理却仍返回 gzip 压缩的 SSE，
+// 此时按块增量解压；其余情况原样返回
+pub fn stream_body(
+    response: reqwest::Response,
+) -> impl futures_util::Stream<Item = Result<Vec<u8>, String>> + Unpin {
+    let gzip = response
+        .headers()
+        .get(reqwest::header::CONTENT_ENCODING)
+        .and_then(|v| v.to_str().ok())
+        .is_some_and(|encoding| {
+            encoding.eq_ignore_ascii_case("gzip") || encoding.eq_ignore_ascii_case("x-gzip")
+        });
+    let mut decoder = gzip.then(|| flate2::write::GzDecoder::new(Vec::new()));
+    response.bytes_stream().map(move |chunk| {
+        let chunk = chunk.map_err(|e| format!("Failed to read chunk: {}", e))?;
+        let Some(decoder) = &mut decoder else {
+            return Ok(chunk.to_vec());
+        };
+        decoder
+            .write_all(&chunk)
+            .and_then(|_| decoder.flush())
+            .map_err(|e| format!("Failed to decompress chunk: {}", e))?;
+        Ok(std::mem::take(decoder.get_mut()))
+    })
+}
+
+// 各服务放请求 id 的响应头（OpenAI 与多数兼容服务、Anthropic、Azure、Google、Bedrock）
+const REQUEST_ID_HEADERS: &[&str] = &[
+    "x-request-id",
+    "request-id",
+    "x-ms-request-id",
+    "apim-request-id",
+    "x-goog-request-id",
+    "x-amzn-requestid",
+];
+
+// 服务端为每个请求分配的 id，向服务商反馈问题时需要提供
+pub fn request_id(headers: &reqwest::header::HeaderMap) -> Option<String> {
+    REQUEST_ID_HEADERS.iter().find_map(|name| {
+        headers
+            .get(*name)
+            .and_then(|v| v.to_str().ok())
+            .filter(|v| !v.is_empty())
+            .map(str::to_string)
+    })
+}
+
+// 部分服务（如 Anthropic 的错误响应、通义千问）把请求 id 放在响应体里
+pub fn request_id_from_body(body: &serde_json::Value) -> Option<String> {
+    [
+        &body["request_id"],
+        &body["requestId"],
+        &body["error"]["request_id"],
+    ]
+    .into_iter()
+    .find_map(|v| v.as_str().filter(|v| !v.is_empty()).map(str::to_string))
+}
+
+pub fn api_error_message(request_id: Option<&str>, error_text: &str) -> String {
+    match request_id {
+        Some(id) => format!("API Error (request id: {}): {}", id, error_text),
+        None => format!("API Error: {}", error_text),
+    }
+}
+
+// 读取错误响应，错误信息中带上请求 id
+pub async fn api_error(response: reqwest::Response) -> String {
+    let header_id = request_id(response.headers());
+    let error_text = response
+        .text()
+        .await
+        .unwrap_or_else(|_| "Unknown error".to_string());
+    let request_id = header_id.or_else(|| {
+        serde_json::from_str(&error_text)
+            .ok()
+            .and_then(|body| request_id_from_body(&body))
+    });
+    api_error_message(request_id.as_deref(), &error_text)
+}
+
+// 不提供该接口的服务通常返回 404 / 405，给出明确的不支持提示
+pub async fn check_supported(
+    response: reqwest::Response,
+    base_url: &str,
+    feature: &str,
+) -> Result<reqwest::Response, String> {
+    let status = response.status();
+    if status == reqwest::StatusCode::NOT_FOUND || status == reqwest::StatusCode::METHOD_NOT_ALLOWED
+    {
+        return Err(format!(
+            "{} is not supported by this provider ({})",
+            feature, base_url
+        ));
+    }
+    if !status.is_success() {
+        return Err(api_error(response).await);
+    }
+    Ok(response)
+}
+
+// 分块读取响应体，超过上限立即中止，而不是先整体读入内存
+pub async fn read_body(
+    response: reqwest::Response,
+    max_bytes: Option<usize>,
+) -> Result<Vec<u8>, String> {
+    read_body_with_progress(response, max_bytes, |_| {}).await
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ResponseProgress {
+    pub received_bytes: usize,
+    // 来自 Content-Length；压缩传输或分块编码时未知
+    pub total_bytes: Option<u64>,
+    pub percent: Option<f64>,
+}
+
+// 两次进度回调之间的最短间隔
+const PROGRESS_INTERVAL: std::time::Duration = std::time::Duration::from_millis(100);
+
+// 同 read_body，读取过程中按时间间隔回报进度，读完时一定回报一次
+pub async fn read_body_with_progress(
+    response: reqwest::Response,
+    max_bytes: Option<usize>,
+    mut on_progress: impl FnMut(ResponseProgress),
+) -> Result<Vec<u8>, String> {
+    let exceeded = |limit| format!("Response exceeded the maximum size of {} bytes", limit);
+    let total_bytes = response.content_length();
+    if let (Some(limit), Some(len)) = (max_bytes, total_bytes) {
+        if len as usize > limit {
+            return Err(exceeded(limit));
+        }
+    }
+
+    let progress = |received_bytes: usize| ResponseProgress {
+        received_bytes,
+        total_bytes,
+        percent: total_bytes
+            .filter(|&total| total > 0)
+            .map(|total| (received_bytes as f64 / total as f64 * 100.0).min(100.0)),
+    };
+    let mut body = Vec::with_capacity(total_bytes.unwrap_or(0).min(1 << 24) as usize);
+    let mut last_report = std::time::Instant::now();
+    let mut stream = response.bytes_stream();
+    while let Some(chunk) = stream.next().await {
+        let chunk = chunk.map_err(|e| format!("Failed to read response: {}", e))?;
+        if let Some(limit) = max_bytes.filter(|&l| body.len() + chunk.len() > l) {
+            return Err(exceeded(limit));
+        }
+        body.extend_from_slice(&chunk);
+        if last_report.elapsed() >= PROGRESS_INTERVAL {
+            last_report = std::time::Instant::now();
+            on_progress(progress(body.len()));
+        }
+    }
+    on_progress(progress(body.len()));
+    Ok(body)
+}
+
+// 预热耗时（毫秒）。reqwest 不提供分阶段计时，这里是整个 HEAD 请求的往返时间，
+// 包含 DNS、TCP 连接、TLS 握手和服务端响应，不是单独的握手耗时
+#[derive(Debug, Clone, Serialize)]
+pub struct WarmupTiming {
+    pub round_trip_ms: u64,
+}
+
+// 提前与服务端建立连接（含 TLS 握手）放入连接池，首条消息即可复用。
+// 只关心连接本身，任何 HTTP 状态码都算成功
+#[tauri::command]
+pub async fn warmup(
+    base_url: String,
+    client: tauri::State<'_, HttpClient>,
+) -> Result<WarmupTiming, String> {
+    let http = client.client_for(&base_url)?;
+    let started = std::time::Instant::now();
+    http.head(&base_url)
+        .timeout(std::time::Duration::from_secs(10))
+        .send()
+        .await
+        .map_err(|e| format!("Failed to warm up connection: {}", e))?;
+    Ok(WarmupTiming {
+        round_trip_ms: started.elapsed().as_millis() as u64,
+    })
+}
+
+#[tauri::command]
+pub fn configure_client(
+    config: ClientConfig,
+    client: tauri::State<'_, HttpClient>,
+) -> Result<(), String> {
+    client.reconfigure(config)
+}
+
+#[tauri::command]
+pub fn get_client_config(client: tauri::State<'_, HttpClient>) -> ClientConfig {
+    client.config()
+}
+
+#[tauri::command]
+pub fn set_allowed_hosts(hosts: Vec<String>, client: tauri::State<'_, HttpClient>) {
+    client.policy.write().unwrap().allowed = normalize_hosts(hosts);
+}
+
+#[tauri::command]
+pub fn set_denied_hosts(hosts: Vec<String>, client: tauri::State<'_, HttpClient>) {
+    client.policy.write().unwrap().denied = normalize_hosts(hosts);
+}
+
+#[tauri::command]
+pub fn get_host_policy(client: tauri::State<'_, HttpClient>) -> HostPolicy {
+    client.policy.read().unwrap().clone()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // 不带 Content-Length、分块到达的响应
+    fn chunked_response(chunks: Vec<&'static [u8]>) -> reqwest::Response {
+        let body = futures_util::stream::iter(
+            chunks
+                .into_iter()
+                .map(|chunk| Ok::<_, std::io::Error>(chunk.to_vec())),
+        );
+        http::Response::builder()
+            .body(reqwest::Body::wrap_stream(body))
+            .map(reqwest::Response::from)
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn read_body_aborts_when_stream_exceeds_limit() {
+        let response = chunked_response(vec![b"0123456789", b"0123456789", b"0123456789"]);
+        let error = read_body(response, Some(25)).await.unwrap_err();
+        assert_eq!(error, "Response exceeded the maximum size of 25 bytes");
+    }
+
+    #[tokio::test]
+    async fn read_body_rejects_declared_length_over_limit() {
+        let response = http::Response::builder()
+            .header("Content-Length", "100")
+            .body(vec![b'x'; 100])
+            .map(reqwest::Response::from)
+            .unwrap();
+        assert!(read_body(response, Some(50)).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn read_body_within_limit_returns_everything() {
+        let response = chunked_response(vec![b"hello ", b"world"]);
+        assert_eq!(
+            read_body(response, Some(11)).await.unwrap(),
+            b"hello world".to_vec()
+        );
+    }
+
+    #[tokio::test]
+    async fn missing_endpoint_is_reported_as_unsupported() {
+        for status in [404, 405] {
+            let response = http::Response::builder()
+                .status(status)
+                .body("")
+                .map(reqwest::Response::from)
+                .unwrap();
+            let error = check_supported(response, "https://api.example.com/v1", "Moderation")
+                .await
+                .unwrap_err();
+            assert_eq!(
+                error,
+                "Moderation is not supported by this provider (https://api.example.com/v1)"
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn isolated_client_applies_redirect_policy() {
+        // 只会回复一次重定向的本地服务
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            use tokio::io::{AsyncReadExt, AsyncWriteExt};
+            let (mut socket, _) = listener.accept().await.unwrap();
+            let mut request = [0; 1024];
+            let _ = socket.read(&mut request).await;
+            let response = "HTTP/1.1 302 Found\r\nLocation: http://blocked.example.com/\r\nContent-Length: 0\r\n\r\n";
+            socket.write_all(response.as_bytes()).await.unwrap();
+        });
+
+        let http = HttpClient::new(ClientConfig::default()).unwrap();
+        http.policy.write().unwrap().denied = vec!["*.example.com".to_string()];
+        let url = format!("http://{}/", addr);
+        let client = http.isolated_client_for(&url).unwrap();
+        let error = client.get(&url).send().await.unwrap_err();
+        assert!(error.is_redirect());
+        assert!(http
+            .isolated_client_for("https://api.example.com/v1")
+            .is_err());
+    }
+
+    #[tokio::test]
+    async fn stream_body_decompresses_gzip_chunks() {
+        use flate2::write::GzEncoder;
+        use std::io::Write;
+
+        let sse = "data: {\"a\":1}\n\ndata: [DONE]\n\n".repeat(20);
+        let mut encoder = GzEncoder::new(Vec::new(), flate2::Compression::default());
+        encoder.write_all(sse.as_bytes()).unwrap();
+        let compressed = encoder.finish().unwrap();
+        // 压缩数据被拆成任意大小的块到达
+        let chunks: Vec<Result<Vec<u8>, std::io::Error>> =
+            compressed.chunks(7).map(|c| Ok(c.to_vec())).collect();
+        let response = http::Response::builder()
+            .header("Content-Encoding", "gzip")
+            .body(reqwest::Body::wrap_stream(futures_util::stream::iter(
+                chunks,
+            )))
+            .map(reqwest::Response::from)
+            .unwrap();
+
+        let mut decoded = Vec::new();
+        let mut body = stream_body(response);
+        while let Some(chunk) = body.next().await {
+            decoded.extend(chunk.unwrap());
+        }
+        assert_eq!(String::from_utf8(decoded).unwrap(), sse);
+    }
+
+    #[tokio::test]
+    async fn stream_body_passes_through_uncompressed() {
+        let mut body = stream_body(chunked_response(vec![b"data: a\n", b"\n"]));
+        let mut received = Vec::new();
+        while let Some(chunk) = body.next().await {
+            received.extend(chunk.unwrap());
+        }
+        assert_eq!(received, b"data: a\n\n");
+    }
+}
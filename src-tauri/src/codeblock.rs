@@ -0,0 +1,214 @@
+use serde::Serialize;
+
+// 信息串（语言标记所在行）的长度上限，超出的行不视为围栏，避免长行占用内存
+const MAX_FENCE_LINE: usize = 256;
+
+// 通过 code-block-start / code-block-end 事件发送，index 为本次回复中代码块的序号
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct CodeBlockEvent {
+    pub index: usize,
+    pub language: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum CodeFence {
+    Start(CodeBlockEvent),
+    End(CodeBlockEvent),
+}
+
+struct OpenFence {
+    marker: char,
+    len: usize,
+    event: CodeBlockEvent,
+}
+
+// 跨增量识别 ``` / ~~~ 围栏代码块。只缓存当前行中可能是围栏的前缀，
+// 整行结束后才判断，因此围栏被拆到多个分片中也能识别
+pub struct CodeFenceScanner {
+    line: String,
+    candidate: bool,
+    open: Option<OpenFence>,
+    count: usize,
+}
+
+impl Default for CodeFenceScanner {
+    fn default() -> Self {
+        CodeFenceScanner {
+            line: String::new(),
+            // 回复的开头也是行首
+            candidate: true,
+            open: None,
+            count: 0,
+        }
+    }
+}
+
+// 返回 (缩进后的围栏字符, 围栏长度, 信息串)
+fn parse_fence(line: &str) -> Option<(char, usize, &str)> {
+    let rest = line.trim_start_matches(' ');
+    if line.len() - rest.len() > 3 {
+        return None;
+    }
+    let marker = rest.chars().next().filter(|c| *c == '`' || *c == '~')?;
+    let len = rest.chars().take_while(|c| *c == marker).count();
+    if len < 3 {
+        return None;
+    }
+    Some((marker, len, rest[len..].trim()))
+}
+
+// 行的前缀是否仍可能构成围栏
+fn could_be_fence(line: &str) -> bool {
+    if line.len() > MAX_FENCE_LINE {
+        return false;
+    }
+    let rest = line.trim_start_matches(' ');
+    if line.len() - rest.len() > 3 {
+        return false;
+    }
+    let Some(marker) = rest.chars().next() else {
+        return true;
+    };
+    if marker != '`' && marker != '~' {
+        return false;
+    }
+    let len = rest.chars().take_while(|c| *c == marker).count();
+    len == rest.len() || len >= 3
+}
+
+impl CodeFenceScanner {
+    pub fn feed(&mut self, text: &str) -> Vec<CodeFence> {
+        let mut events = Vec::new();
+        for c in text.chars() {
+            if c == '\n' {
+                if self.candidate {
+                    events.extend(self.end_line());
+                }
+                self.line.clear();
+                self.candidate = true;
+            } else if self.candidate {
+                self.line.push(c);
+                if !could_be_fence(&self.line) {
+                    self.candidate = false;
+                    self.line.clear();
+                }
+            }
+        }
+        events
+    }
+
+    // 回复结束：处理没有换行的最后一行，未闭合的代码块也发送结束事件
+    pub fn finish(&mut self) -> Vec<CodeFence> {
+        let mut events = Vec::new();
+        if self.candidate {
+            events.extend(self.end_line());
+        }
+        self.line.clear();
+        if let Some(open) = self.open.take() {
+            events.push(CodeFence::End(open.event));
+        }
+        events
+    }
+
+    fn end_line(&mut self) -> Option<CodeFence> {
+        let (marker, len, info) = parse_fence(&self.line)?;
+        match &self.open {
+            Some(open) => {
+                // 闭合围栏：同种字符、长度不短于开头、没有信息串
+                if marker == open.marker && len >= open.len && info.is_empty() {
+                    return self.open.take().map(|open| CodeFence::End(open.event));
+                }
+                None
+            }
+            None => {
+                // 反引号围栏的信息串中不能再出现反引号（那是行内代码）
+                if marker == '`' && info.contains('`') {
+                    return None;
+                }
+                let event = CodeBlockEvent {
+                    index: self.count,
+                    language: info
+                        .split_whitespace()
+                        .next()
+                        .map(|word| word.to_lowercase()),
+                };
+                self.count += 1;
+                self.open = Some(OpenFence {
+                    marker,
+                    len,
+                    event: event.clone(),
+                });
+                Some(CodeFence::Start(event))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn scan(chunks: &[&str]) -> Vec<CodeFence> {
+        let mut scanner = CodeFenceScanner::default();
+        let mut events: Vec<CodeFence> = chunks.iter().flat_map(|c| scanner.feed(c)).collect();
+        events.extend(scanner.finish());
+        events
+    }
+
+    fn start(index: usize, language: Option<&str>) -> CodeFence {
+        CodeFence::Start(CodeBlockEvent {
+            index,
+            language: language.map(str::to_string),
+        })
+    }
+
+    fn end(index: usize, language: Option<&str>) -> CodeFence {
+        CodeFence::End(CodeBlockEvent {
+            index,
+            language: language.map(str::to_string),
+        })
+    }
+
+    #[test]
+    fn fence_split_across_chunks() {
+        let events = scan(&["Here:\n`", "``Ru", "st\nfn main() {}\n``", "`\nDone"]);
+        assert_eq!(events, [start(0, Some("rust")), end(0, Some("rust"))]);
+    }
+
+    #[test]
+    fn every_split_point_gives_same_events() {
+        let text = "a\n```py\nprint(1)\n```\n~~~\n```\nnot a close\n~~~\n";
+        let expected = [
+            start(0, Some("py")),
+            end(0, Some("py")),
+            start(1, None),
+            end(1, None),
+        ];
+        assert_eq!(scan(&[text]), expected);
+        for i in 1..text.len() {
+            let (a, b) = text.split_at(i);
+            assert_eq!(scan(&[a, b]), expected, "split at {}", i);
+        }
+    }
+
+    #[test]
+    fn closing_fence_must_match_marker_and_length() {
+        let events = scan(&["````md\n```\nstill inside\n````\n"]);
+        assert_eq!(events, [start(0, Some("md")), end(0, Some("md"))]);
+    }
+
+    #[test]
+    fn unclosed_block_ends_on_finish() {
+        assert_eq!(
+            scan(&["```js\nconsole.log(1)"]),
+            [start(0, Some("js")), end(0, Some("js"))]
+        );
+    }
+
+    #[test]
+    fn ignores_inline_code_and_indented_fences() {
+        assert!(scan(&["use ```inline``` here\n"]).is_empty());
+        assert!(scan(&["```a`b\n"]).is_empty());
+        assert!(scan(&["    ```\n    code\n    ```\n"]).is_empty());
+    }
+}
@@ -0,0 +1,158 @@
+use serde::Serialize;
+use tauri::{AppHandle, Manager};
+
+use crate::client::HttpClient;
+use crate::messages::estimate_tokens;
+use crate::storage;
+use crate::{build_request_body, message_text, send_chat_request, ChatOptions, Message};
+
+// 摘要消息的固定前缀，再次压缩时据此识别并把旧摘要一并纳入
+const SUMMARY_PREFIX: &str = "Summary of the earlier conversation:\n";
+
+const SUMMARIZE_PROMPT: &str = "Summarize the following conversation so it can replace the original messages as context for continuing the chat. Keep facts, decisions, names, numbers, code identifiers and open questions. Be concise and write in the language of the conversation. Output only the summary.";
+
+#[derive(Debug, Clone, Serialize)]
+pub struct CompressionResult {
+    // 被摘要替换掉的消息数
+    pub compressed_messages: usize,
+    pub tokens_before: usize,
+    pub tokens_after: usize,
+    pub tokens_saved: usize,
+    // 压缩前的备份文件，未发生压缩时为 None
+    pub backup_path: Option<String>,
+}
+
+fn is_summary(message: &Message) -> bool {
+    message.role == "system" && message_text(&message.content).starts_with(SUMMARY_PREFIX)
+}
+
+// 返回 (开头需保留的 system 消息数, 最近 keep_recent 轮开始的位置)
+// 一轮从一条 user 消息开始，包含其后的助手回复和工具结果
+fn split_points(messages: &[Message], keep_recent: usize) -> (usize, usize) {
+    let leading = messages
+        .iter()
+        .take_while(|m| m.role == "system" && !is_summary(m))
+        .count();
+    let user_starts: Vec<usize> = messages
+        .iter()
+        .enumerate()
+        .skip(leading)
+        .filter(|(_, m)| m.role == "user")
+        .map(|(i, _)| i)
+        .collect();
+    let recent_start = match keep_recent {
+        0 => messages.len(),
+        n if n >= user_starts.len() => leading,
+        n => user_starts[user_starts.len() - n],
+    };
+    (leading, recent_start.max(leading))
+}
+
+fn transcript(messages: &[Message]) -> String {
+    messages
+        .iter()
+        .map(|m| {
+            let text = message_text(&m.content);
+            match text.strip_prefix(SUMMARY_PREFIX) {
+                Some(summary) => format!("[earlier summary]: {}", summary),
+                None => format!("{}: {}", m.role, text),
+            }
+        })
+        .collect::<Vec<_>>()
+        .join("\n\n")
+}
+
+// 用同一个模型把一段对话压缩成摘要
+pub async fn summarize(
+    base_url: &mut String,
+    api_key: &str,
+    model: &mut String,
+    messages: &[Message],
+    app_handle: &AppHandle,
+) -> Result<String, String> {
+    let options = ChatOptions::resolve(None, base_url, model, app_handle)?;
+    let url = format!("{}/chat/completions", base_url);
+    let client = app_handle.state::<HttpClient>().client_for(&url)?;
+    let request = [
+        Message {
+            role: "system".to_string(),
+            content: serde_json::Value::String(SUMMARIZE_PROMPT.to_string()),
+            pinned: None,
+        },
+        Message {
+            role: "user".to_string(),
+            content: serde_json::Value::String(transcript(messages)),
+            pinned: None,
+        },
+    ];
+    let request_body = build_request_body(model, &request, false, false, &options)?;
+    let response =
+        send_chat_request(&client, &url, api_key, &request_body, &options, app_handle).await?;
+    let summary = response
+        .choices
+        .into_iter()
+        .next()
+        .map(|choice| choice.message.content.trim().to_string())
+        .unwrap_or_default();
+    if summary.is_empty() {
+        return Err("Model returned an empty summary".to_string());
+    }
+    Ok(summary)
+}
+
+// 把最近 keep_recent 轮之前的内容替换为一条摘要 system 消息；
+// 开头的 system 提示和置顶消息原样保留（置顶消息放在摘要之后）
+#[tauri::command]
+pub async fn auto_compress_conversation(
+    id: String,
+    keep_recent: usize,
+    mut base_url: String,
+    api_key: String,
+    mut model: String,
+    app_handle: AppHandle,
+) -> Result<CompressionResult, String> {
+    let mut conversation = storage::load(&app_handle, &id)?;
+    let tokens_before = estimate_tokens(&conversation.messages);
+    let (leading, recent_start) = split_points(&conversation.messages, keep_recent);
+
+    let (pinned, older): (Vec<Message>, Vec<Message>) = conversation.messages
+        [leading..recent_start]
+        .iter()
+        .cloned()
+        .partition(Message::is_pinned);
+    // 只有一条已有摘要时再压缩没有意义
+    if older.is_empty() || (older.len() == 1 && is_summary(&older[0])) {
+        return Ok(CompressionResult {
+            compressed_messages: 0,
+            tokens_before,
+            tokens_after: tokens_before,
+            tokens_saved: 0,
+            backup_path: None,
+        });
+    }
+
+    let summary = summarize(&mut base_url, &api_key, &mut model, &older, &app_handle).await?;
+    let backup_path = storage::backup(&app_handle, &conversation)?;
+
+    let compressed_messages = older.len();
+    let recent = conversation.messages.split_off(recent_start);
+    conversation.messages.truncate(leading);
+    conversation.messages.push(Message {
+        role: "system".to_string(),
+        content: serde_json::Value::String(format!("{}{}", SUMMARY_PREFIX, summary)),
+        pinned: None,
+    });
+    conversation.messages.extend(pinned);
+    conversation.messages.extend(recent);
+    conversation.updated_at = storage::now_millis();
+    storage::save(&app_handle, &conversation)?;
+
+    let tokens_after = estimate_tokens(&conversation.messages);
+    Ok(CompressionResult {
+        compressed_messages,
+        tokens_before,
+        tokens_after,
+        tokens_saved: tokens_before.saturating_sub(tokens_after),
+        backup_path: Some(backup_path.to_string_lossy().into_owned()),
+    })
+}
@@ -0,0 +1,131 @@
+use std::fs;
+use std::path::PathBuf;
+use std::sync::RwLock;
+
+use serde::{Deserialize, Serialize};
+use tauri::{AppHandle, Manager};
+
+use crate::provider::Provider;
+use crate::ChatOptions;
+
+// 由请求本身决定、不能在配置中设置默认值的字段
+const RESERVED_PARAMS: &[&str] = &["model", "messages", "stream", "stream_options"];
+
+// 应用配置目录下 config.toml（优先）或 config.json 中的默认值。
+// 优先级：请求参数 > 配置档（profile）> 配置文件
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct AppConfig {
+    pub base_url: Option<String>,
+    pub model: Option<String>,
+    pub provider: Option<Provider>,
+    // 附加到每个请求体的默认参数，如 temperature、max_tokens
+    pub params: serde_json::Map<String, serde_json::Value>,
+}
+
+// 启动时加载，reload_config 时替换
+#[derive(Default)]
+pub struct ConfigState(RwLock<AppConfig>);
+
+fn config_paths(app_handle: &AppHandle) -> Result<[PathBuf; 2], String> {
+    let dir = app_handle
+        .path()
+        .app_config_dir()
+        .map_err(|e| format!("Failed to resolve app config dir: {}", e))?;
+    Ok([dir.join("config.toml"), dir.join("config.json")])
+}
+
+fn validate(config: &AppConfig) -> Result<(), String> {
+    if let Some(base_url) = &config.base_url {
+        reqwest::Url::parse(base_url)
+            .map_err(|e| format!("Invalid base_url {:?} in config: {}", base_url, e))?;
+    }
+    if let Some(key) = RESERVED_PARAMS
+        .iter()
+        .find(|key| config.params.contains_key(**key))
+    {
+        return Err(format!("\"{}\" cannot be set in config params", key));
+    }
+    let ranges = [("temperature", 0.0, 2.0), ("top_p", 0.0, 1.0)];
+    for (key, min, max) in ranges {
+        if let Some(value) = config.params.get(key) {
+            match value.as_f64() {
+                Some(v) if (min..=max).contains(&v) => {}
+                _ => {
+                    return Err(format!(
+                        "\"{}\" in config params must be a number between {} and {}",
+                        key, min, max
+                    ))
+                }
+            }
+        }
+    }
+    if let Some(value) = config.params.get("max_tokens") {
+        if value.as_u64().is_none_or(|v| v == 0) {
+            return Err("\"max_tokens\" in config params must be a positive integer".to_string());
+        }
+    }
+    Ok(())
+}
+
+// 两个文件都不存在时使用空配置
+pub fn load(app_handle: &AppHandle) -> Result<AppConfig, String> {
+    let Some(path) = config_paths(app_handle)?.into_iter().find(|p| p.exists()) else {
+        return Ok(AppConfig::default());
+    };
+    let text = fs::read_to_string(&path)
+        .map_err(|e| format!("Failed to read config {}: {}", path.display(), e))?;
+    let config: AppConfig = if path.extension().is_some_and(|e| e == "toml") {
+        toml::from_str(&text).map_err(|e| format!("Failed to parse {}: {}", path.display(), e))?
+    } else {
+        serde_json::from_str(&text)
+            .map_err(|e| format!("Failed to parse {}: {}", path.display(), e))?
+    };
+    validate(&config).map_err(|e| format!("{}: {}", path.display(), e))?;
+    Ok(config)
+}
+
+// 未传入的 base_url / model / provider 用配置文件补全；默认参数只补充配置档里没有的字段
+pub fn apply(
+    app_handle: &AppHandle,
+    base_url: &mut String,
+    model: &mut String,
+    options: &mut ChatOptions,
+) {
+    let state = app_handle.state::<ConfigState>();
+    let config = state.0.read().unwrap();
+    if base_url.is_empty() {
+        if let Some(default) = &config.base_url {
+            *base_url = default.clone();
+        }
+    }
+    if model.is_empty() {
+        if let Some(default) = &config.model {
+            *model = default.clone();
+        }
+    }
+    if options.provider.is_none() {
+        options.provider = config.provider;
+    }
+    for (key, value) in &config.params {
+        if !options.profile_params.contains_key(key) {
+            options.profile_params.insert(key.clone(), value.clone());
+        }
+    }
+}
+
+// 启动时调用：配置有误只记录日志并使用空配置，不影响程序启动
+pub fn init(app_handle: &AppHandle) {
+    match load(app_handle) {
+        Ok(config) => *app_handle.state::<ConfigState>().0.write().unwrap() = config,
+        Err(e) => log::error!("{}", e),
+    }
+}
+
+// 修改配置文件后无需重启即可生效；文件有误时保留当前配置并返回错误
+#[tauri::command]
+pub fn reload_config(app_handle: AppHandle) -> Result<AppConfig, String> {
+    let config = load(&app_handle)?;
+    *app_handle.state::<ConfigState>().0.write().unwrap() = config.clone();
+    Ok(config)
+}
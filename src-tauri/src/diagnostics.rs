@@ -0,0 +1,488 @@
+use std::time::{Duration, Instant};
+
+use futures_util::stream::StreamExt;
+use serde::Serialize;
+
+use tauri::Manager;
+
+use crate::client::{self, HttpClient};
+use crate::sse::SseParser;
+use crate::{build_request_body, parse_chat_response, ChatOptions, Message, StreamChunk};
+
+// 探测时的上限，超过这个长度不再继续增长
+const MAX_PROBE_TOKENS: u32 = 2_097_152;
+// 二分到这个精度即停止
+const PROBE_PRECISION: u32 = 256;
+const MAX_PROBE_REQUESTS: u32 = 24;
+// 每次探测之间的间隔，避免触发限流
+const PROBE_DELAY: Duration = Duration::from_millis(500);
+const LATENCY_TIMEOUT: Duration = Duration::from_secs(30);
+
+enum ProbeOutcome {
+    Fits,
+    TooLong(Option<u32>),
+}
+
+// " hello" 在常见分词器中都是一个 token
+fn dummy_prompt(tokens: u32) -> String {
+    " hello".repeat(tokens as usize)
+}
+
+fn is_context_length_error(error_text: &str) -> bool {
+    let lower = error_text.to_lowercase();
+    [
+        "context length",
+        "context_length",
+        "maximum context",
+        "too long",
+        "too many tokens",
+    ]
+    .iter()
+    .any(|pattern| lower.contains(pattern))
+}
+
+// 从错误信息中提取上限，如 "maximum context length is 8192 tokens"
+fn parse_limit_from_error(error_text: &str) -> Option<u32> {
+    let lower = error_text.to_lowercase();
+    let start = lower
+        .find("maximum context length is")
+        .or_else(|| lower.find("context length of"))?;
+    lower[start..]
+        .split(|c: char| !c.is_ascii_digit())
+        .find(|part| !part.is_empty())
+        .and_then(|digits| digits.parse().ok())
+}
+
+async fn probe_prompt_length(
+    client: &reqwest::Client,
+    url: &str,
+    api_key: &str,
+    model: &str,
+    tokens: u32,
+) -> Result<ProbeOutcome, String> {
+    let request_body = serde_json::json!({
+        "model": model,
+        "messages": [{ "role": "user", "content": dummy_prompt(tokens) }],
+        "max_tokens": 1,
+        "stream": false,
+    });
+
+    let mut rate_limited = 0;
+    loop {
+        let response = client
+            .post(url)
+            .header("Content-Type", "application/json")
+            .header("Authorization", format!("Bearer {}", api_key))
+            .json(&request_body)
+            .send()
+            .await
+            .map_err(|e| format!("Failed to send request: {}", e))?;
+
+        let status = response.status();
+        if status.is_success() {
+            return Ok(ProbeOutcome::Fits);
+        }
+
+        // 被限流时等待后重试同一长度
+        if status == reqwest::StatusCode::TOO_MANY_REQUESTS && rate_limited < 3 {
+            rate_limited += 1;
+            let retry_after = response
+                .headers()
+                .get("retry-after")
+                .and_then(|v| v.to_str().ok())
+                .and_then(|v| v.parse::<u64>().ok())
+                .unwrap_or(5);
+            tokio::time::sleep(Duration::from_secs(retry_after)).await;
+            continue;
+        }
+
+        let request_id = client::request_id(response.headers());
+        let error_text = response
+            .text()
+            .await
+            .unwrap_or_else(|_| "Unknown error".to_string());
+        if status == reqwest::StatusCode::PAYLOAD_TOO_LARGE || is_context_length_error(&error_text)
+        {
+            return Ok(ProbeOutcome::TooLong(parse_limit_from_error(&error_text)));
+        }
+        return Err(client::api_error_message(
+            request_id.as_deref(),
+            &error_text,
+        ));
+    }
+}
+
+// 通过发送逐渐变长的请求探测模型实际的上下文窗口：先倍增找到上界，再二分
+#[tauri::command]
+pub async fn probe_context_window(
+    base_url: String,
+    api_key: String,
+    model: String,
+    http: tauri::State<'_, HttpClient>,
+) -> Result<u32, String> {
+    let url = format!("{}/chat/completions", base_url);
+    let client = http.client_for(&url)?;
+
+    let mut low = 0;
+    let mut high = None;
+    let mut tokens = 1024;
+    let mut requests = 0;
+
+    while high.is_none() && tokens <= MAX_PROBE_TOKENS {
+        requests += 1;
+        match probe_prompt_length(&client, &url, &api_key, &model, tokens).await? {
+            ProbeOutcome::Fits => low = tokens,
+            // 服务端直接告知了上限
+            ProbeOutcome::TooLong(Some(limit)) => return Ok(limit),
+            ProbeOutcome::TooLong(None) => high = Some(tokens),
+        }
+        tokens *= 2;
+        tokio::time::sleep(PROBE_DELAY).await;
+    }
+
+    let Some(mut high) = high else {
+        // 达到探测上限仍未报错
+        return Ok(low);
+    };
+
+    while high - low > PROBE_PRECISION && requests < MAX_PROBE_REQUESTS {
+        requests += 1;
+        let mid = low + (high - low) / 2;
+        match probe_prompt_length(&client, &url, &api_key, &model, mid).await? {
+            ProbeOutcome::Fits => low = mid,
+            ProbeOutcome::TooLong(Some(limit)) => return Ok(limit),
+            ProbeOutcome::TooLong(None) => high = mid,
+        }
+        tokio::time::sleep(PROBE_DELAY).await;
+    }
+
+    Ok(low)
+}
+
+// 各阶段耗时（毫秒）。reqwest 不提供分阶段计时，DNS 和 TCP 连接单独测量；
+// TLS 为新连接上首个请求与复用连接上第二个请求的耗时差再减去 DNS 和连接耗时，是估算值
+#[derive(Debug, Clone, Serialize)]
+pub struct LatencyBreakdown {
+    pub dns_ms: u64,
+    pub connect_ms: u64,
+    // http 地址没有 TLS
+    pub tls_ms: Option<u64>,
+    // 发出聊天请求到收到响应头
+    pub ttfb_ms: u64,
+    // 发出聊天请求到收到第一个内容增量；未生成任何内容时为 None
+    pub first_token_ms: Option<u64>,
+}
+
+fn elapsed_ms(started: Instant) -> u64 {
+    started.elapsed().as_millis() as u64
+}
+
+fn is_content_event(data: &str) -> bool {
+    let Ok(value) = serde_json::from_str::<serde_json::Value>(data) else {
+        return false;
+    };
+    let delta = &value["choices"][0]["delta"];
+    let non_empty = |v: &serde_json::Value| v.as_str().is_some_and(|s| !s.is_empty());
+    non_empty(&delta["content"])
+        || non_empty(&delta["reasoning_content"])
+        || value["type"] == "content_block_delta"
+}
+
+// 区分慢在网络还是慢在模型：依次测量 DNS、TCP、TLS、首字节和首个 token
+#[tauri::command]
+pub async fn latency_breakdown(
+    base_url: String,
+    api_key: String,
+    model: String,
+    http: tauri::State<'_, HttpClient>,
+) -> Result<LatencyBreakdown, String> {
+    let url = format!("{}/chat/completions", base_url);
+    // 共享客户端可能已有到该主机的连接，计时使用连接池独立的新客户端
+    let client = http.isolated_client_for(&url)?;
+    let parsed = reqwest::Url::parse(&base_url).map_err(|e| format!("Invalid base URL: {}", e))?;
+    let host = parsed
+        .host_str()
+        .ok_or_else(|| format!("Base URL has no host: {}", base_url))?
+        .to_string();
+    let port = parsed.port_or_known_default().unwrap_or(443);
+
+    let started = Instant::now();
+    let addrs: Vec<_> = tokio::net::lookup_host((host.as_str(), port))
+        .await
+        .map_err(|e| format!("Failed to resolve {}: {}", host, e))?
+        .collect();
+    let dns_ms = elapsed_ms(started);
+    let addr = addrs
+        .first()
+        .ok_or_else(|| format!("No addresses found for {}", host))?;
+
+    let started = Instant::now();
+    tokio::time::timeout(LATENCY_TIMEOUT, tokio::net::TcpStream::connect(addr))
+        .await
+        .map_err(|_| format!("Timed out connecting to {}", addr))?
+        .map_err(|e| format!("Failed to connect to {}: {}", addr, e))?;
+    let connect_ms = elapsed_ms(started);
+
+    let head = || async {
+        let started = Instant::now();
+        client
+            .head(&base_url)
+            .timeout(LATENCY_TIMEOUT)
+            .send()
+            .await
+            .map_err(|e| format!("Failed to reach {}: {}", base_url, e))?;
+        Ok::<_, String>(elapsed_ms(started))
+    };
+    let cold = head().await?;
+    let warm = head().await?;
+    let tls_ms =
+        (parsed.scheme() == "https").then(|| cold.saturating_sub(warm + dns_ms + connect_ms));
+
+    let request_body = serde_json::json!({
+        "model": model,
+        "messages": [{ "role": "user", "content": "Hi" }],
+        "stream": true,
+        "max_tokens": 16,
+    });
+    let started = Instant::now();
+    let response = client::stream_request(client.post(&url))
+        .header("Content-Type", "application/json")
+        .header("Authorization", format!("Bearer {}", api_key))
+        .timeout(LATENCY_TIMEOUT)
+        .json(&request_body)
+        .send()
+        .await
+        .map_err(|e| format!("Failed to send request: {}", e))?;
+    let ttfb_ms = elapsed_ms(started);
+    if !response.status().is_success() {
+        return Err(client::api_error(response).await);
+    }
+
+    let mut parser = SseParser::default();
+    let mut first_token_ms = None;
+    let mut stream = response.bytes_stream();
+    'read: while let Some(chunk) = stream.next().await {
+        let chunk = chunk.map_err(|e| format!("Failed to read chunk: {}", e))?;
+        for event in parser.feed(&chunk) {
+            if event.data == "[DONE]" {
+                break 'read;
+            }
+            if is_content_event(&event.data) {
+                first_token_ms = Some(elapsed_ms(started));
+                // 拿到首个 token 即可，丢弃连接不再继续生成
+                break 'read;
+            }
+        }
+    }
+
+    Ok(LatencyBreakdown {
+        dns_ms,
+        connect_ms,
+        tls_ms,
+        ttfb_ms,
+        first_token_ms,
+    })
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct StreamingTestResult {
+    // 内容增量分多次到达，确实是边生成边下发
+    pub streaming_supported: bool,
+    pub content_type: Option<String>,
+    // 携带内容增量的网络读取次数；网关缓冲整段回复时只有一次
+    pub content_chunks: usize,
+    pub content_events: usize,
+    pub first_token_ms: Option<u64>,
+    // 第一个与最后一个内容增量之间的时间跨度
+    pub spread_ms: u64,
+    pub total_ms: u64,
+}
+
+// 许多网关声称支持流式，实际却攒满整段回复才下发。发送一个小的流式请求，
+// 观察内容增量是否分多次、陆续到达；只解析不发送事件
+#[tauri::command]
+pub async fn test_streaming(
+    base_url: String,
+    api_key: String,
+    model: String,
+    http: tauri::State<'_, HttpClient>,
+) -> Result<StreamingTestResult, String> {
+    let url = format!("{}/chat/completions", base_url);
+    let client = http.client_for(&url)?;
+    let request_body = serde_json::json!({
+        "model": model,
+        "messages": [{ "role": "user", "content": "Count from 1 to 20, separated by spaces." }],
+        "stream": true,
+        "max_tokens": 64,
+    });
+
+    let started = Instant::now();
+    let response = client::stream_request(client.post(&url))
+        .header("Content-Type", "application/json")
+        .header("Authorization", format!("Bearer {}", api_key))
+        .json(&request_body)
+        .timeout(LATENCY_TIMEOUT)
+        .send()
+        .await
+        .map_err(|e| format!("Failed to send request: {}", e))?;
+    if !response.status().is_success() {
+        return Err(client::api_error(response).await);
+    }
+    let content_type = response
+        .headers()
+        .get(reqwest::header::CONTENT_TYPE)
+        .and_then(|v| v.to_str().ok())
+        .map(str::to_string);
+
+    let mut parser = SseParser::default();
+    let mut content_chunks = 0;
+    let mut content_events = 0;
+    let mut first_token: Option<Instant> = None;
+    let mut last_token = None;
+    let mut stream = response.bytes_stream();
+    'read: while let Some(chunk) = stream.next().await {
+        let chunk = chunk.map_err(|e| format!("Failed to read chunk: {}", e))?;
+        let mut has_content = false;
+        for event in parser.feed(&chunk) {
+            if event.data == "[DONE]" {
+                break 'read;
+            }
+            if is_content_event(&event.data) {
+                has_content = true;
+                content_events += 1;
+            }
+        }
+        if has_content {
+            content_chunks += 1;
+            let now = Instant::now();
+            first_token.get_or_insert(now);
+            last_token = Some(now);
+        }
+    }
+
+    let is_sse = content_type
+        .as_deref()
+        .is_some_and(|ct| ct.contains("text/event-stream"));
+    Ok(StreamingTestResult {
+        streaming_supported: is_sse && content_chunks > 1,
+        content_type,
+        content_chunks,
+        content_events,
+        first_token_ms: first_token.map(|t| t.duration_since(started).as_millis() as u64),
+        spread_ms: match (first_token, last_token) {
+            (Some(first), Some(last)) => last.duration_since(first).as_millis() as u64,
+            _ => 0,
+        },
+        total_ms: elapsed_ms(started),
+    })
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ModeTiming {
+    pub total_ms: u64,
+    // 用户看到第一个字的时间；非流式要等整段回复下载完
+    pub first_token_ms: Option<u64>,
+    pub content_chars: usize,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct StreamModeComparison {
+    pub streamed: ModeTiming,
+    pub non_streamed: ModeTiming,
+}
+
+async fn time_streamed(
+    client: &reqwest::Client,
+    url: &str,
+    api_key: &str,
+    request_body: &serde_json::Value,
+) -> Result<ModeTiming, String> {
+    let started = Instant::now();
+    let response = client::stream_request(client.post(url))
+        .header("Content-Type", "application/json")
+        .header("Authorization", format!("Bearer {}", api_key))
+        .json(request_body)
+        .send()
+        .await
+        .map_err(|e| format!("Failed to send request: {}", e))?;
+    if !response.status().is_success() {
+        return Err(client::api_error(response).await);
+    }
+
+    let mut parser = SseParser::default();
+    let mut first_token_ms = None;
+    let mut content_chars = 0;
+    let mut stream = client::stream_body(response);
+    'read: while let Some(chunk) = stream.next().await {
+        for event in parser.feed(&chunk?) {
+            if event.data == "[DONE]" {
+                break 'read;
+            }
+            if !is_content_event(&event.data) {
+                continue;
+            }
+            first_token_ms.get_or_insert_with(|| elapsed_ms(started));
+            if let Ok(chunk) = serde_json::from_str::<StreamChunk>(&event.data) {
+                content_chars += chunk
+                    .choices
+                    .first()
+                    .and_then(|choice| choice.delta.content.as_ref())
+                    .map_or(0, |content| content.chars().count());
+            }
+        }
+    }
+    Ok(ModeTiming {
+        total_ms: elapsed_ms(started),
+        first_token_ms,
+        content_chars,
+    })
+}
+
+// 同一提示词分别以流式和非流式各请求一次，比较总耗时和首字时间。
+// 先发一个 HEAD 请求预热连接，避免先测的一方承担建连开销；测量期间不发送事件
+#[tauri::command]
+pub async fn compare_stream_modes(
+    mut base_url: String,
+    api_key: String,
+    mut model: String,
+    messages: Vec<Message>,
+    app_handle: tauri::AppHandle,
+) -> Result<StreamModeComparison, String> {
+    let options = ChatOptions::resolve(None, &mut base_url, &mut model, &app_handle)?;
+    let url = format!("{}/chat/completions", base_url);
+    let client = app_handle.state::<HttpClient>().client_for(&url)?;
+    let messages = crate::attachments::resolve_attachments(messages, app_handle.clone())?;
+    let _ = client.head(&base_url).timeout(LATENCY_TIMEOUT).send().await;
+
+    let request_body = build_request_body(&model, &messages, true, false, &options)?;
+    let streamed = time_streamed(&client, &url, &api_key, &request_body).await?;
+
+    let request_body = build_request_body(&model, &messages, false, false, &options)?;
+    let started = Instant::now();
+    let response = client
+        .post(&url)
+        .header("Content-Type", "application/json")
+        .header("Authorization", format!("Bearer {}", api_key))
+        .json(&request_body)
+        .send()
+        .await
+        .map_err(|e| format!("Failed to send request: {}", e))?;
+    if !response.status().is_success() {
+        return Err(client::api_error(response).await);
+    }
+    let body = client::read_body(response, options.max_response_bytes).await?;
+    let total_ms = elapsed_ms(started);
+    let content_chars = parse_chat_response(&body, &options)?
+        .choices
+        .first()
+        .map_or(0, |choice| choice.message.content.chars().count());
+
+    Ok(StreamModeComparison {
+        streamed,
+        non_streamed: ModeTiming {
+            total_ms,
+            first_token_ms: (content_chars > 0).then_some(total_ms),
+            content_chars,
+        },
+    })
+}
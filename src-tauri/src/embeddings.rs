@@ -0,0 +1,209 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::PathBuf;
+
+use serde::{Deserialize, Serialize};
+use tauri::{AppHandle, Manager};
+
+use crate::client::{self, HttpClient};
+use crate::message_text;
+use crate::storage::{self, Conversation};
+
+// 一次请求最多嵌入的文本数
+const BATCH_SIZE: usize = 64;
+// 代表会话的文本长度上限（字符），超出部分截断
+const SUMMARY_CHARS: usize = 4000;
+
+#[derive(Debug, Deserialize)]
+struct EmbeddingResponse {
+    data: Vec<EmbeddingEntry>,
+}
+
+#[derive(Debug, Deserialize)]
+struct EmbeddingEntry {
+    #[serde(default)]
+    index: usize,
+    embedding: Vec<f32>,
+}
+
+// 会话未修改（updated_at 相同）且模型相同时直接复用
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct CachedEmbedding {
+    model: String,
+    updated_at: u64,
+    vector: Vec<f32>,
+}
+
+pub async fn embed(
+    client: &reqwest::Client,
+    base_url: &str,
+    api_key: &str,
+    model: &str,
+    inputs: &[String],
+) -> Result<Vec<Vec<f32>>, String> {
+    let url = format!("{}/embeddings", base_url);
+    let response = client
+        .post(&url)
+        .header("Content-Type", "application/json")
+        .header("Authorization", format!("Bearer {}", api_key))
+        .json(&serde_json::json!({ "model": model, "input": inputs }))
+        .send()
+        .await
+        .map_err(|e| format!("Failed to send request: {}", e))?;
+    if !response.status().is_success() {
+        return Err(client::api_error(response).await);
+    }
+
+    let mut parsed = response
+        .json::<EmbeddingResponse>()
+        .await
+        .map_err(|e| format!("Failed to parse response: {}", e))?;
+    if parsed.data.len() != inputs.len() {
+        return Err(format!(
+            "Expected {} embeddings, got {}",
+            inputs.len(),
+            parsed.data.len()
+        ));
+    }
+    parsed.data.sort_by_key(|entry| entry.index);
+    Ok(parsed
+        .data
+        .into_iter()
+        .map(|entry| entry.embedding)
+        .collect())
+}
+
+#[tauri::command]
+pub async fn create_embeddings(
+    base_url: String,
+    api_key: String,
+    model: String,
+    input: Vec<String>,
+    http: tauri::State<'_, HttpClient>,
+) -> Result<Vec<Vec<f32>>, String> {
+    let client = http.client_for(&format!("{}/embeddings", base_url))?;
+    let mut vectors = Vec::with_capacity(input.len());
+    for batch in input.chunks(BATCH_SIZE) {
+        vectors.extend(embed(&client, &base_url, &api_key, &model, batch).await?);
+    }
+    Ok(vectors)
+}
+
+fn cosine_similarity(a: &[f32], b: &[f32]) -> f32 {
+    if a.len() != b.len() {
+        return 0.0;
+    }
+    let dot: f32 = a.iter().zip(b).map(|(x, y)| x * y).sum();
+    let norm = |v: &[f32]| v.iter().map(|x| x * x).sum::<f32>().sqrt();
+    let denominator = norm(a) * norm(b);
+    if denominator == 0.0 {
+        0.0
+    } else {
+        dot / denominator
+    }
+}
+
+// 标题加上 user / assistant 消息的正文，从开头截取，足以代表会话主题
+fn summary_text(conversation: &Conversation) -> String {
+    let mut text = conversation.title.clone();
+    for message in &conversation.messages {
+        if message.role != "user" && message.role != "assistant" {
+            continue;
+        }
+        text.push('\n');
+        text.push_str(&message_text(&message.content));
+        if text.chars().count() >= SUMMARY_CHARS {
+            break;
+        }
+    }
+    text.chars().take(SUMMARY_CHARS).collect()
+}
+
+fn cache_path(app_handle: &AppHandle) -> Result<PathBuf, String> {
+    let dir = app_handle
+        .path()
+        .app_data_dir()
+        .map_err(|e| format!("Failed to resolve app data dir: {}", e))?;
+    fs::create_dir_all(&dir).map_err(|e| format!("Failed to create app data dir: {}", e))?;
+    Ok(dir.join("embeddings.json"))
+}
+
+fn load_cache(app_handle: &AppHandle) -> BTreeMap<String, CachedEmbedding> {
+    cache_path(app_handle)
+        .ok()
+        .and_then(|path| fs::read_to_string(path).ok())
+        .and_then(|text| serde_json::from_str(&text).ok())
+        .unwrap_or_default()
+}
+
+fn save_cache(
+    app_handle: &AppHandle,
+    cache: &BTreeMap<String, CachedEmbedding>,
+) -> Result<(), String> {
+    let path = cache_path(app_handle)?;
+    let text = serde_json::to_string(cache)
+        .map_err(|e| format!("Failed to serialize embeddings: {}", e))?;
+    let tmp = path.with_extension("json.tmp");
+    fs::write(&tmp, text).map_err(|e| format!("Failed to write embeddings: {}", e))?;
+    fs::rename(&tmp, &path).map_err(|e| format!("Failed to write embeddings: {}", e))
+}
+
+// 按与目标会话的余弦相似度返回最相近的 top_k 个会话 (id, 相似度)。
+// 会话的嵌入缓存在 embeddings.json 中，只有新增或修改过的会话才重新计算
+#[tauri::command]
+pub async fn find_similar_conversations(
+    id: String,
+    top_k: usize,
+    base_url: String,
+    api_key: String,
+    model: String,
+    app_handle: AppHandle,
+) -> Result<Vec<(String, f32)>, String> {
+    let conversations = storage::load_all(&app_handle)?;
+    if !conversations.iter().any(|c| c.id == id) {
+        return Err(format!("Conversation not found: {}", id));
+    }
+
+    let mut cache = load_cache(&app_handle);
+    // 已删除会话的缓存一并清理
+    cache.retain(|cached_id, _| conversations.iter().any(|c| &c.id == cached_id));
+    let stale: Vec<&Conversation> = conversations
+        .iter()
+        .filter(|c| {
+            cache
+                .get(&c.id)
+                .is_none_or(|cached| cached.model != model || cached.updated_at != c.updated_at)
+        })
+        .collect();
+
+    if !stale.is_empty() {
+        let client = app_handle
+            .state::<HttpClient>()
+            .client_for(&format!("{}/embeddings", base_url))?;
+        for batch in stale.chunks(BATCH_SIZE) {
+            let inputs: Vec<String> = batch.iter().map(|c| summary_text(c)).collect();
+            let vectors = embed(&client, &base_url, &api_key, &model, &inputs).await?;
+            for (conversation, vector) in batch.iter().zip(vectors) {
+                cache.insert(
+                    conversation.id.clone(),
+                    CachedEmbedding {
+                        model: model.clone(),
+                        updated_at: conversation.updated_at,
+                        vector,
+                    },
+                );
+            }
+        }
+    }
+    save_cache(&app_handle, &cache)?;
+
+    let target = &cache[&id].vector;
+    let mut scores: Vec<(String, f32)> = cache
+        .iter()
+        .filter(|(other_id, _)| **other_id != id)
+        .map(|(other_id, cached)| (other_id.clone(), cosine_similarity(target, &cached.vector)))
+        .collect();
+    scores.sort_by(|a, b| b.1.total_cmp(&a.1));
+    scores.truncate(top_k);
+    Ok(scores)
+}
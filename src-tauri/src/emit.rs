@@ -0,0 +1,748 @@
+use std::collections::VecDeque;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use serde::Serialize;
+use tauri::{AppHandle, Emitter};
+
+use crate::transform::StreamTransform;
+use crate::StreamData;
+
+// 合并多个增量后再发送，减少 Rust 与 webview 之间的 IPC 次数
+pub struct EmitBuffer {
+    max_deltas: usize,
+    max_delay: Option<Duration>,
+    pending: Option<StreamData>,
+    count: usize,
+    since: Instant,
+    // 统计用：收到的增量数与实际 emit 次数
+    pub received: usize,
+    pub emitted: usize,
+}
+
+impl EmitBuffer {
+    pub fn new(max_deltas: Option<usize>, interval_ms: Option<u64>) -> Self {
+        EmitBuffer {
+            max_deltas: max_deltas.unwrap_or(1).max(1),
+            max_delay: interval_ms.map(Duration::from_millis),
+            pending: None,
+            count: 0,
+            since: Instant::now(),
+            received: 0,
+            emitted: 0,
+        }
+    }
+
+    fn is_passthrough(&self) -> bool {
+        self.max_deltas == 1 && self.max_delay.is_none()
+    }
+
+    // 放入一个增量，达到条数或时间阈值时返回需要立即发送的合并结果
+    pub fn push(&mut self, data: StreamData) -> Option<StreamData> {
+        self.received += 1;
+        if self.is_passthrough() {
+            self.emitted += 1;
+            return Some(data);
+        }
+
+        let terminal = data.finish_reason.is_some();
+        match &mut self.pending {
+            Some(pending) => merge(pending, data),
+            None => {
+                self.since = Instant::now();
+                self.pending = Some(data);
+            }
+        }
+        self.count += 1;
+
+        let elapsed = self
+            .max_delay
+            .is_some_and(|delay| self.since.elapsed() >= delay);
+        if terminal || self.count >= self.max_deltas || elapsed {
+            return self.flush();
+        }
+        None
+    }
+
+    pub fn flush(&mut self) -> Option<StreamData> {
+        self.count = 0;
+        let pending = self.pending.take();
+        if pending.is_some() {
+            self.emitted += 1;
+        }
+        pending
+    }
+
+    // 有待发送数据时，最晚应在何时按时间阈值发送
+    pub fn deadline(&self) -> Option<tokio::time::Instant> {
+        let delay = self.max_delay?;
+        self.pending.as_ref()?;
+        Some(tokio::time::Instant::from_std(self.since + delay))
+    }
+}
+
+fn append(target: &mut Option<String>, text: Option<String>) {
+    if let Some(text) = text {
+        target.get_or_insert_with(String::new).push_str(&text);
+    }
+}
+
+fn merge(pending: &mut StreamData, data: StreamData) {
+    if pending.role.is_none() {
+        pending.role = data.role;
+    }
+    append(&mut pending.content, data.content);
+    append(&mut pending.reasoning_content, data.reasoning_content);
+    if let Some(deltas) = data.tool_call_deltas {
+        crate::tool_calls::merge(
+            pending.tool_call_deltas.get_or_insert_with(Vec::new),
+            deltas,
+        );
+    }
+    if data.finish_reason.is_some() {
+        pending.finish_reason = data.finish_reason;
+    }
+    pending.done |= data.done;
+}
+
+// 流式数据的输出端：解析循环只负责产出 StreamData，由各个 sink 决定如何处理
+pub trait StreamSink: Send {
+    fn send(&mut self, data: &StreamData) -> Result<(), String>;
+
+    fn finish(&mut self) -> Result<(), String> {
+        Ok(())
+    }
+}
+
+// 发送 Tauri 事件到前端（默认 sink）
+pub struct EventSink {
+    app_handle: AppHandle,
+}
+
+impl StreamSink for EventSink {
+    fn send(&mut self, data: &StreamData) -> Result<(), String> {
+        self.app_handle
+            .emit("stream-chunk", data)
+            .map_err(|e| format!("Failed to emit stream chunk: {}", e))
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct BackpressureWarning {
+    pub capacity: usize,
+}
+
+// 前端已处理完的事件数（最后确认的 seq + 1），由 ack_stream_chunk 命令更新
+pub type Acked = Arc<AtomicU64>;
+
+// 暂存的合并内容最多保留这么久，前端一直不确认时也会按这个间隔继续发出
+const PENDING_MAX_DELAY: Duration = Duration::from_millis(500);
+// 有暂存内容时检查确认进度的间隔
+const QUEUE_POLL_INTERVAL: Duration = Duration::from_millis(25);
+
+// 按前端确认做背压：emit 不等待 webview 处理，因此以已发送但未确认的事件数为准。
+// 超过 capacity 时不阻塞网络读取也不丢弃，而是把后续增量合并成一条暂存，
+// 等前端确认跟上或暂存超过 PENDING_MAX_DELAY 后再发出
+struct EventQueue {
+    frontend: Box<dyn StreamSink>,
+    capacity: u64,
+    acked: Acked,
+    // 经队列发出的事件数，与下一个 seq 相同
+    emitted: u64,
+    pending: Option<StreamData>,
+    pending_since: Instant,
+    max_delay: Duration,
+    coalescing: bool,
+    app_handle: Option<AppHandle>,
+}
+
+impl EventQueue {
+    fn new(frontend: Box<dyn StreamSink>, capacity: usize, acked: Acked) -> Self {
+        EventQueue {
+            frontend,
+            capacity: capacity.max(1) as u64,
+            acked,
+            emitted: 0,
+            pending: None,
+            pending_since: Instant::now(),
+            max_delay: PENDING_MAX_DELAY,
+            coalescing: false,
+            app_handle: None,
+        }
+    }
+
+    fn is_full(&self) -> bool {
+        let acked = self.acked.load(Ordering::Relaxed).min(self.emitted);
+        self.emitted - acked >= self.capacity
+    }
+
+    // 暂存的内容是否应该发出
+    fn is_ready(&self) -> bool {
+        !self.is_full() || self.pending_since.elapsed() >= self.max_delay
+    }
+
+    // 放入暂存，与已有的暂存合并
+    fn hold(&mut self, data: StreamData) {
+        match &mut self.pending {
+            Some(pending) => merge(pending, data),
+            None => {
+                self.pending_since = Instant::now();
+                self.pending = Some(data);
+            }
+        }
+    }
+
+    fn emit(&mut self, data: &StreamData) {
+        self.emitted += 1;
+        if let Err(e) = self.frontend.send(data) {
+            log::warn!("{}", e);
+        }
+    }
+
+    // 每次开始合并时提示一次，队列恢复后重新计
+    fn warn_backpressure(&mut self) {
+        if self.coalescing {
+            return;
+        }
+        self.coalescing = true;
+        log::warn!(
+            "Frontend has not acknowledged {} stream events, coalescing deltas",
+            self.capacity
+        );
+        if let Some(app_handle) = &self.app_handle {
+            let _ = app_handle.emit(
+                "stream-backpressure",
+                BackpressureWarning {
+                    capacity: self.capacity as usize,
+                },
+            );
+        }
+    }
+}
+
+// 以 JSON Lines 形式把每个 StreamData 写入文件，便于留档排查
+pub struct FileSink {
+    writer: BufWriter<File>,
+}
+
+impl FileSink {
+    pub fn create(path: &str) -> Result<Self, String> {
+        let file = File::create(path)
+            .map_err(|e| format!("Failed to create stream output file {}: {}", path, e))?;
+        Ok(FileSink {
+            writer: BufWriter::new(file),
+        })
+    }
+}
+
+impl StreamSink for FileSink {
+    fn send(&mut self, data: &StreamData) -> Result<(), String> {
+        let line = serde_json::to_string(data)
+            .map_err(|e| format!("Failed to serialize stream chunk: {}", e))?;
+        writeln!(self.writer, "{}", line)
+            .map_err(|e| format!("Failed to write stream chunk: {}", e))
+    }
+
+    fn finish(&mut self) -> Result<(), String> {
+        self.writer
+            .flush()
+            .map_err(|e| format!("Failed to flush stream output file: {}", e))
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct Accumulated {
+    pub content: String,
+    pub reasoning_content: String,
+}
+
+// 在内存中拼接完整回复，结束后可通过共享句柄读取
+pub struct AccumulatorSink {
+    accumulated: Arc<Mutex<Accumulated>>,
+}
+
+impl AccumulatorSink {
+    pub fn new() -> (Self, Arc<Mutex<Accumulated>>) {
+        let accumulated = Arc::new(Mutex::new(Accumulated::default()));
+        (
+            AccumulatorSink {
+                accumulated: Arc::clone(&accumulated),
+            },
+            accumulated,
+        )
+    }
+}
+
+impl StreamSink for AccumulatorSink {
+    fn send(&mut self, data: &StreamData) -> Result<(), String> {
+        let mut accumulated = self.accumulated.lock().unwrap();
+        if let Some(content) = &data.content {
+            accumulated.content.push_str(content);
+        }
+        if let Some(reasoning) = &data.reasoning_content {
+            accumulated.reasoning_content.push_str(reasoning);
+        }
+        Ok(())
+    }
+}
+
+// 每个流已发送过的数据，前端订阅晚了可以据此补齐开头
+pub type RecentChunks = Arc<Mutex<VecDeque<StreamData>>>;
+
+// 超过条数后新数据并入最后一条，开头不丢、内容也不丢
+const RECENT_CAPACITY: usize = 256;
+
+pub struct RecentSink {
+    recent: RecentChunks,
+}
+
+impl StreamSink for RecentSink {
+    fn send(&mut self, data: &StreamData) -> Result<(), String> {
+        let mut recent = self.recent.lock().unwrap();
+        let full = recent.len() >= RECENT_CAPACITY;
+        match recent.back_mut() {
+            // 合并后的一条带最新的 seq，前端按 seq 去重时不会与之后的实时事件重叠
+            Some(last) if full => {
+                merge(last, data.clone());
+                last.seq = data.seq;
+            }
+            _ => recent.push_back(data.clone()),
+        }
+        Ok(())
+    }
+}
+
+pub struct Sinks {
+    sinks: Vec<Box<dyn StreamSink>>,
+    next_seq: u64,
+    // 所有 sink 收到的都是变换后的正文
+    transform: Option<Box<dyn StreamTransform>>,
+    sanitize: bool,
+    queue: Option<EventQueue>,
+}
+
+impl Sinks {
+    // 不向前端发送事件，输出端全部由 add 添加
+    pub fn new() -> Self {
+        Sinks {
+            sinks: Vec::new(),
+            next_seq: 0,
+            transform: None,
+            sanitize: false,
+            queue: None,
+        }
+    }
+
+    pub fn events(app_handle: AppHandle) -> Self {
+        let mut sinks = Sinks::new();
+        sinks.add(EventSink { app_handle });
+        sinks
+    }
+
+    // 前端未确认的事件超过 capacity 时合并增量，见 EventQueue
+    pub fn queued_events(app_handle: AppHandle, capacity: usize, acked: Acked) -> Self {
+        let frontend = EventSink {
+            app_handle: app_handle.clone(),
+        };
+        let mut sinks = Sinks::queued(frontend, capacity, acked);
+        if let Some(queue) = &mut sinks.queue {
+            queue.app_handle = Some(app_handle);
+        }
+        sinks
+    }
+
+    fn queued(frontend: impl StreamSink + 'static, capacity: usize, acked: Acked) -> Self {
+        let mut sinks = Sinks::new();
+        sinks.queue = Some(EventQueue::new(Box::new(frontend), capacity, acked));
+        sinks
+    }
+
+    pub fn set_sanitize(&mut self, sanitize: bool) {
+        self.sanitize = sanitize;
+    }
+
+    pub fn set_transform(&mut self, transform: Option<Box<dyn StreamTransform>>) {
+        self.transform = transform;
+    }
+
+    // 先记录再发事件，保证前端订阅后补取时不会漏掉中间的数据
+    pub fn record_recent(&mut self, recent: RecentChunks) {
+        self.sinks.insert(0, Box::new(RecentSink { recent }));
+    }
+
+    pub fn add(&mut self, sink: impl StreamSink + 'static) {
+        self.sinks.push(Box::new(sink));
+    }
+
+    // 单个 sink 出错只记录日志，不影响其他 sink 和流本身
+    pub fn send(&mut self, data: &StreamData) {
+        // 在这里统一编号，所有 sink 看到的序号一致
+        let mut data = StreamData {
+            seq: self.next_seq,
+            ..data.clone()
+        };
+        if self.sanitize {
+            data.content = data.content.as_deref().map(crate::transform::sanitize);
+            data.reasoning_content = data
+                .reasoning_content
+                .as_deref()
+                .map(crate::transform::sanitize);
+        }
+        if let Some(transform) = &mut self.transform {
+            let mut content = data.content.as_deref().map(|c| transform.feed(c));
+            // 回复结束前输出变换留存的尾部
+            if data.done || data.finish_reason.is_some() {
+                let rest = transform.finish();
+                if !rest.is_empty() {
+                    content.get_or_insert_with(String::new).push_str(&rest);
+                }
+            }
+            data.content = content.filter(|c| !c.is_empty());
+            // 正文全部被留存且没有其他字段时不必发送
+            if data.content.is_none()
+                && data.role.is_none()
+                && data.reasoning_content.is_none()
+                && data.tool_call_deltas.is_none()
+                && data.finish_reason.is_none()
+                && !data.done
+            {
+                return;
+            }
+        }
+
+        // 暂存期间不分配序号、也不交给其他 sink，保证各处看到的序号连续且一致
+        if let Some(queue) = &mut self.queue {
+            if queue.pending.is_some() || !queue.is_ready() {
+                queue.hold(data);
+                if !queue.is_ready() {
+                    queue.warn_backpressure();
+                    return;
+                }
+                data = queue.pending.take().unwrap_or_default();
+            }
+            queue.coalescing = false;
+        }
+        self.deliver(data);
+    }
+
+    fn deliver(&mut self, mut data: StreamData) {
+        data.seq = self.next_seq;
+        self.next_seq += 1;
+        for sink in &mut self.sinks {
+            if let Err(e) = sink.send(&data) {
+                log::warn!("{}", e);
+            }
+        }
+        if let Some(queue) = &mut self.queue {
+            queue.emit(&data);
+        }
+    }
+
+    // 有暂存内容时，最晚应在何时再检查一次前端确认进度
+    pub fn deadline(&self) -> Option<tokio::time::Instant> {
+        self.queue.as_ref()?.pending.as_ref()?;
+        Some(tokio::time::Instant::now() + QUEUE_POLL_INTERVAL)
+    }
+
+    // 由解析循环按 deadline 定时调用：前端确认跟上或暂存过久时发出暂存内容，
+    // 避免上游停顿时内容一直留在暂存中
+    pub fn poll_queue(&mut self) {
+        let ready = self
+            .queue
+            .as_ref()
+            .is_some_and(|queue| queue.pending.is_some() && queue.is_ready());
+        if ready {
+            self.flush_queue();
+        }
+    }
+
+    // 无条件发出暂存内容；流结束后不再有新数据，不会打乱顺序
+    fn flush_queue(&mut self) {
+        let Some(queue) = &mut self.queue else {
+            return;
+        };
+        let Some(data) = queue.pending.take() else {
+            return;
+        };
+        queue.coalescing = false;
+        self.deliver(data);
+    }
+
+    pub fn finish(&mut self) {
+        // 流异常结束、没有发送完成事件时，留存的内容也写出去
+        let rest = self.transform.as_mut().map(|t| t.finish());
+        if let Some(rest) = rest.filter(|r| !r.is_empty()) {
+            self.transform = None;
+            self.send(&StreamData {
+                content: Some(rest),
+                ..Default::default()
+            });
+        }
+        self.flush_queue();
+        for sink in &mut self.sinks {
+            if let Err(e) = sink.finish() {
+                log::warn!("{}", e);
+            }
+        }
+    }
+}
+
+// 提前返回、没有调用 finish 的流也不丢失暂存的内容
+impl Drop for Sinks {
+    fn drop(&mut self) {
+        self.flush_queue();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // 记录收到的每条数据
+    struct Recorder(Arc<Mutex<Vec<StreamData>>>);
+
+    impl StreamSink for Recorder {
+        fn send(&mut self, data: &StreamData) -> Result<(), String> {
+            self.0.lock().unwrap().push(data.clone());
+            Ok(())
+        }
+    }
+
+    fn recording() -> (Sinks, Arc<Mutex<Vec<StreamData>>>) {
+        let received = Arc::new(Mutex::new(Vec::new()));
+        let mut sinks = Sinks::new();
+        sinks.add(Recorder(received.clone()));
+        (sinks, received)
+    }
+
+    fn text(content: &str) -> StreamData {
+        StreamData {
+            content: Some(content.to_string()),
+            ..Default::default()
+        }
+    }
+
+    fn seqs(received: &Mutex<Vec<StreamData>>) -> Vec<u64> {
+        received
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|data| data.seq)
+            .collect()
+    }
+
+    #[test]
+    fn seq_increases_by_one_per_send() {
+        let (mut sinks, received) = recording();
+        for content in ["a", "b", "c"] {
+            sinks.send(&text(content));
+        }
+        sinks.send(&StreamData::finished(Some("stop".to_string())));
+        assert_eq!(seqs(&received), [0, 1, 2, 3]);
+    }
+
+    #[test]
+    fn seq_restarts_for_each_stream() {
+        let (mut first, _) = recording();
+        first.send(&text("a"));
+        first.send(&text("b"));
+        let (mut second, received) = recording();
+        second.send(&text("c"));
+        assert_eq!(seqs(&received), [0]);
+    }
+
+    #[test]
+    fn held_back_chunks_leave_no_gap() {
+        let (mut sinks, received) = recording();
+        sinks.set_transform(Some(Box::<crate::transform::WordBoundary>::default()));
+        // 半个单词被变换留存，不发送也不占用序号
+        sinks.send(&text("hel"));
+        sinks.send(&text("lo wor"));
+        sinks.send(&text("ld"));
+        sinks.send(&StreamData::finished(Some("stop".to_string())));
+        assert_eq!(seqs(&received), [0, 1]);
+        let contents: Vec<Option<String>> = received
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|data| data.content.clone())
+            .collect();
+        assert_eq!(
+            contents,
+            [Some("hello ".to_string()), Some("world".to_string())]
+        );
+    }
+
+    #[test]
+    fn sanitize_applies_to_content_and_reasoning() {
+        let (mut sinks, received) = recording();
+        sinks.set_sanitize(true);
+        sinks.send(&StreamData {
+            content: Some("ok\u{0}\u{200B}".to_string()),
+            reasoning_content: Some("\u{7}think".to_string()),
+            ..Default::default()
+        });
+        let data = received.lock().unwrap()[0].clone();
+        assert_eq!(data.content.as_deref(), Some("ok"));
+        assert_eq!(data.reasoning_content.as_deref(), Some("think"));
+    }
+
+    #[test]
+    fn done_event_flushes_held_back_word() {
+        let (mut sinks, received) = recording();
+        sinks.set_transform(Some(Box::<crate::transform::WordBoundary>::default()));
+        sinks.send(&text("Hello wor"));
+        sinks.send(&text("ld"));
+        sinks.send(&StreamData::finished(Some("stop".to_string())));
+        let received = received.lock().unwrap();
+        assert_eq!(received.len(), 2);
+        assert_eq!(received[0].content.as_deref(), Some("Hello "));
+        assert!(received[1].done);
+        assert_eq!(received[1].content.as_deref(), Some("world"));
+    }
+
+    #[test]
+    fn finish_without_done_event_writes_held_back_word() {
+        let (mut sinks, received) = recording();
+        sinks.set_transform(Some(Box::<crate::transform::WordBoundary>::default()));
+        sinks.send(&text("cut off mid"));
+        sinks.finish();
+        let contents: Vec<Option<String>> = received
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|data| data.content.clone())
+            .collect();
+        assert_eq!(
+            contents,
+            [Some("cut off ".to_string()), Some("mid".to_string())]
+        );
+    }
+
+    // 模拟处理很慢的前端：只有显式确认后 acked 才前进
+    fn slow_frontend(capacity: usize) -> (Sinks, Arc<Mutex<Vec<StreamData>>>, Acked) {
+        let received = Arc::new(Mutex::new(Vec::new()));
+        let acked = Acked::default();
+        let sinks = Sinks::queued(Recorder(received.clone()), capacity, acked.clone());
+        (sinks, received, acked)
+    }
+
+    fn contents(received: &Mutex<Vec<StreamData>>) -> Vec<String> {
+        received
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|d| d.content.clone().unwrap_or_default())
+            .collect()
+    }
+
+    #[test]
+    fn unacknowledged_events_are_coalesced_until_ack() {
+        let (mut sinks, received, acked) = slow_frontend(2);
+        for word in ["a", "b", "c", "d", "e"] {
+            sinks.send(&text(word));
+        }
+        // 前端还没确认任何事件，超出容量的部分合并暂存
+        assert_eq!(contents(&received), ["a", "b"]);
+        assert!(sinks.deadline().is_some());
+        sinks.poll_queue();
+        assert_eq!(contents(&received), ["a", "b"]);
+
+        acked.store(1, Ordering::Relaxed);
+        sinks.poll_queue();
+        assert_eq!(contents(&received), ["a", "b", "cde"]);
+        assert_eq!(seqs(&received), [0, 1, 2]);
+        assert!(sinks.deadline().is_none());
+
+        // 确认跟上后恢复逐条发送
+        acked.store(3, Ordering::Relaxed);
+        sinks.send(&text("f"));
+        assert_eq!(contents(&received), ["a", "b", "cde", "f"]);
+    }
+
+    #[test]
+    fn stalled_pending_is_flushed_after_max_delay() {
+        let (mut sinks, received, _acked) = slow_frontend(1);
+        sinks.send(&text("a"));
+        sinks.send(&text("b"));
+        assert_eq!(contents(&received), ["a"]);
+
+        // 上游停顿、前端也一直不确认时，超过最长暂存时间后照样发出
+        sinks.queue.as_mut().unwrap().max_delay = Duration::ZERO;
+        sinks.poll_queue();
+        assert_eq!(contents(&received), ["a", "b"]);
+    }
+
+    #[test]
+    fn finish_flushes_pending_without_ack() {
+        let (mut sinks, received, _acked) = slow_frontend(1);
+        sinks.send(&text("a"));
+        sinks.send(&text("b"));
+        sinks.send(&StreamData::finished(Some("stop".to_string())));
+        sinks.finish();
+        let received = received.lock().unwrap();
+        assert_eq!(received.len(), 2);
+        assert_eq!(received[1].content.as_deref(), Some("b"));
+        assert!(received[1].done);
+    }
+
+    #[test]
+    fn recent_chunks_keep_the_head_of_long_streams() {
+        let recent = RecentChunks::default();
+        let mut sinks = Sinks::new();
+        sinks.record_recent(recent.clone());
+        let words: Vec<String> = (0..RECENT_CAPACITY + 10)
+            .map(|i| format!("{} ", i))
+            .collect();
+        for word in &words {
+            sinks.send(&text(word));
+        }
+
+        let recent = recent.lock().unwrap();
+        assert_eq!(recent.len(), RECENT_CAPACITY);
+        assert_eq!(recent[0].content.as_deref(), Some("0 "));
+        let last = recent.back().unwrap();
+        assert_eq!(last.seq, words.len() as u64 - 1);
+        assert_eq!(
+            last.content.as_deref(),
+            Some(words[RECENT_CAPACITY - 1..].concat().as_str())
+        );
+    }
+
+    // 长流式回复：逐条发送时每个增量一次 emit，按 16 条合并后只剩约 1/16
+    #[test]
+    fn batching_cuts_emits_on_long_streams() {
+        let deltas: Vec<StreamData> = (0..10_000)
+            .map(|i| text(&format!("{} ", i)))
+            .chain([StreamData::finished(Some("stop".to_string()))])
+            .collect();
+        let run = |mut buffer: EmitBuffer| {
+            let mut sent: Vec<StreamData> = deltas
+                .iter()
+                .filter_map(|data| buffer.push(data.clone()))
+                .collect();
+            sent.extend(buffer.flush());
+            assert_eq!(buffer.received, deltas.len());
+            assert_eq!(buffer.emitted, sent.len());
+            sent
+        };
+
+        assert_eq!(run(EmitBuffer::new(None, None)).len(), 10_001);
+        let batched = run(EmitBuffer::new(Some(16), None));
+        // 625 个满批，结束分片单独成批
+        assert_eq!(batched.len(), 626);
+        let content: String = batched
+            .iter()
+            .filter_map(|data| data.content.as_deref())
+            .collect();
+        let expected: String = (0..10_000).map(|i| format!("{} ", i)).collect();
+        assert_eq!(content, expected);
+        assert_eq!(
+            batched.last().unwrap().finish_reason.as_deref(),
+            Some("stop")
+        );
+    }
+}
@@ -0,0 +1,253 @@
+use futures_util::future::join_all;
+use serde::{Deserialize, Serialize};
+use tauri::{AppHandle, Emitter, Manager};
+use tokio_util::sync::CancellationToken;
+
+use crate::client::{self, HttpClient};
+use crate::emit::{self, StreamSink};
+use crate::streams::StreamRegistry;
+use crate::{
+    attachments, build_request_body, cassette, choice_pieces, end_stream, parse_fallback_response,
+    rate_limit, stream_transform, think, with_deadline, ChatOptions, Message, StreamChunk,
+    StreamData, StreamEnd, StreamReader, StreamStep,
+};
+
+// 参与对比的一路服务配置
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ProviderConfig {
+    pub base_url: String,
+    pub api_key: String,
+    pub model: String,
+    #[serde(default)]
+    pub options: Option<ChatOptions>,
+}
+
+// 通过 ensemble-chunk 事件发送，source_index 对应 configs 中的位置
+#[derive(Debug, Clone, Serialize)]
+pub struct EnsembleChunk {
+    pub stream_id: String,
+    pub source_index: usize,
+    #[serde(flatten)]
+    pub data: StreamData,
+}
+
+// 某一路失败时通过 ensemble-error 事件发送，其余各路继续
+#[derive(Debug, Clone, Serialize)]
+pub struct EnsembleError {
+    pub stream_id: String,
+    pub source_index: usize,
+    pub error: String,
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct EnsembleSourceResult {
+    pub source_index: usize,
+    pub model: Option<String>,
+    pub finish_reason: Option<String>,
+    pub content: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+// 把某一路的数据以 ensemble-chunk 事件发给前端；序号由 Sinks 统一分配
+struct EnsembleSink {
+    app_handle: AppHandle,
+    stream_id: String,
+    source_index: usize,
+}
+
+impl StreamSink for EnsembleSink {
+    fn send(&mut self, data: &StreamData) -> Result<(), String> {
+        self.app_handle
+            .emit(
+                "ensemble-chunk",
+                EnsembleChunk {
+                    stream_id: self.stream_id.clone(),
+                    source_index: self.source_index,
+                    data: data.clone(),
+                },
+            )
+            .map_err(|e| format!("Failed to emit ensemble chunk: {}", e))
+    }
+}
+
+// 与 chat_completions_stream 共用读取循环的结束处理、输出变换和 choice 选择，
+// 同样的选项在两处表现一致
+async fn stream_source(
+    config: ProviderConfig,
+    messages: &[Message],
+    app_handle: &AppHandle,
+    token: &CancellationToken,
+    sinks: &mut emit::Sinks,
+    result: &mut EnsembleSourceResult,
+) -> Result<StreamEnd, String> {
+    let ProviderConfig {
+        mut base_url,
+        api_key,
+        mut model,
+        options,
+    } = config;
+    let options = ChatOptions::resolve(options, &mut base_url, &mut model, app_handle)?;
+    sinks.set_transform(stream_transform(&options)?);
+    sinks.set_sanitize(options.sanitize_output);
+    let url = format!("{}/chat/completions", base_url);
+    let client = app_handle.state::<HttpClient>().client_for(&url)?;
+    let request_body = build_request_body(&model, messages, true, false, &options)?;
+    let request_builder = client::stream_request(client.post(&url))
+        .header("Content-Type", "application/json")
+        .header("Authorization", format!("Bearer {}", api_key))
+        .json(&request_body);
+
+    let request_deadline = options.deadline();
+    let response = with_deadline(request_deadline, async {
+        rate_limit::throttle(app_handle, &url).await;
+        cassette::send(app_handle, &url, &request_body, request_builder).await
+    })
+    .await??;
+    rate_limit::observe(app_handle, &url, response.status(), response.headers());
+    if !response.status().is_success() {
+        return Err(client::api_error(response).await);
+    }
+
+    let is_sse = response
+        .headers()
+        .get(reqwest::header::CONTENT_TYPE)
+        .and_then(|v| v.to_str().ok())
+        .is_none_or(|ct| ct.contains("text/event-stream"));
+    if !is_sse {
+        let body = with_deadline(
+            request_deadline,
+            client::read_body(response, options.max_response_bytes),
+        )
+        .await??;
+        let response = parse_fallback_response(&body, &options)?;
+        result.model = Some(response.model).filter(|m| !m.is_empty());
+        if let Some(choice) = response.choices.into_iter().next() {
+            result.finish_reason = choice.finish_reason.clone();
+            sinks.send(&StreamData {
+                role: Some(choice.message.role),
+                content: Some(choice.message.content).filter(|c| !c.is_empty()),
+                reasoning_content: choice.message.reasoning_content,
+                finish_reason: choice.finish_reason,
+                ..Default::default()
+            });
+        }
+        return Ok(StreamEnd::Done);
+    }
+
+    let mut think_splitter = options
+        .extract_think_tags
+        .then(think::ThinkTagSplitter::default);
+    let mut reader = StreamReader::new(
+        client::stream_body(response),
+        token.clone(),
+        request_deadline,
+        options.max_response_bytes,
+        false,
+    );
+    let end = 'read: loop {
+        let events = match reader.next(None).await {
+            StreamStep::Events { events, .. } => events,
+            StreamStep::Idle => continue,
+            StreamStep::End(end) => break 'read end,
+        };
+        for event in events {
+            if event.data == "[DONE]" {
+                break 'read StreamEnd::Done;
+            }
+            let Ok(json) = serde_json::from_str::<StreamChunk>(&event.data) else {
+                continue;
+            };
+            if result.model.is_none() && !json.model.is_empty() {
+                result.model = Some(json.model.clone());
+            }
+            let Some(choice) = json.choice(options.stream_only_index) else {
+                continue;
+            };
+            if choice.finish_reason.is_some() {
+                result.finish_reason = choice.finish_reason.clone();
+            }
+            for data in choice_pieces(choice, think_splitter.as_mut()) {
+                sinks.send(&data);
+            }
+        }
+    };
+    // 被留存、疑似标签开头的尾部内容
+    for segment in think_splitter
+        .as_mut()
+        .map(|s| s.finish())
+        .into_iter()
+        .flatten()
+    {
+        sinks.send(&StreamData::from(segment));
+    }
+    Ok(end)
+}
+
+// 同一组消息同时发给多个模型，各路增量一到就以 ensemble-chunk 事件转发（带 source_index）。
+// 某一路失败只发送该路的 ensemble-error，不影响其他各路；每一路结束时都会发送 done
+#[tauri::command]
+pub async fn chat_completions_ensemble(
+    configs: Vec<ProviderConfig>,
+    messages: Vec<Message>,
+    stream_id: Option<String>,
+    app_handle: AppHandle,
+) -> Result<Vec<EnsembleSourceResult>, String> {
+    if configs.is_empty() {
+        return Err("At least one provider config is required".to_string());
+    }
+    let registry = app_handle.state::<StreamRegistry>();
+    let guard = registry.register(stream_id);
+    let _ = app_handle.emit("stream-started", guard.id());
+    let stream_id = guard.id().to_string();
+    let messages = attachments::resolve_attachments(messages, app_handle.clone())?;
+
+    let sources = configs
+        .into_iter()
+        .enumerate()
+        .map(|(source_index, config)| {
+            let (app_handle, stream_id, messages, token) =
+                (&app_handle, &stream_id, &messages, &guard.token);
+            async move {
+                let mut sinks = emit::Sinks::new();
+                sinks.add(EnsembleSink {
+                    app_handle: app_handle.clone(),
+                    stream_id: stream_id.clone(),
+                    source_index,
+                });
+                let (accumulator, accumulated) = emit::AccumulatorSink::new();
+                sinks.add(accumulator);
+                let mut result = EnsembleSourceResult {
+                    source_index,
+                    ..Default::default()
+                };
+                // 等待响应头期间也要能取消；读取流时由 StreamReader 处理取消
+                let outcome = tokio::select! {
+                    _ = token.cancelled() => Ok(StreamEnd::Cancelled),
+                    outcome = stream_source(config, messages, app_handle, token, &mut sinks, &mut result) => outcome,
+                };
+                let end = outcome.unwrap_or_else(StreamEnd::Failed);
+                let error = match &end {
+                    StreamEnd::Cancelled => Some("Stream cancelled".to_string()),
+                    end => end.error(),
+                };
+                if let Some(error) = error {
+                    log::warn!("Ensemble source {} failed: {}", source_index, error);
+                    let _ = app_handle.emit(
+                        "ensemble-error",
+                        EnsembleError {
+                            stream_id: stream_id.clone(),
+                            source_index,
+                            error: error.clone(),
+                        },
+                    );
+                    result.error = Some(error);
+                }
+                end_stream(&mut sinks, &end, result.finish_reason.clone());
+                result.content = accumulated.lock().unwrap().content.clone();
+                result
+            }
+        });
+
+    Ok(join_all(sources).await)
+}
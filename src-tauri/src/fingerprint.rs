@@ -0,0 +1,70 @@
+use std::collections::HashMap;
+use std::sync::Mutex;
+
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+use tauri::{AppHandle, Emitter, Manager};
+
+use crate::messages::canonical_json;
+
+// 缓存条目上限，超过后整体清空重新记录
+const MAX_ENTRIES: usize = 1024;
+
+// 请求哈希 -> 上次返回的 system_fingerprint，只在内存中保留
+#[derive(Default)]
+pub struct FingerprintCache(Mutex<HashMap<String, String>>);
+
+// 相同请求、相同 seed 下服务端后端变了，输出不再保证可复现
+#[derive(Debug, Clone, Serialize)]
+pub struct ReproducibilityWarning {
+    pub request_hash: String,
+    pub previous_fingerprint: String,
+    pub system_fingerprint: String,
+}
+
+// 流式与否不影响生成结果，计算哈希时去掉
+fn request_hash(request_body: &serde_json::Value) -> String {
+    let mut body = request_body.clone();
+    if let Some(fields) = body.as_object_mut() {
+        fields.remove("stream");
+        fields.remove("stream_options");
+    }
+    let digest = Sha256::digest(canonical_json(&body).as_bytes());
+    digest.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+// 仅对带 seed 的请求记录指纹；与上次记录不同时发送 reproducibility-warning 事件
+pub fn check(
+    app_handle: &AppHandle,
+    request_body: &serde_json::Value,
+    system_fingerprint: Option<&str>,
+) -> Option<ReproducibilityWarning> {
+    request_body.get("seed")?;
+    let system_fingerprint = system_fingerprint.filter(|f| !f.is_empty())?;
+    let request_hash = request_hash(request_body);
+
+    let previous = {
+        let cache = app_handle.state::<FingerprintCache>();
+        let mut cache = cache.0.lock().unwrap();
+        if cache.len() >= MAX_ENTRIES && !cache.contains_key(&request_hash) {
+            cache.clear();
+        }
+        cache.insert(request_hash.clone(), system_fingerprint.to_string())
+    }?;
+    if previous == system_fingerprint {
+        return None;
+    }
+
+    let warning = ReproducibilityWarning {
+        request_hash,
+        previous_fingerprint: previous,
+        system_fingerprint: system_fingerprint.to_string(),
+    };
+    log::warn!(
+        "system_fingerprint changed from {} to {} for a seeded request",
+        warning.previous_fingerprint,
+        warning.system_fingerprint
+    );
+    let _ = app_handle.emit("reproducibility-warning", &warning);
+    Some(warning)
+}
@@ -0,0 +1,111 @@
+use std::time::{Duration, Instant};
+
+use serde::{Deserialize, Serialize};
+use tauri::{Emitter, Manager};
+
+use crate::client::{self, HttpClient};
+
+// 图片生成通常需要数十秒，超时给得宽松一些
+const GENERATION_TIMEOUT: Duration = Duration::from_secs(300);
+const PROGRESS_INTERVAL: Duration = Duration::from_secs(2);
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ImageGenerationStatus {
+    pub status: String,
+    pub elapsed_ms: u64,
+}
+
+#[derive(Debug, Deserialize)]
+struct ImagesResponse {
+    data: Vec<ImageData>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ImageData {
+    url: Option<String>,
+    b64_json: Option<String>,
+}
+
+// 调用 /images/generations 生成图片。服务端按 response_format 返回 URL 或 base64，
+// 后者统一转成 data URL，前端都可以直接用作 <img> 的 src
+#[tauri::command]
+#[allow(clippy::too_many_arguments)]
+pub async fn generate_image(
+    base_url: String,
+    api_key: String,
+    model: String,
+    prompt: String,
+    size: String,
+    n: u32,
+    response_format: Option<String>,
+    app_handle: tauri::AppHandle,
+) -> Result<Vec<String>, String> {
+    let url = format!("{}/images/generations", base_url);
+    let client = app_handle.state::<HttpClient>().client_for(&url)?;
+
+    let mut body = serde_json::json!({
+        "model": model,
+        "prompt": prompt,
+        "size": size,
+        "n": n,
+    });
+    // "url" 或 "b64_json"，不传时由服务端决定
+    if let Some(format) = response_format {
+        body["response_format"] = serde_json::Value::String(format);
+    }
+    let request = client
+        .post(&url)
+        .header("Content-Type", "application/json")
+        .header("Authorization", format!("Bearer {}", api_key))
+        .timeout(GENERATION_TIMEOUT)
+        .json(&body)
+        .send();
+    tokio::pin!(request);
+
+    // 等待期间定期发送 pending 状态，前端据此显示进度
+    let started = Instant::now();
+    let mut ticker = tokio::time::interval(PROGRESS_INTERVAL);
+    let response = loop {
+        tokio::select! {
+            response = &mut request => break response,
+            _ = ticker.tick() => {
+                let _ = app_handle.emit(
+                    "image-generation-status",
+                    ImageGenerationStatus {
+                        status: "pending".to_string(),
+                        elapsed_ms: started.elapsed().as_millis() as u64,
+                    },
+                );
+            }
+        }
+    }
+    .map_err(|e| format!("Failed to send request: {}", e))?;
+
+    if !response.status().is_success() {
+        return Err(client::api_error(response).await);
+    }
+
+    let parsed = response
+        .json::<ImagesResponse>()
+        .await
+        .map_err(|e| format!("Failed to parse response: {}", e))?;
+    let _ = app_handle.emit(
+        "image-generation-status",
+        ImageGenerationStatus {
+            status: "done".to_string(),
+            elapsed_ms: started.elapsed().as_millis() as u64,
+        },
+    );
+
+    Ok(parsed
+        .data
+        .into_iter()
+        .filter_map(|image| {
+            image.url.or_else(|| {
+                image
+                    .b64_json
+                    .map(|b64| format!("data:image/png;base64,{}", b64))
+            })
+        })
+        .collect())
+}
@@ -1,20 +1,83 @@
+use std::collections::HashMap;
+
 use futures_util::stream::StreamExt;
 use serde::{Deserialize, Serialize};
-use tauri::Emitter;
+use tauri::{Emitter, Manager};
+
+mod attachments;
+mod audio;
+mod autosave;
+mod calibration;
+mod capabilities;
+mod cassette;
+mod citations;
+mod client;
+mod codeblock;
+mod compress;
+mod config;
+mod diagnostics;
+mod embeddings;
+mod emit;
+mod ensemble;
+mod fingerprint;
+mod images;
+mod injection;
+mod lint;
+mod messages;
+mod model_alias;
+mod model_fit;
+mod moderation;
+mod output_limits;
+mod partial_json;
+mod pricing;
+mod profiles;
+mod provider;
+mod rate_limit;
+mod redact;
+mod scheduler;
+mod schema;
+mod scrollback;
+mod sse;
+mod sse_server;
+mod storage;
+mod streams;
+mod sweep;
+mod templates;
+mod think;
+mod throughput;
+mod tool_calls;
+mod transform;
+
+use capabilities::CapabilityCache;
+use client::{ClientConfig, HttpClient};
+use provider::{Provider, ThinkingConfig};
+use streams::StreamRegistry;
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct ChatRequest {
     pub model: String,
     pub messages: Vec<Message>,
+    #[serde(default)]
     pub stream: bool,
-    #[serde(skip_serializing_if = "Option::is_none")]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
     pub thinking: Option<Thinking>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_tokens: Option<u32>,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Message {
     pub role: String,
     pub content: serde_json::Value, // 支持字符串或数组（多模态）
+    // 置顶的消息在裁剪上下文时总是保留；只在本地使用，不会发给服务端
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub pinned: Option<bool>,
+}
+
+impl Message {
+    pub fn is_pinned(&self) -> bool {
+        self.pinned == Some(true)
+    }
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -30,6 +93,80 @@ pub struct ChatResponse {
     pub created: u64,
     pub model: String,
     pub choices: Vec<Choice>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub usage: Option<Usage>,
+    // 来自响应头而非响应体
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub rate_limit: Option<rate_limit::RateLimitStatus>,
+    // 各服务的引用格式不同，由 citations::extract 从原始响应中提取
+    #[serde(skip_deserializing, skip_serializing_if = "Option::is_none")]
+    pub citations: Option<Vec<citations::Citation>>,
+    // 服务端的请求 id（优先取响应头），联系服务商排查时使用
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub request_id: Option<String>,
+    // 服务端后端配置的标识，配合 seed 判断输出是否可复现
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub system_fingerprint: Option<String>,
+    #[serde(skip_deserializing, skip_serializing_if = "Option::is_none")]
+    pub reproducibility_warning: Option<fingerprint::ReproducibilityWarning>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Usage {
+    #[serde(default)]
+    pub prompt_tokens: u32,
+    #[serde(default)]
+    pub completion_tokens: u32,
+    #[serde(default)]
+    pub total_tokens: u32,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub completion_tokens_details: Option<CompletionTokensDetails>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub prompt_tokens_details: Option<PromptTokensDetails>,
+    // 推理模型的思考 token 数和命中缓存的提示词 token 数，计费方式与普通 token 不同。
+    // 由 fill_details 从上面两个 details 中提取
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub reasoning_tokens: Option<u32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cached_tokens: Option<u32>,
+    // DeepSeek 把缓存命中数放在顶层
+    #[serde(default, skip_serializing)]
+    prompt_cache_hit_tokens: Option<u32>,
+}
+
+impl Usage {
+    fn fill_details(&mut self) {
+        if self.reasoning_tokens.is_none() {
+            self.reasoning_tokens = self
+                .completion_tokens_details
+                .as_ref()
+                .and_then(|details| details.reasoning_tokens);
+        }
+        if self.cached_tokens.is_none() {
+            self.cached_tokens = self
+                .prompt_tokens_details
+                .as_ref()
+                .and_then(|details| details.cached_tokens)
+                .or(self.prompt_cache_hit_tokens);
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct CompletionTokensDetails {
+    // 使用 prediction 时命中 / 未命中的预测 token 数
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub accepted_prediction_tokens: Option<u32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub rejected_prediction_tokens: Option<u32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub reasoning_tokens: Option<u32>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct PromptTokensDetails {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cached_tokens: Option<u32>,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -37,23 +174,71 @@ pub struct Choice {
     pub index: u32,
     pub message: ResponseMessage,
     pub finish_reason: Option<String>,
+    // 服务端返回的内容安全评级（Gemini 的 safety_ratings、Azure 的 content_filter_results）
+    #[serde(
+        default,
+        alias = "content_filter_results",
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub safety_ratings: Option<serde_json::Value>,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct ResponseMessage {
     pub role: String,
+    // 仅调用工具的回复中 content 可能为 null
+    #[serde(default, deserialize_with = "null_as_default")]
     pub content: String,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub reasoning_content: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tool_calls: Option<Vec<ToolCall>>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ToolCall {
+    #[serde(default)]
+    pub id: String,
+    #[serde(rename = "type", default)]
+    pub call_type: String,
+    pub function: FunctionCall,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FunctionCall {
+    #[serde(default)]
+    pub name: String,
+    #[serde(default)]
+    pub arguments: String,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct StreamChunk {
+    #[serde(default)]
     pub id: String,
+    #[serde(default)]
     pub object: String,
+    #[serde(default)]
     pub created: u64,
+    #[serde(default)]
     pub model: String,
+    #[serde(default)]
     pub choices: Vec<StreamChoice>,
+    // 开启 stream_options.include_usage 时最后一个分片携带用量
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub usage: Option<Usage>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub system_fingerprint: Option<String>,
+}
+
+impl StreamChunk {
+    // 设置了 only_index 时只取该候选，否则取第一个
+    fn choice(&self, only_index: Option<u32>) -> Option<&StreamChoice> {
+        match only_index {
+            Some(index) => self.choices.iter().find(|c| c.index == index),
+            None => self.choices.first(),
+        }
+    }
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -69,157 +254,1441 @@ pub struct StreamDelta {
     pub content: Option<String>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub reasoning_content: Option<String>,
+    #[serde(default, skip_serializing)]
+    pub tool_calls: Option<Vec<tool_calls::WireToolCallDelta>>,
 }
 
-#[derive(Debug, Clone, Serialize)]
+#[derive(Debug, Clone, Default, Serialize)]
 pub struct StreamData {
+    // 同一个流内从 0 开始递增，前端据此检测乱序和丢失
+    pub seq: u64,
+    // 仅在消息的第一个分片中出现，标明说话的角色
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub role: Option<String>,
     pub content: Option<String>,
     pub reasoning_content: Option<String>,
+    // 工具调用参数的增量，多个调用以 index 区分
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tool_call_deltas: Option<Vec<tool_calls::ToolCallDelta>>,
     pub done: bool,
+    // stop / length / content_filter 等，便于前端提示回复被截断或拦截
+    pub finish_reason: Option<String>,
 }
 
-#[tauri::command]
-async fn chat_completions(
-    base_url: String,
-    api_key: String,
-    model: String,
-    messages: Vec<Message>,
+impl StreamData {
+    pub fn finished(finish_reason: Option<String>) -> Self {
+        StreamData {
+            done: true,
+            finish_reason,
+            ..Default::default()
+        }
+    }
+}
+
+impl From<think::Segment> for StreamData {
+    fn from(segment: think::Segment) -> Self {
+        match segment {
+            think::Segment::Content(text) => StreamData {
+                content: Some(text),
+                ..Default::default()
+            },
+            think::Segment::Reasoning(text) => StreamData {
+                reasoning_content: Some(text),
+                ..Default::default()
+            },
+        }
+    }
+}
+
+// 流式命令结束时返回的汇总信息，前端 await 即可拿到最终状态
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct StreamResult {
+    pub finish_reason: Option<String>,
+    pub total_tokens: Option<u32>,
+    // 服务端返回的完整用量（流式需开启 include_usage），同时通过 stream-usage 事件发送
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub usage: Option<Usage>,
+    // 实际提供服务的模型（网关可能把别名路由到其他模型）
+    pub model: Option<String>,
+    pub rate_limit: Option<rate_limit::RateLimitStatus>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub request_id: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub system_fingerprint: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub reproducibility_warning: Option<fingerprint::ReproducibilityWarning>,
+    // 回复内容的字符数
+    pub content_length: usize,
+    // 开启 accumulate 时返回完整的回复内容
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub content: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub reasoning_content: Option<String>,
+    // 按 index 拼接完成的工具调用
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tool_calls: Option<Vec<ToolCall>>,
+}
+
+// 收到响应头后立即通过 stream-meta 发送，请求失败时前端也能拿到请求 id
+#[derive(Debug, Clone, Serialize)]
+pub struct StreamMeta {
+    pub stream_id: String,
+    pub request_id: Option<String>,
+}
+
+// 未识别的 SSE 事件原样转发给前端，便于对接非标准服务
+#[derive(Debug, Clone, Serialize)]
+pub struct CustomStreamEvent {
+    pub event: String,
+    pub data: String,
+}
+
+// 聊天命令的可选参数，前端未传的字段保持默认行为
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ChatOptions {
+    // 流式请求的 id，用于取消
+    pub stream_id: Option<String>,
+    pub tools: Option<Vec<serde_json::Value>>,
+    // 为 false 时要求每次最多调用一个工具；未传工具时不发送（部分服务会拒绝）
+    pub parallel_tool_calls: Option<bool>,
+    // "auto" / "none" / "required"，或 { "type": "function", "function": { "name": ... } }
+    // 指定必须调用的工具；只能和 tools 一起使用
+    pub tool_choice: Option<serde_json::Value>,
+    // 返回空内容（且没有工具调用）时自动重试一次
+    pub retry_on_empty: bool,
+    // token id（字符串形式）到偏置值的映射，取值范围 [-100, 100]
+    pub logit_bias: Option<HashMap<String, f32>>,
+    // 如 { "type": "json_object" }
+    pub response_format: Option<serde_json::Value>,
+    // 流式时把已累积的内容按不完整 JSON 尽力解析，通过 stream-partial-json 事件发送
+    pub partial_json: bool,
+    // 发送前合并相邻的同角色文本消息（部分服务不接受连续同角色消息）
+    pub merge_consecutive_roles: bool,
+    // 发送前把开头的多条 system 消息合并为一条
+    pub coalesce_system_messages: bool,
+    // 流式时每累积 N 个增量或每隔 M 毫秒合并发送一次，降低 IPC 开销
+    pub emit_batch_size: Option<usize>,
+    pub emit_interval_ms: Option<u64>,
+    // 前端需用 ack_stream_chunk 确认处理过的事件；未确认的超过 N 条时合并后续增量（内容不丢失），
+    // 并发送 stream-backpressure 事件。不设置时直接 emit
+    pub event_queue_capacity: Option<usize>,
+    // 预期输出（OpenAI predicted outputs），用于改写类任务加速生成
+    pub prediction: Option<String>,
+    // 流式时请求服务端在最后一个分片返回用量（stream_options.include_usage）
+    pub include_usage: bool,
+    // 流式数据额外以 JSON Lines 写入该文件
+    pub output_file: Option<String>,
+    // 在 Rust 侧累积完整回复，并在 StreamResult 中返回
+    pub accumulate: bool,
+    // 响应体（解压后）的最大字节数，超出即中止，防止异常服务耗尽内存
+    pub max_response_bytes: Option<usize>,
+    // 把 content 中的 <think> / <thinking> 块移到 reasoning_content
+    pub extract_think_tags: bool,
+    // 让服务端一次生成 n 个候选回复
+    pub n: Option<u32>,
+    // n > 1 时流式只转发该序号的候选，其余丢弃以减少 IPC
+    pub stream_only_index: Option<u32>,
+    pub max_tokens: Option<u32>,
+    // 不传时根据 base_url 推断
+    pub provider: Option<Provider>,
+    // 内容安全阈值设置，按服务商放到请求体中对应的位置
+    pub safety_settings: Option<serde_json::Value>,
+    // 请求成功后自动把本轮消息和回复保存到该会话（写入有防抖）
+    pub conversation_id: Option<String>,
+    pub autosave: bool,
+    // 会话锁定了模型 / 服务商时，仍然使用本次传入的模型
+    pub override_lock: bool,
+    // 以该文本作为助手回复的开头（prefill），服务端从这里接着生成。
+    // 返回的内容只包含续写部分，不含 prefill 本身
+    pub prefill: Option<String>,
+    // 使用已保存的配置补全 base_url / model / provider
+    pub profile: Option<String>,
+    #[serde(skip)]
+    pub profile_params: serde_json::Map<String, serde_json::Value>,
+    // 深度思考的 token 预算（Anthropic extended thinking 必需，其余服务映射为 effort）
+    pub thinking_budget: Option<u32>,
+    // 绝对截止时间（Unix 毫秒），多个串联操作可以共用同一个截止时间
+    pub deadline_unix_ms: Option<u64>,
+    pub temperature: Option<f32>,
+    pub top_p: Option<f32>,
+    pub seed: Option<u64>,
+    // 可复现输出的快捷开关：temperature 0、top_p 1 加固定 seed（可用 seed 指定），
+    // 开启时覆盖显式传入的 temperature / top_p；关闭时不改动调用方的参数
+    pub deterministic: bool,
+    // 流式正文的实时变换：none / strip_markdown / mask_profanity
+    pub transform: Option<String>,
+    // 去掉回复中的控制字符和零宽空格等不可见字符（保留换行和制表符）
+    pub sanitize_output: bool,
+    // redact_messages 返回的占位符映射，回复中的占位符会还原为原文
+    pub restore_redactions: Option<HashMap<String, String>>,
+    // 流式正文只在单词边界处发送，完成事件前输出剩余部分
+    pub align_words: bool,
+    // 非流式回复因 length 截断时，把已生成部分作为助手消息追加并请求续写，
+    // 直到自然结束或达到 max_continuations 次（默认 3）
+    pub auto_continue: bool,
+    pub max_continuations: Option<u32>,
+    // 最后深度合并进请求体的任意字段，用于本程序尚未支持的服务商参数。
+    // 与内置字段冲突时以这里为准；对象逐层合并，值为 null 表示删除该字段
+    pub extra_body: Option<serde_json::Value>,
+    // 流式时识别正文中的围栏代码块，进入 / 离开时发送 code-block-start / code-block-end 事件
+    pub detect_code_blocks: bool,
+    // 流式正文额外保存在 Rust 侧（最多 scrollback_max_chars 个字符），
+    // 前端可用 get_stream_content 按偏移量分段读取，不必自己保留全文
+    pub scrollback: bool,
+    pub scrollback_max_chars: Option<usize>,
+    // 调试用：除解析后的 stream-chunk 外，每一行原始 SSE（含空行和注释行）都通过 raw-sse-line
+    // 事件原样发送。事件量很大，只应在排查单个流时开启
+    pub raw_sse: bool,
+}
+
+// deterministic 未指定 seed 时使用的固定值
+const DETERMINISTIC_SEED: u64 = 42;
+
+impl ChatOptions {
+    // 实际发送的 (temperature, top_p, seed)
+    fn sampling(&self) -> (Option<f32>, Option<f32>, Option<u64>) {
+        if self.deterministic {
+            (
+                Some(0.0),
+                Some(1.0),
+                Some(self.seed.unwrap_or(DETERMINISTIC_SEED)),
+            )
+        } else {
+            (self.temperature, self.top_p, self.seed)
+        }
+    }
+
+    fn resolve(
+        options: Option<ChatOptions>,
+        base_url: &mut String,
+        model: &mut String,
+        app_handle: &tauri::AppHandle,
+    ) -> Result<ChatOptions, String> {
+        let mut options = options.unwrap_or_default();
+        profiles::apply(app_handle, base_url, model, &mut options)?;
+        config::apply(app_handle, base_url, model, &mut options);
+        let provider = *options
+            .provider
+            .get_or_insert_with(|| Provider::detect(base_url));
+        if let Some(id) = options
+            .conversation_id
+            .as_ref()
+            .filter(|_| !options.override_lock)
+        {
+            storage::check_model_lock(app_handle, id, model, provider)?;
+        }
+        Ok(options)
+    }
+
+    fn deadline(&self) -> Option<tokio::time::Instant> {
+        let deadline = self.deadline_unix_ms?;
+        let remaining = deadline.saturating_sub(storage::now_millis());
+        Some(tokio::time::Instant::now() + std::time::Duration::from_millis(remaining))
+    }
+}
+
+const DEADLINE_EXCEEDED: &str = "Deadline exceeded";
+// 开启 autosave 的流式请求每隔这么久把已生成的部分写入会话
+const AUTOSAVE_CHECKPOINT_INTERVAL: std::time::Duration = std::time::Duration::from_secs(2);
+
+async fn with_deadline<F: std::future::Future>(
+    deadline: Option<tokio::time::Instant>,
+    future: F,
+) -> Result<F::Output, String> {
+    match deadline {
+        Some(deadline) => tokio::time::timeout_at(deadline, future)
+            .await
+            .map_err(|_| DEADLINE_EXCEEDED.to_string()),
+        None => Ok(future.await),
+    }
+}
+
+fn null_as_default<'de, D, T>(deserializer: D) -> Result<T, D::Error>
+where
+    D: serde::Deserializer<'de>,
+    T: Default + Deserialize<'de>,
+{
+    Ok(Option::<T>::deserialize(deserializer)?.unwrap_or_default())
+}
+
+// 以助手消息结尾即为 prefill。OpenAI 系接口原样接受；
+// Anthropic 要求最后一条助手消息不能以空白结尾，否则直接报错
+fn prefill_message(prefill: &str, provider: Provider) -> Message {
+    let text = match provider {
+        Provider::Anthropic => prefill.trim_end(),
+        _ => prefill,
+    };
+    Message {
+        role: "assistant".to_string(),
+        content: serde_json::Value::String(text.to_string()),
+        pinned: None,
+    }
+}
+
+fn build_request_body(
+    model: &str,
+    messages: &[Message],
+    stream: bool,
     enable_deep_thinking: bool,
-) -> Result<ChatResponse, String> {
-    let url = format!("{}/chat/completions", base_url);
+    options: &ChatOptions,
+) -> Result<serde_json::Value, String> {
+    let merged;
+    let messages = if options.merge_consecutive_roles {
+        merged = messages::merge_consecutive_roles(messages.to_vec());
+        &merged[..]
+    } else {
+        messages
+    };
+
+    // Anthropic 的合并结果放到顶层 system 字段，其他服务仍是开头的一条 system 消息
+    let coalesced;
+    let mut system = None;
+    let messages = if options.coalesce_system_messages {
+        let (text, rest) = messages::coalesce_system_messages(
+            messages.to_vec(),
+            options.provider.unwrap_or_default(),
+        );
+        system = text;
+        coalesced = rest;
+        &coalesced[..]
+    } else {
+        messages
+    };
+
+    let prefilled;
+    let messages = match options.prefill.as_deref().filter(|p| !p.is_empty()) {
+        Some(prefill) => {
+            let mut with_prefill = messages.to_vec();
+            with_prefill.push(prefill_message(
+                prefill,
+                options.provider.unwrap_or_default(),
+            ));
+            prefilled = with_prefill;
+            &prefilled[..]
+        }
+        None => messages,
+    };
 
-    let client = reqwest::Client::new();
     let mut request_body = serde_json::json!({
         "model": model,
         "messages": messages,
-        "stream": false,
+        "stream": stream,
     });
+    if let Some(system) = system {
+        request_body["system"] = serde_json::json!(system);
+    }
+    for message in request_body["messages"]
+        .as_array_mut()
+        .into_iter()
+        .flatten()
+    {
+        if let Some(fields) = message.as_object_mut() {
+            fields.remove("pinned");
+        }
+    }
 
-    // 添加 thinking 参数
-    if enable_deep_thinking {
-        request_body["thinking"] = serde_json::json!({
-            "type": "enabled"
-        });
-    } else {
-        request_body["thinking"] = serde_json::json!({
-            "type": "disabled"
+    // 添加 thinking 参数，字段形式因服务商而异
+    ThinkingConfig::new(enable_deep_thinking, options.thinking_budget)
+        .apply(options.provider.unwrap_or_default(), &mut request_body)?;
+
+    if let Some(tools) = &options.tools {
+        request_body["tools"] = serde_json::json!(tools);
+        if let Some(parallel) = options.parallel_tool_calls.filter(|_| !tools.is_empty()) {
+            request_body["parallel_tool_calls"] = serde_json::json!(parallel);
+        }
+    }
+
+    if let Some(tool_choice) = &options.tool_choice {
+        validate_tool_choice(tool_choice, options.tools.as_deref().unwrap_or_default())?;
+        request_body["tool_choice"] = tool_choice.clone();
+    }
+
+    if let Some(logit_bias) = &options.logit_bias {
+        for (token, bias) in logit_bias {
+            if token.parse::<u32>().is_err() {
+                return Err(format!("Invalid logit_bias token id: {}", token));
+            }
+            if !(-100.0..=100.0).contains(bias) {
+                return Err(format!(
+                    "logit_bias for token {} must be between -100 and 100, got {}",
+                    token, bias
+                ));
+            }
+        }
+        request_body["logit_bias"] = serde_json::json!(logit_bias);
+    }
+
+    if let Some(response_format) = &options.response_format {
+        request_body["response_format"] = response_format.clone();
+    }
+
+    if stream && options.include_usage {
+        request_body["stream_options"] = serde_json::json!({ "include_usage": true });
+    }
+
+    let (temperature, top_p, seed) = options.sampling();
+    if let Some(temperature) = temperature {
+        request_body["temperature"] = serde_json::json!(temperature);
+    }
+    if let Some(top_p) = top_p {
+        request_body["top_p"] = serde_json::json!(top_p);
+    }
+    if let Some(seed) = seed {
+        request_body["seed"] = serde_json::json!(seed);
+    }
+
+    if let Some(max_tokens) = options.max_tokens {
+        request_body["max_tokens"] = serde_json::json!(max_tokens);
+    }
+
+    if let Some(n) = options.n {
+        request_body["n"] = serde_json::json!(n);
+    }
+
+    if let Some(safety_settings) = &options.safety_settings {
+        match options.provider.unwrap_or_default() {
+            Provider::Gemini | Provider::Generic => {
+                request_body["safety_settings"] = safety_settings.clone();
+            }
+            // OpenAI / Azure 的内容过滤在服务端配置，请求体中传入未知字段会被拒绝
+            provider => log::warn!(
+                "safety_settings is not supported by {:?}, ignoring",
+                provider
+            ),
+        }
+    }
+
+    if let Some(prediction) = &options.prediction {
+        request_body["prediction"] = serde_json::json!({
+            "type": "content",
+            "content": prediction,
         });
     }
 
+    // 配置档中的参数只补充请求体里没有的字段
+    for (key, value) in &options.profile_params {
+        if request_body.get(key).is_none() {
+            request_body[key] = value.clone();
+        }
+    }
+
+    if let Some(extra_body) = &options.extra_body {
+        if !extra_body.is_object() {
+            return Err("extra_body must be a JSON object".to_string());
+        }
+        deep_merge(&mut request_body, extra_body);
+    }
+
+    Ok(request_body)
+}
+
+fn validate_tool_choice(
+    tool_choice: &serde_json::Value,
+    tools: &[serde_json::Value],
+) -> Result<(), String> {
+    if tools.is_empty() {
+        return Err("tool_choice requires tools".to_string());
+    }
+    match tool_choice {
+        serde_json::Value::String(mode)
+            if ["auto", "none", "required"].contains(&mode.as_str()) =>
+        {
+            Ok(())
+        }
+        serde_json::Value::Object(_) if tool_choice["type"] == "function" => {
+            let name = tool_choice["function"]["name"]
+                .as_str()
+                .ok_or_else(|| "tool_choice.function.name is required".to_string())?;
+            if tools.iter().any(|tool| tool["function"]["name"] == name) {
+                Ok(())
+            } else {
+                Err(format!("tool_choice names an unknown tool: {}", name))
+            }
+        }
+        other => Err(format!("Invalid tool_choice: {}", other)),
+    }
+}
+
+fn deep_merge(target: &mut serde_json::Value, patch: &serde_json::Value) {
+    match (target.as_object_mut(), patch.as_object()) {
+        (Some(target), Some(patch)) => {
+            for (key, value) in patch {
+                if value.is_null() {
+                    target.remove(key);
+                } else {
+                    deep_merge(
+                        target.entry(key.clone()).or_insert(serde_json::Value::Null),
+                        value,
+                    );
+                }
+            }
+        }
+        _ => *target = patch.clone(),
+    }
+}
+
+async fn send_chat_request(
+    client: &reqwest::Client,
+    url: &str,
+    api_key: &str,
+    request_body: &serde_json::Value,
+    options: &ChatOptions,
+    app_handle: &tauri::AppHandle,
+) -> Result<ChatResponse, String> {
     let request_builder = client
-        .post(&url)
+        .post(url)
         .header("Content-Type", "application/json")
         .header("Authorization", format!("Bearer {}", api_key))
-        .json(&request_body);
+        .json(request_body);
 
-    let response = request_builder
-        .send()
-        .await
-        .map_err(|e| format!("Failed to send request: {}", e))?;
+    rate_limit::throttle(app_handle, url).await;
+    let response = cassette::send(app_handle, url, request_body, request_builder).await?;
 
+    rate_limit::observe(app_handle, url, response.status(), response.headers());
+    let rate_limit = emit_rate_limit(app_handle, &response);
     if !response.status().is_success() {
-        let error_text = response
-            .text()
-            .await
-            .unwrap_or_else(|_| "Unknown error".to_string());
-        return Err(format!("API Error: {}", error_text));
+        return Err(client::api_error(response).await);
     }
 
-    let result = response
-        .json::<ChatResponse>()
-        .await
+    let request_id = client::request_id(response.headers());
+    // 大的非流式响应下载较慢，边读边通过 response-progress 报告进度
+    let body = client::read_body_with_progress(response, options.max_response_bytes, |progress| {
+        let _ = app_handle.emit("response-progress", progress);
+    })
+    .await?;
+    let mut response = parse_chat_response(&body, options)?;
+    response.rate_limit = rate_limit;
+    if request_id.is_some() {
+        response.request_id = request_id;
+    }
+    response.reproducibility_warning = fingerprint::check(
+        app_handle,
+        request_body,
+        response.system_fingerprint.as_deref(),
+    );
+    Ok(response)
+}
+
+// 每个响应（包括错误响应）都带着最新的限流额度，取到就通过 rate-limit-status 事件发送
+fn emit_rate_limit(
+    app_handle: &tauri::AppHandle,
+    response: &reqwest::Response,
+) -> Option<rate_limit::RateLimitStatus> {
+    let status = rate_limit::RateLimitStatus::from_headers(response.headers())?;
+    let _ = app_handle.emit("rate-limit-status", &status);
+    Some(status)
+}
+
+fn parse_chat_response(body: &[u8], options: &ChatOptions) -> Result<ChatResponse, String> {
+    let raw = serde_json::from_slice::<serde_json::Value>(body)
         .map_err(|e| format!("Failed to parse response: {}", e))?;
+    let mut response =
+        ChatResponse::deserialize(&raw).map_err(|e| format!("Failed to parse response: {}", e))?;
+    response.citations = citations::extract(&raw);
+    if let Some(usage) = &mut response.usage {
+        usage.fill_details();
+    }
 
-    Ok(result)
+    if let Some(mapping) = &options.restore_redactions {
+        for choice in &mut response.choices {
+            choice.message.content = redact::restore(&choice.message.content, mapping);
+        }
+    }
+
+    if options.sanitize_output {
+        for choice in &mut response.choices {
+            choice.message.content = transform::sanitize(&choice.message.content);
+            if let Some(reasoning) = &mut choice.message.reasoning_content {
+                *reasoning = transform::sanitize(reasoning);
+            }
+        }
+    }
+
+    if options.extract_think_tags {
+        for choice in &mut response.choices {
+            let split = think::extract_think_tags(&choice.message.content);
+            if !split.reasoning.is_empty() {
+                let mut reasoning = choice.message.reasoning_content.take().unwrap_or_default();
+                reasoning.push_str(&split.reasoning);
+                choice.message.reasoning_content = Some(reasoning);
+            }
+            choice.message.content = split.content;
+        }
+    }
+    Ok(response)
+}
+
+// 流式请求收到非 SSE 响应时内容仍要经过 Sinks，还原脱敏和清理由 Sinks 的变换链负责，
+// 这里只解析出原始内容，避免两步各执行两次
+fn parse_fallback_response(body: &[u8], options: &ChatOptions) -> Result<ChatResponse, String> {
+    let options = ChatOptions {
+        restore_redactions: None,
+        sanitize_output: false,
+        ..options.clone()
+    };
+    parse_chat_response(body, &options)
+}
+
+const DEFAULT_MAX_CONTINUATIONS: u32 = 3;
+const CONTINUE_PROMPT: &str =
+    "Continue exactly where your previous answer stopped. Do not repeat anything or add a preamble.";
+
+// 续写请求以已生成的内容作为助手消息结尾，不能再追加 prefill
+fn continuation_body(
+    model: &str,
+    messages: &[Message],
+    enable_deep_thinking: bool,
+    options: &ChatOptions,
+) -> Result<serde_json::Value, String> {
+    let options = ChatOptions {
+        prefill: None,
+        ..options.clone()
+    };
+    build_request_body(model, messages, false, enable_deep_thinking, &options)
+}
+
+// 第一个候选因 length 截断时反复请求续写（最多 cap 次），把续写内容拼接到该候选上，用量累加。
+// send 负责发送续写请求的消息列表
+async fn continue_truncated<F, Fut>(
+    mut response: ChatResponse,
+    messages: &[Message],
+    cap: u32,
+    mut send: F,
+) -> Result<ChatResponse, String>
+where
+    F: FnMut(Vec<Message>) -> Fut,
+    Fut: std::future::Future<Output = Result<ChatResponse, String>>,
+{
+    for _ in 0..cap {
+        let Some(choice) = response.choices.first() else {
+            break;
+        };
+        if choice.finish_reason.as_deref() != Some("length") || choice.message.tool_calls.is_some()
+        {
+            break;
+        }
+        let mut request = messages.to_vec();
+        request.push(Message {
+            role: "assistant".to_string(),
+            content: serde_json::Value::String(choice.message.content.clone()),
+            pinned: None,
+        });
+        request.push(Message {
+            role: "user".to_string(),
+            content: serde_json::Value::String(CONTINUE_PROMPT.to_string()),
+            pinned: None,
+        });
+        let next = send(request).await?;
+        let Some(next_choice) = next.choices.into_iter().next() else {
+            break;
+        };
+        let choice = &mut response.choices[0];
+        choice
+            .message
+            .content
+            .push_str(&next_choice.message.content);
+        choice.finish_reason = next_choice.finish_reason;
+        if let (Some(usage), Some(more)) = (&mut response.usage, next.usage) {
+            usage.prompt_tokens += more.prompt_tokens;
+            usage.completion_tokens += more.completion_tokens;
+            usage.total_tokens += more.total_tokens;
+            let add = |a: Option<u32>, b: Option<u32>| match (a, b) {
+                (None, None) => None,
+                (a, b) => Some(a.unwrap_or(0) + b.unwrap_or(0)),
+            };
+            usage.reasoning_tokens = add(usage.reasoning_tokens, more.reasoning_tokens);
+            usage.cached_tokens = add(usage.cached_tokens, more.cached_tokens);
+        }
+    }
+    Ok(response)
+}
+
+// 内容为空且没有工具调用才视为异常的空回复；只调用工具的回复是正常的
+fn is_empty_response(response: &ChatResponse) -> bool {
+    response.choices.iter().all(|choice| {
+        choice
+            .message
+            .tool_calls
+            .as_ref()
+            .is_none_or(|calls| calls.is_empty())
+            && choice.message.content.trim().is_empty()
+    })
 }
 
 #[tauri::command]
-async fn chat_completions_stream(
-    base_url: String,
+async fn chat_completions(
+    mut base_url: String,
     api_key: String,
-    model: String,
+    mut model: String,
     messages: Vec<Message>,
     enable_deep_thinking: bool,
+    options: Option<ChatOptions>,
     app_handle: tauri::AppHandle,
-) -> Result<(), String> {
+) -> Result<ChatResponse, String> {
+    let options = ChatOptions::resolve(options, &mut base_url, &mut model, &app_handle)?;
     let url = format!("{}/chat/completions", base_url);
 
-    let client = reqwest::Client::new();
-    let mut request_body = serde_json::json!({
-        "model": model,
-        "messages": messages,
-        "stream": true,
-    });
+    let client = app_handle.state::<HttpClient>().client_for(&url)?;
+    let resolved = attachments::resolve_attachments(messages.clone(), app_handle.clone())?;
+    let request_body =
+        build_request_body(&model, &resolved, false, enable_deep_thinking, &options)?;
 
-    // 添加 thinking 参数
-    if enable_deep_thinking {
-        request_body["thinking"] = serde_json::json!({
-            "type": "enabled"
-        });
-    } else {
-        request_body["thinking"] = serde_json::json!({
-            "type": "disabled"
-        });
+    let send = || {
+        send_chat_request(
+            &client,
+            &url,
+            &api_key,
+            &request_body,
+            &options,
+            &app_handle,
+        )
+    };
+    let result = with_deadline(options.deadline(), async {
+        let result = send().await?;
+        // 部分服务偶尔返回 200 但内容为空，按需重试一次
+        let result = if options.retry_on_empty && is_empty_response(&result) {
+            log::warn!("Empty response from {}, retrying once", url);
+            send().await?
+        } else {
+            result
+        };
+        if !options.auto_continue {
+            return Ok(result);
+        }
+        let (client, url, api_key, model, options, app_handle) =
+            (&client, &url, &api_key, &model, &options, &app_handle);
+        let cap = options
+            .max_continuations
+            .unwrap_or(DEFAULT_MAX_CONTINUATIONS);
+        continue_truncated(result, &resolved, cap, move |request| {
+            let body = continuation_body(model, &request, enable_deep_thinking, options);
+            async move {
+                send_chat_request(client, url, api_key, &body?, options, app_handle).await
+            }
+        })
+        .await
+    })
+    .await??;
+
+    if let Some(id) = options
+        .conversation_id
+        .as_ref()
+        .filter(|_| options.autosave)
+    {
+        let reply = result
+            .choices
+            .first()
+            .map(|choice| choice.message.content.as_str())
+            .unwrap_or_default();
+        autosave::schedule(&app_handle, id, &messages, reply);
     }
 
-    let request_builder = client
-        .post(&url)
+    Ok(result)
+}
+
+// 先还原脱敏占位符，再做用户选择的变换
+fn stream_transform(
+    options: &ChatOptions,
+) -> Result<Option<Box<dyn transform::StreamTransform>>, String> {
+    let mut transforms: Vec<Box<dyn transform::StreamTransform>> = Vec::new();
+    if let Some(mapping) = &options.restore_redactions {
+        transforms.push(Box::new(redact::RestoreRedactions::new(mapping.clone())));
+    }
+    transforms.extend(transform::by_name(options.transform.as_deref())?);
+    if options.align_words {
+        transforms.push(Box::<transform::WordBoundary>::default());
+    }
+    Ok(transform::chain(transforms))
+}
+
+// 一个分片中选中的 choice 转成要发送的数据。开启 think 标签提取时思考内容和正文
+// 依原始顺序拆成多条；角色和工具调用增量放在第一条，finish_reason 放在最后一条
+fn choice_pieces(
+    choice: &StreamChoice,
+    think_splitter: Option<&mut think::ThinkTagSplitter>,
+) -> Vec<StreamData> {
+    let mut pieces = match (think_splitter, &choice.delta.content) {
+        (Some(splitter), Some(text)) => {
+            let mut pieces: Vec<StreamData> = choice
+                .delta
+                .reasoning_content
+                .iter()
+                .map(|r| think::Segment::Reasoning(r.clone()))
+                .chain(splitter.feed(text))
+                .map(StreamData::from)
+                .collect();
+            if pieces.is_empty()
+                && (choice.finish_reason.is_some()
+                    || choice.delta.role.is_some()
+                    || choice.delta.tool_calls.is_some())
+            {
+                pieces.push(StreamData::default());
+            }
+            pieces
+        }
+        _ => vec![StreamData {
+            content: choice.delta.content.clone(),
+            reasoning_content: choice.delta.reasoning_content.clone(),
+            ..Default::default()
+        }],
+    };
+    if let Some(first) = pieces.first_mut() {
+        first.role = choice.delta.role.clone();
+        first.tool_call_deltas = choice
+            .delta
+            .tool_calls
+            .as_ref()
+            .map(|calls| calls.iter().map(tool_calls::ToolCallDelta::from).collect());
+    }
+    if let Some(last) = pieces.last_mut() {
+        last.finish_reason = choice.finish_reason.clone();
+    }
+    pieces
+}
+
+// 流的结束方式
+#[derive(Debug, PartialEq)]
+enum StreamEnd {
+    // 收到 [DONE] 或 Anthropic 的 message_stop
+    Done,
+    // 服务端直接断开连接，没有发送结束标记
+    Closed,
+    Cancelled,
+    Deadline,
+    // 超过 max_response_bytes
+    TooLarge(usize),
+    // 读取或解压响应时出错
+    Failed(String),
+}
+
+impl StreamEnd {
+    // 异常结束时返回的错误信息
+    fn error(&self) -> Option<String> {
+        match self {
+            StreamEnd::Done | StreamEnd::Closed | StreamEnd::Cancelled => None,
+            StreamEnd::Deadline => Some(DEADLINE_EXCEEDED.to_string()),
+            StreamEnd::TooLarge(limit) => Some(format!(
+                "Response exceeded the maximum size of {} bytes",
+                limit
+            )),
+            StreamEnd::Failed(e) => Some(e.clone()),
+        }
+    }
+}
+
+enum StreamStep {
+    // 新解析出的事件，以及开启 raw_sse 时的原始行
+    Events {
+        events: Vec<sse::SseEvent>,
+        raw_lines: Vec<String>,
+    },
+    // 到了缓冲区按时间阈值发送的时刻
+    Idle,
+    End(StreamEnd),
+}
+
+// 从字节流中读出 SSE 事件，同时处理取消、总时限和响应大小上限
+struct StreamReader<S> {
+    stream: S,
+    parser: sse::SseParser,
+    raw_lines: Option<sse::SseLineSplitter>,
+    token: tokio_util::sync::CancellationToken,
+    deadline: Option<tokio::time::Instant>,
+    max_bytes: Option<usize>,
+    received_bytes: usize,
+    closed: bool,
+}
+
+impl<S> StreamReader<S>
+where
+    S: futures_util::Stream<Item = Result<Vec<u8>, String>> + Unpin,
+{
+    fn new(
+        stream: S,
+        token: tokio_util::sync::CancellationToken,
+        deadline: Option<tokio::time::Instant>,
+        max_bytes: Option<usize>,
+        raw_sse: bool,
+    ) -> Self {
+        StreamReader {
+            stream,
+            parser: sse::SseParser::default(),
+            raw_lines: raw_sse.then(sse::SseLineSplitter::default),
+            token,
+            deadline,
+            max_bytes,
+            received_bytes: 0,
+            closed: false,
+        }
+    }
+
+    // flush_at 为缓冲区最晚的发送时刻，到时返回 Idle
+    async fn next(&mut self, flush_at: Option<tokio::time::Instant>) -> StreamStep {
+        if self.closed {
+            return StreamStep::End(StreamEnd::Closed);
+        }
+        let chunk = tokio::select! {
+            _ = self.token.cancelled() => return StreamStep::End(StreamEnd::Cancelled),
+            _ = tokio::time::sleep_until(self.deadline.unwrap_or_else(tokio::time::Instant::now)), if self.deadline.is_some() => {
+                return StreamStep::End(StreamEnd::Deadline);
+            }
+            _ = tokio::time::sleep_until(flush_at.unwrap_or_else(tokio::time::Instant::now)), if flush_at.is_some() => {
+                return StreamStep::Idle;
+            }
+            chunk = self.stream.next() => chunk,
+        };
+
+        match chunk {
+            Some(Ok(chunk)) => {
+                self.received_bytes += chunk.len();
+                if let Some(limit) = self.max_bytes.filter(|&l| self.received_bytes > l) {
+                    return StreamStep::End(StreamEnd::TooLarge(limit));
+                }
+                StreamStep::Events {
+                    raw_lines: self
+                        .raw_lines
+                        .as_mut()
+                        .map(|lines| lines.feed(&chunk))
+                        .unwrap_or_default(),
+                    events: self.parser.feed(&chunk),
+                }
+            }
+            Some(Err(e)) => StreamStep::End(StreamEnd::Failed(e)),
+            // 先处理缓冲区中最后一个未以空行结束的事件，下次调用再返回 Closed
+            None => {
+                self.closed = true;
+                StreamStep::Events {
+                    raw_lines: self
+                        .raw_lines
+                        .as_mut()
+                        .and_then(|lines| lines.finish())
+                        .into_iter()
+                        .collect(),
+                    events: self.parser.finish().into_iter().collect(),
+                }
+            }
+        }
+    }
+}
+
+fn earliest(
+    a: Option<tokio::time::Instant>,
+    b: Option<tokio::time::Instant>,
+) -> Option<tokio::time::Instant> {
+    match (a, b) {
+        (Some(a), Some(b)) => Some(a.min(b)),
+        (a, b) => a.or(b),
+    }
+}
+
+// 无论流如何结束都发送完成事件并结束各个 sink，否则前端会一直等待。
+// 异常结束时 finish_reason 标明原因
+fn end_stream(sinks: &mut emit::Sinks, end: &StreamEnd, finish_reason: Option<String>) {
+    let finish_reason = match end {
+        StreamEnd::Deadline => Some("deadline".to_string()),
+        StreamEnd::TooLarge(_) => Some("max_response_bytes".to_string()),
+        StreamEnd::Failed(_) => Some("error".to_string()),
+        StreamEnd::Done | StreamEnd::Closed | StreamEnd::Cancelled => finish_reason,
+    };
+    sinks.send(&StreamData::finished(finish_reason));
+    sinks.finish();
+}
+
+#[tauri::command]
+async fn chat_completions_stream(
+    mut base_url: String,
+    api_key: String,
+    mut model: String,
+    messages: Vec<Message>,
+    enable_deep_thinking: bool,
+    options: Option<ChatOptions>,
+    app_handle: tauri::AppHandle,
+) -> Result<StreamResult, String> {
+    let options = ChatOptions::resolve(options, &mut base_url, &mut model, &app_handle)?;
+    let transform = stream_transform(&options)?;
+    let registry = app_handle.state::<StreamRegistry>();
+    let guard = registry.register(options.stream_id.clone());
+    // 把句柄告知前端，之后可用它调用 release_stream 中止本次请求
+    let _ = app_handle.emit("stream-started", guard.id());
+
+    let url = format!("{}/chat/completions", base_url);
+
+    let client = app_handle.state::<HttpClient>().client_for(&url)?;
+    let resolved = attachments::resolve_attachments(messages.clone(), app_handle.clone())?;
+    let request_body = build_request_body(&model, &resolved, true, enable_deep_thinking, &options)?;
+
+    let request_builder = client::stream_request(client.post(&url))
         .header("Content-Type", "application/json")
         .header("Authorization", format!("Bearer {}", api_key))
         .json(&request_body);
 
-    let response = request_builder
-        .send()
-        .await
-        .map_err(|e| format!("Failed to send request: {}", e))?;
+    // 等待响应头期间同样可以被取消
+    let request_deadline = options.deadline();
+    let response = tokio::select! {
+        _ = guard.token.cancelled() => return Err("Stream cancelled".to_string()),
+        response = with_deadline(request_deadline, async {
+            rate_limit::throttle(&app_handle, &url).await;
+            cassette::send(&app_handle, &url, &request_body, request_builder).await
+        }) => response??
+    };
+
+    rate_limit::observe(&app_handle, &url, response.status(), response.headers());
 
+    let rate_limit = emit_rate_limit(&app_handle, &response);
+    let request_id = client::request_id(response.headers());
+    let _ = app_handle.emit(
+        "stream-meta",
+        StreamMeta {
+            stream_id: guard.id().to_string(),
+            request_id: request_id.clone(),
+        },
+    );
     if !response.status().is_success() {
-        let error_text = response
-            .text()
-            .await
-            .unwrap_or_else(|_| "Unknown error".to_string());
-        return Err(format!("API Error: {}", error_text));
-    }
-
-    // 读取流式响应
-    let mut stream = response.bytes_stream();
-
-    while let Some(chunk) = stream.next().await {
-        let chunk = chunk.map_err(|e| format!("Failed to read chunk: {}", e))?;
-        let text = String::from_utf8_lossy(&chunk);
-
-        for line in text.lines() {
-            if line.starts_with("data: ") {
-                let data = &line[6..];
-                if data == "[DONE]" {
-                    // 发送完成事件
-                    let _ = app_handle.emit(
-                        "stream-chunk",
-                        StreamData {
-                            content: None,
-                            reasoning_content: None,
-                            done: true,
-                        },
-                    );
-                    return Ok(());
-                }
+        return Err(client::api_error(response).await);
+    }
+
+    // 部分网关忽略 stream: true，直接返回完整的 JSON，此时整体读取后作为一次增量发送
+    let is_sse = response
+        .headers()
+        .get(reqwest::header::CONTENT_TYPE)
+        .and_then(|v| v.to_str().ok())
+        .is_none_or(|ct| ct.contains("text/event-stream"));
+    let (stream, fallback_body) = if is_sse {
+        // 读取流式响应
+        (Some(client::stream_body(response)), None)
+    } else {
+        let body = with_deadline(
+            request_deadline,
+            client::read_body(response, options.max_response_bytes),
+        )
+        .await??;
+        (None, Some(body))
+    };
+    let mut result = StreamResult {
+        rate_limit,
+        request_id,
+        ..Default::default()
+    };
+    let mut partial_parser = options.partial_json.then(partial_json::PartialParser::new);
+    let mut all_citations = Vec::new();
+    let mut tool_call_accumulator = tool_calls::ToolCallAccumulator::default();
+    let mut buffer = emit::EmitBuffer::new(options.emit_batch_size, options.emit_interval_ms);
+    // 设置了 max_tokens 时按生成速度估算剩余时间，通过 stream-eta 事件发送
+    let mut throughput = options.max_tokens.map(throughput::ThroughputTracker::new);
+    let mut think_splitter = options
+        .extract_think_tags
+        .then(think::ThinkTagSplitter::default);
+    let mut code_fences = options
+        .detect_code_blocks
+        .then(codeblock::CodeFenceScanner::default);
+
+    // 解析出的数据依次交给各个 sink：前端事件、可选的文件记录、可选的内存累积
+    let mut sinks = match options.event_queue_capacity {
+        Some(capacity) => {
+            emit::Sinks::queued_events(app_handle.clone(), capacity, guard.acked.clone())
+        }
+        None => emit::Sinks::events(app_handle.clone()),
+    };
+    sinks.record_recent(guard.recent.clone());
+    sinks.set_transform(transform);
+    sinks.set_sanitize(options.sanitize_output);
+    if let Some(path) = &options.output_file {
+        sinks.add(emit::FileSink::create(path)?);
+    }
+    if options.scrollback {
+        let store = app_handle.state::<scrollback::ScrollbackStore>();
+        sinks.add(store.open(guard.id(), options.scrollback_max_chars));
+    }
+    let autosave_id = options.conversation_id.clone().filter(|_| options.autosave);
+    let accumulator = (options.accumulate || autosave_id.is_some()).then(|| {
+        let (sink, handle) = emit::AccumulatorSink::new();
+        sinks.add(sink);
+        handle
+    });
+
+    let mut last_checkpoint = std::time::Instant::now();
+    if let Some(body) = fallback_body {
+        log::warn!("{} returned a non-SSE response to a streaming request", url);
+        let response = parse_fallback_response(&body, &options)?;
+        if let Some(usage) = &response.usage {
+            result.total_tokens = Some(usage.total_tokens);
+            let _ = app_handle.emit("stream-usage", usage);
+        }
+        result.usage = response.usage.clone();
+        result.model = Some(response.model).filter(|m| !m.is_empty());
+        if result.request_id.is_none() {
+            result.request_id = response.request_id;
+        }
+        result.system_fingerprint = response.system_fingerprint;
+        if let Some(choice) = response.choices.into_iter().next() {
+            result.finish_reason = choice.finish_reason.clone();
+            result.content_length = choice.message.content.chars().count();
+            if let Some(scanner) = &mut code_fences {
+                emit_code_fences(&app_handle, scanner.feed(&choice.message.content));
+            }
+            if let Some(calls) = &choice.message.tool_calls {
+                let _ = app_handle.emit("stream-tool-calls", calls);
+            }
+            result.tool_calls = choice.message.tool_calls;
+            sinks.send(&StreamData {
+                role: Some(choice.message.role),
+                content: Some(choice.message.content).filter(|c| !c.is_empty()),
+                reasoning_content: choice.message.reasoning_content,
+                finish_reason: choice.finish_reason,
+                ..Default::default()
+            });
+        }
+    }
+    let end = match stream {
+        // 非 SSE 的完整响应已在上面处理
+        None => StreamEnd::Done,
+        Some(stream) => {
+            let mut reader = StreamReader::new(
+                stream,
+                guard.token.clone(),
+                request_deadline,
+                options.max_response_bytes,
+                options.raw_sse,
+            );
+            'read: loop {
+                let events = match reader
+                    .next(earliest(buffer.deadline(), sinks.deadline()))
+                    .await
+                {
+                    StreamStep::Events { events, raw_lines } => {
+                        for line in raw_lines {
+                            let _ = app_handle.emit("raw-sse-line", line);
+                        }
+                        events
+                    }
+                    // 长时间没有新增量时按时间阈值发送已缓冲的内容
+                    StreamStep::Idle => {
+                        let now = tokio::time::Instant::now();
+                        if buffer.deadline().is_some_and(|deadline| deadline <= now) {
+                            if let Some(data) = buffer.flush() {
+                                sinks.send(&data);
+                            }
+                        }
+                        sinks.poll_queue();
+                        continue;
+                    }
+                    StreamStep::End(end) => break 'read end,
+                };
+
+                for event in events {
+                    if event.data == "[DONE]" {
+                        break 'read StreamEnd::Done;
+                    }
+
+                    // Anthropic 的思考增量走 content_block_delta，转成 reasoning_content
+                    if options.provider == Some(Provider::Anthropic) {
+                        if let Some(anthropic) = provider::parse_anthropic_event(&event.data) {
+                            let stream_data = match anthropic {
+                                provider::AnthropicEvent::Text(text) => {
+                                    result.content_length += text.chars().count();
+                                    if let Some(scanner) = &mut code_fences {
+                                        emit_code_fences(&app_handle, scanner.feed(&text));
+                                    }
+                                    StreamData {
+                                        content: Some(text),
+                                        ..Default::default()
+                                    }
+                                }
+                                provider::AnthropicEvent::Thinking(text) => StreamData {
+                                    reasoning_content: Some(text),
+                                    ..Default::default()
+                                },
+                                provider::AnthropicEvent::StopReason(reason) => {
+                                    result.finish_reason = Some(reason.clone());
+                                    StreamData {
+                                        finish_reason: Some(reason),
+                                        ..Default::default()
+                                    }
+                                }
+                                provider::AnthropicEvent::MessageStop => {
+                                    break 'read StreamEnd::Done;
+                                }
+                                provider::AnthropicEvent::Other => continue,
+                            };
+                            if let Some(data) = buffer.push(stream_data) {
+                                sinks.send(&data);
+                            }
+                            continue;
+                        }
+                    }
+
+                    let parsed = match event.event.as_deref() {
+                        None | Some("message") => {
+                            serde_json::from_str::<StreamChunk>(&event.data).ok()
+                        }
+                        Some(_) => None,
+                    };
+                    let Some(json) = parsed else {
+                        let _ = app_handle.emit(
+                            "stream-custom-event",
+                            CustomStreamEvent {
+                                event: event.event.unwrap_or_else(|| "message".to_string()),
+                                data: event.data,
+                            },
+                        );
+                        continue;
+                    };
+
+                    if result.model.is_none() && !json.model.is_empty() {
+                        let _ = app_handle.emit("stream-model", &json.model);
+                        result.model = Some(json.model.clone());
+                    }
+
+                    // 引用可能随分片重复下发或分散在多个分片中，合并后有变化才通知前端
+                    if ["citations", "search_results", "annotations"]
+                        .iter()
+                        .any(|field| event.data.contains(field))
+                    {
+                        let found = serde_json::from_str(&event.data)
+                            .ok()
+                            .and_then(|raw| citations::extract(&raw));
+                        if let Some(found) = found {
+                            if citations::merge(&mut all_citations, found) {
+                                let _ = app_handle.emit("stream-citations", &all_citations);
+                            }
+                        }
+                    }
+
+                    if let Some(mut usage) = json.usage.clone() {
+                        usage.fill_details();
+                        result.total_tokens = Some(usage.total_tokens);
+                        let _ = app_handle.emit("stream-usage", &usage);
+                        result.usage = Some(usage);
+                    }
+                    if result.system_fingerprint.is_none() {
+                        result.system_fingerprint = json.system_fingerprint.clone();
+                    }
 
-                if let Ok(json) = serde_json::from_str::<StreamChunk>(data) {
-                    if let Some(choice) = json.choices.first() {
-                        let stream_data = StreamData {
-                            content: choice.delta.content.clone(),
-                            reasoning_content: choice.delta.reasoning_content.clone(),
-                            done: false,
-                        };
+                    if let Some(choice) = json.choice(options.stream_only_index) {
+                        if choice.finish_reason.is_some() {
+                            result.finish_reason = choice.finish_reason.clone();
+                        }
+                        let pieces = choice_pieces(choice, think_splitter.as_mut());
+                        let deltas = pieces.first().and_then(|p| p.tool_call_deltas.as_ref());
+                        for delta in deltas.into_iter().flatten() {
+                            tool_call_accumulator.push(delta);
+                        }
 
-                        // 发送流式数据事件
-                        let _ = app_handle.emit("stream-chunk", &stream_data);
+                        // 多数服务每个分片对应一个 token，据此近似统计生成速度
+                        let has_text = choice.delta.content.is_some()
+                            || choice.delta.reasoning_content.is_some();
+                        if let Some(eta) = throughput
+                            .as_mut()
+                            .filter(|_| has_text)
+                            .and_then(|tracker| tracker.record(1))
+                        {
+                            let _ = app_handle.emit("stream-eta", eta);
+                        }
+
+                        for stream_data in pieces {
+                            if let Some(content) = &stream_data.content {
+                                result.content_length += content.chars().count();
+                                if let Some(scanner) = &mut code_fences {
+                                    emit_code_fences(&app_handle, scanner.feed(content));
+                                }
+                            }
+
+                            if let (Some(parser), Some(content)) =
+                                (&mut partial_parser, &stream_data.content)
+                            {
+                                if let Some(partial) = parser.push(content) {
+                                    let _ = app_handle.emit("stream-partial-json", &partial);
+                                }
+                            }
+
+                            // 发送流式数据事件
+                            if let Some(data) = buffer.push(stream_data) {
+                                sinks.send(&data);
+                            }
+                        }
                     }
                 }
+
+                if let (Some(id), Some(accumulated)) = (&autosave_id, &accumulator) {
+                    if last_checkpoint.elapsed() >= AUTOSAVE_CHECKPOINT_INTERVAL {
+                        last_checkpoint = std::time::Instant::now();
+                        let partial = accumulated.lock().unwrap().content.clone();
+                        autosave::checkpoint(&app_handle, id, &messages, &partial);
+                    }
+                }
+            }
+        }
+    };
+
+    if let Some(message) = end.error() {
+        let _ = app_handle.emit("stream-error", &message);
+        if let Some(data) = buffer.flush() {
+            sinks.send(&data);
+        }
+        end_stream(&mut sinks, &end, None);
+        if let (Some(id), Some(accumulated)) = (&autosave_id, &accumulator) {
+            let partial = accumulated.lock().unwrap().content.clone();
+            autosave::save_partial(&app_handle, id, &messages, &partial);
+        }
+        return Err(message);
+    }
+
+    // 被留存、疑似标签开头的尾部内容
+    if let Some(splitter) = &mut think_splitter {
+        for segment in splitter.finish() {
+            let rest = StreamData::from(segment);
+            if let Some(content) = &rest.content {
+                result.content_length += content.chars().count();
+                if let Some(scanner) = &mut code_fences {
+                    emit_code_fences(&app_handle, scanner.feed(content));
+                }
+            }
+            if let Some(data) = buffer.push(rest) {
+                sinks.send(&data);
+            }
+        }
+    }
+    if let Some(scanner) = &mut code_fences {
+        emit_code_fences(&app_handle, scanner.finish());
+    }
+    if let Some(partial) = partial_parser.as_mut().and_then(|parser| parser.finish()) {
+        let _ = app_handle.emit("stream-partial-json", &partial);
+    }
+    if let Some(data) = buffer.flush() {
+        sinks.send(&data);
+    }
+    log::info!(
+        "Stream finished: {} deltas sent in {} emits",
+        buffer.received,
+        buffer.emitted
+    );
+    result.reproducibility_warning = fingerprint::check(
+        &app_handle,
+        &request_body,
+        result.system_fingerprint.as_deref(),
+    );
+    if !tool_call_accumulator.is_empty() {
+        let calls = tool_call_accumulator.finish();
+        let _ = app_handle.emit("stream-tool-calls", &calls);
+        result.tool_calls = Some(calls);
+    }
+    // 部分服务生成结束后直接断开连接、不发送 [DONE]，同样要发送完成事件，
+    // 否则前端会一直等待
+    if end == StreamEnd::Closed {
+        log::warn!("{} closed the stream without [DONE]", url);
+    }
+    end_stream(&mut sinks, &end, result.finish_reason.clone());
+
+    if let Some(accumulated) = accumulator {
+        let accumulated = accumulated.lock().unwrap();
+        match &autosave_id {
+            Some(id) if end == StreamEnd::Cancelled => {
+                autosave::save_partial(&app_handle, id, &messages, &accumulated.content)
+            }
+            Some(id) => autosave::schedule(&app_handle, id, &messages, &accumulated.content),
+            None => {}
+        }
+        if options.accumulate {
+            result.content = Some(accumulated.content.clone());
+            if !accumulated.reasoning_content.is_empty() {
+                result.reasoning_content = Some(accumulated.reasoning_content.clone());
+            }
+        }
+    }
+
+    Ok(result)
+}
+
+// 围栏在整行结束后才能确定，事件可能早于包含该行的 stream-chunk 到达
+fn emit_code_fences(app_handle: &tauri::AppHandle, fences: Vec<codeblock::CodeFence>) {
+    for fence in fences {
+        let _ = match fence {
+            codeblock::CodeFence::Start(event) => app_handle.emit("code-block-start", event),
+            codeblock::CodeFence::End(event) => app_handle.emit("code-block-end", event),
+        };
+    }
+}
+
+// 将文本切分为"词"：空白随前一个词一起输出，CJK 等非 ASCII 文字逐字输出
+fn split_words(text: &str) -> Vec<String> {
+    let mut words = Vec::new();
+    let mut current = String::new();
+
+    for c in text.chars() {
+        if c.is_whitespace() {
+            current.push(c);
+            words.push(std::mem::take(&mut current));
+        } else if !c.is_ascii() && c.is_alphanumeric() {
+            if !current.is_empty() {
+                words.push(std::mem::take(&mut current));
+            }
+            words.push(c.to_string());
+        } else {
+            current.push(c);
+        }
+    }
+    if !current.is_empty() {
+        words.push(current);
+    }
+    words
+}
+
+// 提取消息中的纯文本（字符串内容，或多模态数组中的 text 部分）
+fn message_text(content: &serde_json::Value) -> String {
+    match content {
+        serde_json::Value::String(text) => text.clone(),
+        serde_json::Value::Array(parts) => parts
+            .iter()
+            .filter(|part| part["type"] == "text")
+            .filter_map(|part| part["text"].as_str())
+            .collect::<Vec<_>>()
+            .join("\n"),
+        _ => String::new(),
+    }
+}
+
+// 回放已保存会话的最后一条助手消息，用于前端离线调试和演示
+#[tauri::command]
+async fn replay_conversation(
+    id: String,
+    delay_ms: u64,
+    stream_id: Option<String>,
+    app_handle: tauri::AppHandle,
+) -> Result<(), String> {
+    let conversation = storage::load(&app_handle, &id)?;
+    let text = conversation
+        .messages
+        .iter()
+        .rev()
+        .find(|m| m.role == "assistant")
+        .map(|m| message_text(&m.content))
+        .ok_or_else(|| format!("Conversation {} has no assistant message", id))?;
+
+    let registry = app_handle.state::<StreamRegistry>();
+    let guard = registry.register(Some(stream_id.unwrap_or(id)));
+
+    let mut finish_reason = Some("stop".to_string());
+    let mut sinks = emit::Sinks::events(app_handle.clone());
+    sinks.record_recent(guard.recent.clone());
+    for (i, word) in split_words(&text).into_iter().enumerate() {
+        sinks.send(&StreamData {
+            role: (i == 0).then(|| "assistant".to_string()),
+            content: Some(word),
+            ..Default::default()
+        });
+
+        tokio::select! {
+            _ = guard.token.cancelled() => {
+                finish_reason = None;
+                break;
             }
+            _ = tokio::time::sleep(std::time::Duration::from_millis(delay_ms)) => {}
         }
     }
 
+    sinks.send(&StreamData::finished(finish_reason));
+
     Ok(())
 }
 
@@ -227,9 +1696,120 @@ async fn chat_completions_stream(
 pub fn run() {
     tauri::Builder::default()
         .plugin(tauri_plugin_http::init())
+        .manage(StreamRegistry::default())
+        .manage(autosave::Autosave::default())
+        .manage(CapabilityCache::default())
+        .manage(fingerprint::FingerprintCache::default())
+        .manage(config::ConfigState::default())
+        .manage(pricing::PricingTable::default())
+        .manage(output_limits::ProbedOutputLimits::default())
+        .manage(sse_server::LocalSseServer::default())
+        .manage(rate_limit::AdaptiveLimiter::default())
+        .manage(cassette::CassetteState::default())
+        .manage(scrollback::ScrollbackStore::default())
+        .manage(calibration::TokenCalibration::default())
+        .manage(scheduler::Scheduler::default())
+        .manage(profiles::ProfileKeys::default())
+        .manage(HttpClient::new(ClientConfig::default()).expect("failed to build HTTP client"))
         .invoke_handler(tauri::generate_handler![
             chat_completions,
-            chat_completions_stream
+            chat_completions_stream,
+            replay_conversation,
+            client::warmup,
+            client::configure_client,
+            client::get_client_config,
+            client::set_allowed_hosts,
+            client::set_denied_hosts,
+            client::get_host_policy,
+            moderation::moderate_content,
+            messages::merge_consecutive_roles,
+            messages::coalesce_system_messages,
+            messages::conversation_hash,
+            messages::trim_messages,
+            messages::format_transcript,
+            storage::export_finetuning_jsonl,
+            storage::pin_message,
+            storage::unpin_message,
+            messages::validate_multimodal,
+            partial_json::repair_tool_arguments,
+            attachments::store_attachment,
+            attachments::resolve_attachments,
+            attachments::delete_orphan_attachments,
+            attachments::validate_image_url,
+            attachments::encode_audio,
+            audio::text_to_speech,
+            audio::transcribe_audio,
+            images::generate_image,
+            sweep::parameter_sweep,
+            capabilities::detect_capabilities,
+            diagnostics::probe_context_window,
+            diagnostics::compare_stream_modes,
+            diagnostics::latency_breakdown,
+            diagnostics::test_streaming,
+            streams::acquire_stream_handle,
+            streams::release_stream,
+            streams::get_stream_buffer,
+            streams::ack_stream_chunk,
+            streams::cancel_stream,
+            streams::cancel_all_streams,
+            provider::normalize_thinking,
+            autosave::get_resumable_streams,
+            autosave::dismiss_resumable_stream,
+            compress::auto_compress_conversation,
+            templates::save_template,
+            templates::render_template,
+            templates::list_templates,
+            templates::load_template,
+            templates::delete_template,
+            redact::redact_messages,
+            redact::restore_redactions,
+            config::reload_config,
+            pricing::refresh_pricing,
+            pricing::get_model_price,
+            pricing::estimate_cost,
+            rate_limit::get_learned_rate_limits,
+            rate_limit::reset_learned_rate_limits,
+            embeddings::create_embeddings,
+            embeddings::find_similar_conversations,
+            ensemble::chat_completions_ensemble,
+            schema::validate_against_schema,
+            model_fit::suggest_model,
+            cassette::set_record_mode,
+            cassette::set_replay_mode,
+            cassette::disable_cassette,
+            scrollback::get_stream_content,
+            scrollback::get_stream_content_info,
+            scrollback::release_stream_content,
+            calibration::count_tokens,
+            calibration::calibrate_tokenizer,
+            scheduler::schedule_prompt,
+            scheduler::cancel_schedule,
+            scheduler::list_schedules,
+            model_alias::canonicalize_model,
+            output_limits::probe_max_output,
+            storage::lock_conversation_model,
+            storage::unlock_conversation_model,
+            sse_server::start_local_sse_server,
+            sse_server::stop_local_sse_server,
+            injection::default_injection_rules,
+            injection::detect_injection,
+            lint::lint_request,
+            lint::format_request,
+            profiles::save_profile,
+            profiles::list_profiles,
+            profiles::load_profile,
+            profiles::delete_profile,
+            profiles::set_profile_key,
+            storage::save_conversation,
+            storage::load_conversation,
+            storage::list_conversations,
+            storage::delete_conversation,
+            storage::export_all_conversations,
+            storage::import_all_conversations,
+            storage::set_conversation_tags,
+            storage::get_conversations_by_tag,
+            storage::list_all_tags,
+            storage::diff_conversations
         ])
         .setup(|app| {
             if cfg!(debug_assertions) {
@@ -239,8 +1819,680 @@ pub fn run() {
                         .build(),
                 )?;
             }
+            config::init(app.handle());
+            pricing::init(app.handle());
+            rate_limit::init(app.handle());
+            calibration::init(app.handle());
+            output_limits::init(app.handle());
+            scheduler::init(app.handle());
+            Ok(())
+        })
+        .build(tauri::generate_context!())
+        .expect("error while running tauri application")
+        .run(|app_handle, event| {
+            if let tauri::RunEvent::ExitRequested { .. } = event {
+                // 退出前取消所有进行中的流，并短暂等待连接释放，避免继续消耗 token
+                let registry = app_handle.state::<StreamRegistry>();
+                registry.cancel_all();
+                let deadline = std::time::Instant::now() + std::time::Duration::from_millis(500);
+                while !registry.is_empty() && std::time::Instant::now() < deadline {
+                    std::thread::sleep(std::time::Duration::from_millis(20));
+                }
+                // 被取消的流已立即保存，这里写入其余仍在延迟中的更新
+                autosave::flush(app_handle);
+            }
+        });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn response(choices: serde_json::Value) -> ChatResponse {
+        let body = serde_json::json!({
+            "id": "chatcmpl-1",
+            "object": "chat.completion",
+            "created": 0,
+            "model": "test-model",
+            "choices": choices,
+        });
+        parse_chat_response(body.to_string().as_bytes(), &ChatOptions::default()).unwrap()
+    }
+
+    #[test]
+    fn whitespace_only_content_is_empty_response() {
+        let empty = response(serde_json::json!([{
+            "index": 0,
+            "message": { "role": "assistant", "content": " \n " },
+            "finish_reason": "stop",
+        }]));
+        assert!(is_empty_response(&empty));
+
+        let null_content = response(serde_json::json!([{
+            "index": 0,
+            "message": { "role": "assistant", "content": null },
+            "finish_reason": "stop",
+        }]));
+        assert!(is_empty_response(&null_content));
+    }
+
+    #[test]
+    fn tool_call_only_turn_is_not_empty_response() {
+        let tool_turn = response(serde_json::json!([{
+            "index": 0,
+            "message": {
+                "role": "assistant",
+                "content": null,
+                "tool_calls": [{
+                    "id": "call_1",
+                    "type": "function",
+                    "function": { "name": "lookup", "arguments": "{}" },
+                }],
+            },
+            "finish_reason": "tool_calls",
+        }]));
+        assert!(!is_empty_response(&tool_turn));
+
+        let answered = response(serde_json::json!([{
+            "index": 0,
+            "message": { "role": "assistant", "content": "Hi" },
+            "finish_reason": "stop",
+        }]));
+        assert!(!is_empty_response(&answered));
+    }
+
+    // n = 2 时每个分片同时带有两个候选的增量
+    fn multi_choice_stream() -> Vec<StreamChunk> {
+        let body = [("A1", "B1"), ("A2", "B2")]
+            .iter()
+            .map(|(a, b)| {
+                let chunk = serde_json::json!({
+                    "choices": [
+                        { "index": 0, "delta": { "content": a }, "finish_reason": null },
+                        { "index": 1, "delta": { "content": b }, "finish_reason": null },
+                    ],
+                });
+                format!("data: {}\n\n", chunk)
+            })
+            .collect::<String>()
+            + "data: [DONE]\n\n";
+        let mut parser = sse::SseParser::default();
+        parser
+            .feed(body.as_bytes())
+            .into_iter()
+            .filter(|event| event.data != "[DONE]")
+            .map(|event| serde_json::from_str(&event.data).unwrap())
+            .collect()
+    }
+
+    fn streamed_content(only_index: Option<u32>) -> String {
+        multi_choice_stream()
+            .iter()
+            .filter_map(|chunk| chunk.choice(only_index))
+            .filter_map(|choice| choice.delta.content.clone())
+            .collect()
+    }
+
+    #[test]
+    fn stream_only_index_selects_one_choice() {
+        assert_eq!(streamed_content(Some(1)), "B1B2");
+        assert_eq!(streamed_content(Some(0)), "A1A2");
+    }
+
+    #[test]
+    fn streams_first_choice_by_default() {
+        assert_eq!(streamed_content(None), "A1A2");
+        assert_eq!(streamed_content(Some(5)), "");
+    }
+
+    #[test]
+    fn anthropic_prefill_trims_trailing_whitespace() {
+        let message = prefill_message("Answer: \n", Provider::Anthropic);
+        assert_eq!(message.role, "assistant");
+        assert_eq!(message.content, "Answer:");
+        // 开头的空白不受影响
+        assert_eq!(prefill_message("  {", Provider::Anthropic).content, "  {");
+    }
+
+    #[test]
+    fn openai_prefill_is_sent_verbatim() {
+        assert_eq!(
+            prefill_message("Answer: \n", Provider::OpenAi).content,
+            "Answer: \n"
+        );
+    }
+
+    #[test]
+    fn prefill_is_appended_as_last_message() {
+        let options = ChatOptions {
+            provider: Some(Provider::Anthropic),
+            prefill: Some("{ ".to_string()),
+            ..Default::default()
+        };
+        let messages = vec![Message {
+            role: "user".to_string(),
+            content: serde_json::json!("Reply in JSON"),
+            pinned: None,
+        }];
+        let body = build_request_body("model", &messages, false, false, &options).unwrap();
+        assert_eq!(
+            body["messages"][1],
+            serde_json::json!({ "role": "assistant", "content": "{" })
+        );
+    }
+
+    #[tokio::test]
+    async fn past_deadline_fails_immediately() {
+        let options = ChatOptions {
+            deadline_unix_ms: Some(storage::now_millis() - 1_000),
+            ..Default::default()
+        };
+        let deadline = options.deadline();
+        assert!(deadline.unwrap() <= tokio::time::Instant::now());
+
+        let started = std::time::Instant::now();
+        let slow = tokio::time::sleep(std::time::Duration::from_secs(60));
+        assert_eq!(
+            with_deadline(deadline, slow).await,
+            Err(DEADLINE_EXCEEDED.to_string())
+        );
+        assert!(started.elapsed() < std::time::Duration::from_secs(1));
+    }
+
+    #[tokio::test]
+    async fn no_deadline_waits_for_completion() {
+        assert_eq!(ChatOptions::default().deadline(), None);
+        assert_eq!(with_deadline(None, async { 42 }).await, Ok(42));
+    }
+
+    #[test]
+    fn explicit_sampling_is_sent_as_is() {
+        let options = ChatOptions {
+            temperature: Some(0.7),
+            top_p: Some(0.9),
+            ..Default::default()
+        };
+        assert_eq!(options.sampling(), (Some(0.7), Some(0.9), None));
+        assert_eq!(ChatOptions::default().sampling(), (None, None, None));
+    }
+
+    #[test]
+    fn deterministic_overrides_temperature_and_top_p() {
+        let options = ChatOptions {
+            deterministic: true,
+            temperature: Some(0.7),
+            top_p: Some(0.9),
+            ..Default::default()
+        };
+        assert_eq!(
+            options.sampling(),
+            (Some(0.0), Some(1.0), Some(DETERMINISTIC_SEED))
+        );
+        // 显式指定的 seed 仍然生效
+        let seeded = ChatOptions {
+            seed: Some(7),
+            ..options
+        };
+        assert_eq!(seeded.sampling(), (Some(0.0), Some(1.0), Some(7)));
+    }
+
+    #[test]
+    fn profile_params_do_not_override_sampling() {
+        let options = ChatOptions {
+            deterministic: true,
+            profile_params: serde_json::json!({ "temperature": 1.5, "top_k": 40 })
+                .as_object()
+                .unwrap()
+                .clone(),
+            ..Default::default()
+        };
+        let body = build_request_body("model", &[], false, false, &options).unwrap();
+        assert_eq!(body["temperature"], 0.0);
+        assert_eq!(body["top_p"], 1.0);
+        assert_eq!(body["seed"], DETERMINISTIC_SEED);
+        assert_eq!(body["top_k"], 40);
+    }
+
+    fn completion(content: &str, finish_reason: &str, completion_tokens: u32) -> ChatResponse {
+        let body = serde_json::json!({
+            "id": "chatcmpl-1",
+            "object": "chat.completion",
+            "created": 0,
+            "model": "test-model",
+            "choices": [{
+                "index": 0,
+                "message": { "role": "assistant", "content": content },
+                "finish_reason": finish_reason,
+            }],
+            "usage": {
+                "prompt_tokens": 10,
+                "completion_tokens": completion_tokens,
+                "total_tokens": 10 + completion_tokens,
+            },
+        });
+        parse_chat_response(body.to_string().as_bytes(), &ChatOptions::default()).unwrap()
+    }
+
+    fn question() -> Vec<Message> {
+        vec![Message {
+            role: "user".to_string(),
+            content: serde_json::json!("Write a long story"),
+            pinned: None,
+        }]
+    }
+
+    #[tokio::test]
+    async fn continues_until_natural_stop() {
+        let mut replies = vec![
+            completion(" and", "length", 5),
+            completion(" end.", "stop", 2),
+        ];
+        let mut requests = Vec::new();
+        let response = continue_truncated(
+            completion("Once upon a time", "length", 8),
+            &question(),
+            3,
+            |request| {
+                requests.push(request);
+                let next = replies.remove(0);
+                async move { Ok(next) }
+            },
+        )
+        .await
+        .unwrap();
+
+        let choice = &response.choices[0];
+        assert_eq!(choice.message.content, "Once upon a time and end.");
+        assert_eq!(choice.finish_reason.as_deref(), Some("stop"));
+        let usage = response.usage.unwrap();
+        assert_eq!((usage.prompt_tokens, usage.completion_tokens), (30, 15));
+
+        assert_eq!(requests.len(), 2);
+        // 第二次续写带上前两段拼接后的内容
+        let last = &requests[1];
+        assert_eq!(last.len(), 3);
+        assert_eq!(last[1].role, "assistant");
+        assert_eq!(last[1].content, "Once upon a time and");
+        assert_eq!(last[2].content, CONTINUE_PROMPT);
+    }
+
+    #[tokio::test]
+    async fn stops_at_continuation_cap() {
+        let mut calls = 0;
+        let response = continue_truncated(completion("a", "length", 1), &question(), 2, |_| {
+            calls += 1;
+            async { Ok(completion("a", "length", 1)) }
+        })
+        .await
+        .unwrap();
+        assert_eq!(calls, 2);
+        assert_eq!(response.choices[0].message.content, "aaa");
+        assert_eq!(response.choices[0].finish_reason.as_deref(), Some("length"));
+    }
+
+    #[tokio::test]
+    async fn complete_response_is_not_continued() {
+        let response =
+            continue_truncated(completion("done", "stop", 1), &question(), 3, |_| async {
+                Err::<ChatResponse, _>("should not be called".to_string())
+            })
+            .await
+            .unwrap();
+        assert_eq!(response.choices[0].message.content, "done");
+    }
+
+    #[test]
+    fn continuation_does_not_append_prefill() {
+        let options = ChatOptions {
+            prefill: Some("{".to_string()),
+            ..Default::default()
+        };
+        let mut request = question();
+        request.push(prefill_message("{\"title\": ", Provider::Generic));
+        let body = continuation_body("model", &request, false, &options).unwrap();
+        let messages = body["messages"].as_array().unwrap();
+        assert_eq!(messages.len(), 2);
+        assert_eq!(messages[1]["content"], "{\"title\": ");
+    }
+
+    #[test]
+    fn deep_merge_keeps_existing_and_overrides_conflicts() {
+        let mut target = serde_json::json!({
+            "model": "a",
+            "stream_options": { "include_usage": true },
+            "stop": ["\n"],
+        });
+        deep_merge(
+            &mut target,
+            &serde_json::json!({
+                "model": "b",
+                "stream_options": { "chunk_size": 4 },
+                "stop": ["END"],
+                "top_k": 40,
+            }),
+        );
+        assert_eq!(
+            target,
+            serde_json::json!({
+                "model": "b",
+                "stream_options": { "include_usage": true, "chunk_size": 4 },
+                // 数组整体替换，不逐项合并
+                "stop": ["END"],
+                "top_k": 40,
+            })
+        );
+    }
+
+    #[test]
+    fn deep_merge_null_removes_field() {
+        let mut target = serde_json::json!({ "temperature": 0.5, "nested": { "a": 1, "b": 2 } });
+        deep_merge(
+            &mut target,
+            &serde_json::json!({ "temperature": null, "nested": { "a": null } }),
+        );
+        assert_eq!(target, serde_json::json!({ "nested": { "b": 2 } }));
+    }
+
+    #[test]
+    fn extra_body_wins_over_built_in_fields() {
+        let options = ChatOptions {
+            max_tokens: Some(100),
+            extra_body: Some(
+                serde_json::json!({ "max_tokens": null, "max_completion_tokens": 100 }),
+            ),
+            ..Default::default()
+        };
+        let body = build_request_body("model", &[], false, false, &options).unwrap();
+        assert!(body.get("max_tokens").is_none());
+        assert_eq!(body["max_completion_tokens"], 100);
+        assert_eq!(body["model"], "model");
+
+        let invalid = ChatOptions {
+            extra_body: Some(serde_json::json!([1, 2])),
+            ..Default::default()
+        };
+        assert_eq!(
+            build_request_body("model", &[], false, false, &invalid).unwrap_err(),
+            "extra_body must be a JSON object"
+        );
+    }
+
+    #[derive(Default)]
+    struct Recorded {
+        sent: Vec<StreamData>,
+        finished: bool,
+    }
+
+    struct Recorder(std::sync::Arc<std::sync::Mutex<Recorded>>);
+
+    impl emit::StreamSink for Recorder {
+        fn send(&mut self, data: &StreamData) -> Result<(), String> {
+            self.0.lock().unwrap().sent.push(data.clone());
+            Ok(())
+        }
+
+        fn finish(&mut self) -> Result<(), String> {
+            self.0.lock().unwrap().finished = true;
             Ok(())
+        }
+    }
+
+    fn reader(
+        chunks: Vec<Result<&str, &str>>,
+        max_bytes: Option<usize>,
+    ) -> StreamReader<impl futures_util::Stream<Item = Result<Vec<u8>, String>> + Unpin> {
+        let chunks: Vec<Result<Vec<u8>, String>> = chunks
+            .into_iter()
+            .map(|c| c.map(|s| s.as_bytes().to_vec()).map_err(str::to_string))
+            .collect();
+        StreamReader::new(
+            futures_util::stream::iter(chunks),
+            tokio_util::sync::CancellationToken::new(),
+            None,
+            max_bytes,
+            false,
+        )
+    }
+
+    // 读到流结束，返回所有事件的 data 与结束方式
+    async fn drain<S>(reader: &mut StreamReader<S>) -> (Vec<String>, StreamEnd)
+    where
+        S: futures_util::Stream<Item = Result<Vec<u8>, String>> + Unpin,
+    {
+        let mut data = Vec::new();
+        loop {
+            match reader.next(None).await {
+                StreamStep::Events { events, .. } => {
+                    data.extend(events.into_iter().map(|e| e.data))
+                }
+                StreamStep::Idle => {}
+                StreamStep::End(end) => return (data, end),
+            }
+        }
+    }
+
+    fn ended(end: &StreamEnd, finish_reason: Option<&str>) -> Recorded {
+        let recorded = std::sync::Arc::new(std::sync::Mutex::new(Recorded::default()));
+        let mut sinks = emit::Sinks::new();
+        sinks.add(Recorder(recorded.clone()));
+        end_stream(&mut sinks, end, finish_reason.map(str::to_string));
+        drop(sinks);
+        std::sync::Arc::try_unwrap(recorded)
+            .ok()
+            .unwrap()
+            .into_inner()
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn abrupt_end_still_sends_done() {
+        // 最后一个事件没有以空行结束，连接就断开了
+        let mut reader = reader(vec![Ok("data: a\n\n"), Ok("data: b")], None);
+        let (data, end) = drain(&mut reader).await;
+        assert_eq!(data, ["a", "b"]);
+        assert_eq!(end, StreamEnd::Closed);
+        assert_eq!(end.error(), None);
+
+        let recorded = ended(&end, Some("stop"));
+        let last = recorded.sent.last().unwrap();
+        assert!(last.done);
+        assert_eq!(last.finish_reason.as_deref(), Some("stop"));
+        assert!(recorded.finished);
+    }
+
+    #[tokio::test]
+    async fn transport_error_sends_done_and_finishes_sinks() {
+        let mut reader = reader(vec![Ok("data: a\n\n"), Err("connection reset")], None);
+        let (data, end) = drain(&mut reader).await;
+        assert_eq!(data, ["a"]);
+        assert_eq!(end, StreamEnd::Failed("connection reset".to_string()));
+        assert_eq!(end.error().as_deref(), Some("connection reset"));
+
+        let recorded = ended(&end, Some("stop"));
+        let last = recorded.sent.last().unwrap();
+        assert!(last.done);
+        assert_eq!(last.finish_reason.as_deref(), Some("error"));
+        assert!(recorded.finished);
+    }
+
+    #[tokio::test]
+    async fn oversized_response_ends_stream() {
+        let mut reader = reader(vec![Ok("data: a\n\n"), Ok("data: bbbbbbbb\n\n")], Some(12));
+        let (data, end) = drain(&mut reader).await;
+        assert_eq!(data, ["a"]);
+        assert_eq!(end, StreamEnd::TooLarge(12));
+        assert_eq!(
+            end.error().as_deref(),
+            Some("Response exceeded the maximum size of 12 bytes")
+        );
+        let recorded = ended(&end, None);
+        assert_eq!(
+            recorded.sent.last().unwrap().finish_reason.as_deref(),
+            Some("max_response_bytes")
+        );
+    }
+
+    #[tokio::test]
+    async fn cancellation_ends_stream_without_error() {
+        let token = tokio_util::sync::CancellationToken::new();
+        token.cancel();
+        let mut reader = StreamReader::new(
+            futures_util::stream::pending::<Result<Vec<u8>, String>>(),
+            token,
+            None,
+            None,
+            false,
+        );
+        assert_eq!(drain(&mut reader).await.1, StreamEnd::Cancelled);
+        assert_eq!(StreamEnd::Cancelled.error(), None);
+        assert!(ended(&StreamEnd::Cancelled, None).sent.last().unwrap().done);
+    }
+
+    #[tokio::test]
+    async fn deadline_ends_stream() {
+        let mut reader = StreamReader::new(
+            futures_util::stream::pending::<Result<Vec<u8>, String>>(),
+            tokio_util::sync::CancellationToken::new(),
+            Some(tokio::time::Instant::now()),
+            None,
+            false,
+        );
+        let (_, end) = drain(&mut reader).await;
+        assert_eq!(end, StreamEnd::Deadline);
+        assert_eq!(end.error().as_deref(), Some(DEADLINE_EXCEEDED));
+    }
+
+    fn weather_tool() -> serde_json::Value {
+        serde_json::json!({
+            "type": "function",
+            "function": { "name": "get_weather", "parameters": { "type": "object" } },
         })
-        .run(tauri::generate_context!())
-        .expect("error while running tauri application");
+    }
+
+    #[test]
+    fn parallel_tool_calls_only_sent_with_tools() {
+        let body_with = |tools: Option<Vec<serde_json::Value>>| {
+            let options = ChatOptions {
+                tools,
+                parallel_tool_calls: Some(false),
+                ..Default::default()
+            };
+            build_request_body("model", &[], false, false, &options).unwrap()
+        };
+        assert!(body_with(None).get("parallel_tool_calls").is_none());
+        assert!(body_with(Some(vec![])).get("parallel_tool_calls").is_none());
+        assert_eq!(
+            body_with(Some(vec![weather_tool()]))["parallel_tool_calls"],
+            false
+        );
+    }
+
+    #[test]
+    fn tool_choice_is_validated_against_tools() {
+        let body_with = |tools: Option<Vec<serde_json::Value>>, tool_choice: serde_json::Value| {
+            let options = ChatOptions {
+                tools,
+                tool_choice: Some(tool_choice),
+                ..Default::default()
+            };
+            build_request_body("model", &[], false, false, &options)
+        };
+        let tools = || Some(vec![weather_tool()]);
+        let by_name =
+            |name: &str| serde_json::json!({ "type": "function", "function": { "name": name } });
+
+        assert_eq!(
+            body_with(None, serde_json::json!("auto")).unwrap_err(),
+            "tool_choice requires tools"
+        );
+        assert_eq!(
+            body_with(tools(), by_name("get_time")).unwrap_err(),
+            "tool_choice names an unknown tool: get_time"
+        );
+        assert_eq!(
+            body_with(tools(), serde_json::json!("sometimes")).unwrap_err(),
+            "Invalid tool_choice: \"sometimes\""
+        );
+
+        assert_eq!(
+            body_with(tools(), serde_json::json!("auto")).unwrap()["tool_choice"],
+            "auto"
+        );
+        assert_eq!(
+            body_with(tools(), by_name("get_weather")).unwrap()["tool_choice"],
+            by_name("get_weather")
+        );
+    }
+
+    #[test]
+    fn coalesced_system_goes_where_the_provider_expects() {
+        let messages = vec![
+            Message {
+                role: "system".to_string(),
+                content: serde_json::json!("base"),
+                pinned: None,
+            },
+            Message {
+                role: "system".to_string(),
+                content: serde_json::json!("persona"),
+                pinned: None,
+            },
+            Message {
+                role: "user".to_string(),
+                content: serde_json::json!("hi"),
+                pinned: None,
+            },
+        ];
+        let body_for = |provider| {
+            let options = ChatOptions {
+                provider: Some(provider),
+                coalesce_system_messages: true,
+                ..Default::default()
+            };
+            build_request_body("model", &messages, false, false, &options).unwrap()
+        };
+
+        let anthropic = body_for(Provider::Anthropic);
+        assert_eq!(anthropic["system"], "base\n\npersona");
+        assert_eq!(
+            anthropic["messages"],
+            serde_json::json!([{ "role": "user", "content": "hi" }])
+        );
+
+        let generic = body_for(Provider::Generic);
+        assert!(generic.get("system").is_none());
+        assert_eq!(generic["messages"][0]["content"], "base\n\npersona");
+        assert_eq!(generic["messages"].as_array().unwrap().len(), 2);
+    }
+
+    #[test]
+    fn fallback_response_leaves_restore_and_sanitize_to_sinks() {
+        let body = serde_json::json!({
+            "id": "chatcmpl-1",
+            "object": "chat.completion",
+            "created": 0,
+            "model": "test-model",
+            "choices": [{
+                "index": 0,
+                "message": { "role": "assistant", "content": "hi [REDACTED_NAME_1]\u{200b}" },
+                "finish_reason": "stop",
+            }],
+        });
+        let options = ChatOptions {
+            restore_redactions: Some(HashMap::from([(
+                "[REDACTED_NAME_1]".to_string(),
+                "Alice".to_string(),
+            )])),
+            sanitize_output: true,
+            ..Default::default()
+        };
+        let body = body.to_string();
+        let parsed = parse_fallback_response(body.as_bytes(), &options).unwrap();
+        assert_eq!(
+            parsed.choices[0].message.content,
+            "hi [REDACTED_NAME_1]\u{200b}"
+        );
+        let parsed = parse_chat_response(body.as_bytes(), &options).unwrap();
+        assert_eq!(parsed.choices[0].message.content, "hi Alice");
+    }
 }
@@ -0,0 +1,347 @@
+use serde::Serialize;
+use tauri::AppHandle;
+
+use crate::output_limits;
+use crate::provider::Provider;
+use crate::ChatRequest;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Severity {
+    Info,
+    Warning,
+    // 服务端几乎肯定会拒绝
+    Error,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct LintWarning {
+    pub severity: Severity,
+    pub code: &'static str,
+    pub message: String,
+}
+
+// 常见模型的最大输出 token 数，按名称前缀匹配（越具体的前缀越靠前）
+const OUTPUT_LIMITS: &[(&str, u32)] = &[
+    ("gpt-4o-mini", 16_384),
+    ("gpt-4o", 16_384),
+    ("gpt-4-turbo", 4_096),
+    ("gpt-4.1", 32_768),
+    ("gpt-3.5-turbo", 4_096),
+    ("o1", 100_000),
+    ("o3", 100_000),
+    ("claude-3-5", 8_192),
+    ("claude-3", 4_096),
+    ("claude-sonnet-4", 64_000),
+    ("claude-opus-4", 32_000),
+    ("gemini-1.5", 8_192),
+    ("gemini-2", 65_536),
+    ("deepseek-chat", 8_192),
+    ("deepseek-reasoner", 65_536),
+];
+
+fn output_limit(model: &str) -> Option<u32> {
+    let model = model.to_lowercase();
+    // 兼容 openai/gpt-4o 这类带服务商前缀的写法
+    let model = model.rsplit('/').next().unwrap_or(&model);
+    OUTPUT_LIMITS
+        .iter()
+        .find(|(prefix, _)| model.starts_with(prefix))
+        .map(|&(_, limit)| limit)
+}
+
+fn warn(warnings: &mut Vec<LintWarning>, severity: Severity, code: &'static str, message: String) {
+    warnings.push(LintWarning {
+        severity,
+        code,
+        message,
+    });
+}
+
+// probed_output_limit 为 probe_max_output 探测到的上限，优先于内置表
+pub fn lint(
+    provider: Provider,
+    request: &ChatRequest,
+    probed_output_limit: Option<u32>,
+) -> Vec<LintWarning> {
+    let mut warnings = Vec::new();
+
+    if request.model.trim().is_empty() {
+        warn(
+            &mut warnings,
+            Severity::Error,
+            "empty_model",
+            "Model name is empty".to_string(),
+        );
+    }
+
+    if request.messages.is_empty() {
+        warn(
+            &mut warnings,
+            Severity::Error,
+            "empty_messages",
+            "Messages array is empty".to_string(),
+        );
+    }
+
+    for (i, message) in request.messages.iter().enumerate() {
+        if !matches!(
+            message.role.as_str(),
+            "system" | "user" | "assistant" | "tool" | "developer"
+        ) {
+            warn(
+                &mut warnings,
+                Severity::Error,
+                "unknown_role",
+                format!("Message {} has unknown role \"{}\"", i, message.role),
+            );
+        }
+        let empty = match &message.content {
+            serde_json::Value::String(text) => text.trim().is_empty(),
+            serde_json::Value::Array(parts) => parts.is_empty(),
+            // 只调用工具的助手消息 content 可以为 null
+            serde_json::Value::Null => message.role != "assistant",
+            _ => false,
+        };
+        if empty {
+            warn(
+                &mut warnings,
+                Severity::Warning,
+                "empty_content",
+                format!("Message {} ({}) has empty content", i, message.role),
+            );
+        }
+    }
+
+    if let Some(thinking) = &request.thinking {
+        match provider {
+            Provider::OpenAi | Provider::Azure | Provider::Gemini | Provider::Ollama => warn(
+                &mut warnings,
+                Severity::Warning,
+                "unsupported_thinking",
+                format!(
+                    "{:?} does not accept the thinking parameter; use reasoning_effort instead",
+                    provider
+                ),
+            ),
+            _ if !matches!(thinking.thinking_type.as_str(), "enabled" | "disabled") => warn(
+                &mut warnings,
+                Severity::Error,
+                "invalid_thinking",
+                format!("Unknown thinking type \"{}\"", thinking.thinking_type),
+            ),
+            _ => {}
+        }
+    }
+
+    if let Some(max_tokens) = request.max_tokens {
+        if max_tokens == 0 {
+            warn(
+                &mut warnings,
+                Severity::Error,
+                "zero_max_tokens",
+                "max_tokens must be greater than 0".to_string(),
+            );
+        } else if let Some(limit) = probed_output_limit
+            .or_else(|| output_limit(&request.model))
+            .filter(|&l| max_tokens > l)
+        {
+            warn(
+                &mut warnings,
+                Severity::Warning,
+                "max_tokens_exceeds_limit",
+                format!(
+                    "max_tokens {} exceeds the known output limit of {} for {}",
+                    max_tokens, limit, request.model
+                ),
+            );
+        }
+    } else if provider == Provider::Anthropic {
+        warn(
+            &mut warnings,
+            Severity::Info,
+            "missing_max_tokens",
+            "Anthropic requires max_tokens; a provider default will be used".to_string(),
+        );
+    }
+
+    if provider == Provider::Anthropic {
+        // Anthropic 只接受开头连续的 system 消息（会被合并为顶层 system）
+        let leading = request
+            .messages
+            .iter()
+            .take_while(|m| m.role == "system")
+            .count();
+        if let Some(i) = request.messages[leading..]
+            .iter()
+            .position(|m| m.role == "system")
+        {
+            warn(
+                &mut warnings,
+                Severity::Error,
+                "system_placement",
+                format!(
+                    "Message {} is a system message after the conversation started; Anthropic only accepts system messages at the beginning",
+                    leading + i
+                ),
+            );
+        }
+        if let Some(first) = request.messages.get(leading) {
+            if first.role != "user" {
+                warn(
+                    &mut warnings,
+                    Severity::Warning,
+                    "first_message_not_user",
+                    format!(
+                        "Anthropic expects the first non-system message to be from the user, got \"{}\"",
+                        first.role
+                    ),
+                );
+            }
+        }
+    }
+
+    warnings
+}
+
+// 发送前检查请求在目标服务商上可能出现的兼容性问题
+#[tauri::command]
+pub fn lint_request(
+    provider: Provider,
+    request: ChatRequest,
+    app_handle: AppHandle,
+) -> Vec<LintWarning> {
+    let probed = output_limits::probed(&app_handle, &request.model);
+    lint(provider, &request, probed)
+}
+
+// 格式化请求 JSON，便于在调试面板中查看
+#[tauri::command]
+pub fn format_request(request: ChatRequest) -> Result<String, String> {
+    serde_json::to_string_pretty(&request).map_err(|e| format!("Failed to format request: {}", e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn request(value: serde_json::Value) -> ChatRequest {
+        serde_json::from_value(value).unwrap()
+    }
+
+    fn codes(provider: Provider, request: &ChatRequest, probed: Option<u32>) -> Vec<&'static str> {
+        lint(provider, request, probed)
+            .into_iter()
+            .map(|w| w.code)
+            .collect()
+    }
+
+    #[test]
+    fn clean_request_has_no_warnings() {
+        let clean = request(json!({
+            "model": "gpt-4o",
+            "messages": [
+                { "role": "system", "content": "Be brief" },
+                { "role": "user", "content": "Hi" },
+            ],
+            "max_tokens": 1024,
+        }));
+        assert!(codes(Provider::OpenAi, &clean, None).is_empty());
+        assert!(codes(Provider::Anthropic, &clean, None).is_empty());
+    }
+
+    #[test]
+    fn empty_request_is_an_error() {
+        let empty = request(json!({ "model": " ", "messages": [] }));
+        let warnings = lint(Provider::OpenAi, &empty, None);
+        assert_eq!(
+            warnings.iter().map(|w| w.code).collect::<Vec<_>>(),
+            ["empty_model", "empty_messages"]
+        );
+        assert!(warnings.iter().all(|w| w.severity == Severity::Error));
+    }
+
+    #[test]
+    fn flags_roles_and_empty_content() {
+        let bad = request(json!({
+            "model": "gpt-4o",
+            "messages": [
+                { "role": "narrator", "content": "Once" },
+                { "role": "user", "content": "  " },
+                // 只调用工具的助手消息
+                { "role": "assistant", "content": null },
+            ],
+        }));
+        assert_eq!(
+            codes(Provider::OpenAi, &bad, None),
+            ["unknown_role", "empty_content"]
+        );
+    }
+
+    #[test]
+    fn thinking_depends_on_provider() {
+        let thinking = request(json!({
+            "model": "deepseek-reasoner",
+            "messages": [{ "role": "user", "content": "Hi" }],
+            "thinking": { "type": "enabled" },
+        }));
+        assert_eq!(
+            codes(Provider::OpenAi, &thinking, None),
+            ["unsupported_thinking"]
+        );
+        assert!(codes(Provider::Generic, &thinking, None).is_empty());
+
+        let invalid = request(json!({
+            "model": "deepseek-reasoner",
+            "messages": [{ "role": "user", "content": "Hi" }],
+            "thinking": { "type": "maybe" },
+        }));
+        assert_eq!(
+            codes(Provider::Generic, &invalid, None),
+            ["invalid_thinking"]
+        );
+    }
+
+    #[test]
+    fn max_tokens_checked_against_known_and_probed_limits() {
+        let large = request(json!({
+            "model": "openai/gpt-4o-mini",
+            "messages": [{ "role": "user", "content": "Hi" }],
+            "max_tokens": 20_000,
+        }));
+        assert_eq!(
+            codes(Provider::Generic, &large, None),
+            ["max_tokens_exceeds_limit"]
+        );
+        // 探测到的上限优先于内置表
+        assert!(codes(Provider::Generic, &large, Some(32_000)).is_empty());
+
+        let zero = request(json!({
+            "model": "unknown-model",
+            "messages": [{ "role": "user", "content": "Hi" }],
+            "max_tokens": 0,
+        }));
+        assert_eq!(codes(Provider::Generic, &zero, None), ["zero_max_tokens"]);
+    }
+
+    #[test]
+    fn anthropic_message_order() {
+        let misplaced = request(json!({
+            "model": "claude-sonnet-4",
+            "messages": [
+                { "role": "assistant", "content": "Hello" },
+                { "role": "system", "content": "Be brief" },
+            ],
+        }));
+        assert_eq!(
+            codes(Provider::Anthropic, &misplaced, None),
+            [
+                "missing_max_tokens",
+                "system_placement",
+                "first_message_not_user"
+            ]
+        );
+        assert!(codes(Provider::OpenAi, &misplaced, None).is_empty());
+    }
+}
@@ -0,0 +1,444 @@
+use serde_json::Value;
+use sha2::{Digest, Sha256};
+
+use crate::provider::Provider;
+use crate::{message_text, Message, ResponseMessage};
+
+// 合并相邻的同角色纯文本消息；多模态数组内容保持原样，避免破坏结构
+#[tauri::command]
+pub fn merge_consecutive_roles(messages: Vec<Message>) -> Vec<Message> {
+    let mut merged: Vec<Message> = Vec::with_capacity(messages.len());
+
+    for message in messages {
+        if let Some(last) = merged.last_mut() {
+            if last.role == message.role {
+                if let (Some(prev), Some(next)) = (last.content.as_str(), message.content.as_str())
+                {
+                    last.content = Value::String(format!("{}\n{}", prev, next));
+                    // 任一条被置顶，合并后的消息也保持置顶
+                    if message.is_pinned() {
+                        last.pinned = Some(true);
+                    }
+                    continue;
+                }
+            }
+        }
+        merged.push(message);
+    }
+    merged
+}
+
+// 粗略估算：ASCII 约 4 个字符一个 token，CJK 等其他字符按一个字一个 token
+fn estimate_message_tokens(message: &Message) -> usize {
+    let text = message_text(&message.content);
+    let ascii = text.chars().filter(char::is_ascii).count();
+    let other = text.chars().count() - ascii;
+    // 每条消息的角色等格式开销
+    4 + ascii.div_ceil(4) + other
+}
+
+pub fn estimate_tokens(messages: &[Message]) -> usize {
+    messages.iter().map(estimate_message_tokens).sum()
+}
+
+// 从最早的消息开始丢弃，直到估算的 token 数不超过 max_tokens。
+// system 消息和置顶消息总是保留，最后一条消息（通常是本轮提问）也不会被丢弃
+#[tauri::command]
+pub fn trim_messages(messages: Vec<Message>, max_tokens: usize) -> Vec<Message> {
+    let mut total = estimate_tokens(&messages);
+    let last = messages.len().saturating_sub(1);
+    let mut keep = vec![true; messages.len()];
+    for (i, message) in messages.iter().enumerate().take(last) {
+        if total <= max_tokens {
+            break;
+        }
+        if message.role == "system" || message.is_pinned() {
+            continue;
+        }
+        keep[i] = false;
+        total -= estimate_message_tokens(message);
+    }
+    messages
+        .into_iter()
+        .zip(keep)
+        .filter_map(|(message, keep)| keep.then_some(message))
+        .collect()
+}
+
+// 按键名排序输出 JSON，保证键顺序不同但语义相同的内容序列化结果一致
+fn write_canonical(value: &Value, out: &mut String) {
+    match value {
+        Value::Object(map) => {
+            let mut keys: Vec<&String> = map.keys().collect();
+            keys.sort();
+            out.push('{');
+            for (i, key) in keys.into_iter().enumerate() {
+                if i > 0 {
+                    out.push(',');
+                }
+                out.push_str(&Value::String(key.clone()).to_string());
+                out.push(':');
+                write_canonical(&map[key], out);
+            }
+            out.push('}');
+        }
+        Value::Array(items) => {
+            out.push('[');
+            for (i, item) in items.iter().enumerate() {
+                if i > 0 {
+                    out.push(',');
+                }
+                write_canonical(item, out);
+            }
+            out.push(']');
+        }
+        other => out.push_str(&other.to_string()),
+    }
+}
+
+pub fn canonical_json(value: &Value) -> String {
+    let mut out = String::new();
+    write_canonical(value, &mut out);
+    out
+}
+
+// 会话内容的稳定哈希（SHA-256），用于缓存键和去重。
+// 只计入 role 和 content，置顶等本地元数据不影响哈希
+#[tauri::command]
+pub fn conversation_hash(messages: Vec<Message>) -> String {
+    let value = Value::Array(
+        messages
+            .into_iter()
+            .map(|m| serde_json::json!({ "role": m.role, "content": m.content }))
+            .collect(),
+    );
+    let digest = Sha256::digest(canonical_json(&value).as_bytes());
+    digest.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+fn require_str<'a>(value: &'a Value, field: &str, index: usize) -> Result<&'a str, String> {
+    value[field]
+        .as_str()
+        .ok_or_else(|| format!("Part {}: \"{}\" must be a string", index, field))
+}
+
+fn validate_part(part: &Value, index: usize) -> Result<(), String> {
+    if !part.is_object() {
+        return Err(format!("Part {}: must be an object", index));
+    }
+    let part_type = require_str(part, "type", index)?;
+    match part_type {
+        "text" => {
+            require_str(part, "text", index)?;
+        }
+        "image_url" => {
+            let image_url = &part["image_url"];
+            if !image_url.is_object() {
+                return Err(format!("Part {}: \"image_url\" must be an object", index));
+            }
+            require_str(image_url, "url", index)?;
+            if let Some(detail) = image_url.get("detail") {
+                if !matches!(detail.as_str(), Some("auto" | "low" | "high")) {
+                    return Err(format!(
+                        "Part {}: \"detail\" must be one of auto, low, high",
+                        index
+                    ));
+                }
+            }
+        }
+        "input_audio" => {
+            let input_audio = &part["input_audio"];
+            if !input_audio.is_object() {
+                return Err(format!("Part {}: \"input_audio\" must be an object", index));
+            }
+            require_str(input_audio, "data", index)?;
+            let format = require_str(input_audio, "format", index)?;
+            if !matches!(format, "wav" | "mp3") {
+                return Err(format!(
+                    "Part {}: unsupported audio format \"{}\"",
+                    index, format
+                ));
+            }
+        }
+        other => return Err(format!("Part {}: unknown type \"{}\"", index, other)),
+    }
+    Ok(())
+}
+
+// 发送前检查多模态内容的结构，错误信息指明出错的部分序号
+#[tauri::command]
+pub fn validate_multimodal(content: Value) -> Result<(), String> {
+    match &content {
+        // 只调用工具的助手消息 content 可以为 null
+        Value::String(_) | Value::Null => Ok(()),
+        Value::Array(parts) => parts
+            .iter()
+            .enumerate()
+            .try_for_each(|(index, part)| validate_part(part, index)),
+        _ => Err("Content must be a string or an array of parts".to_string()),
+    }
+}
+
+// 把一条回复整理成便于导出和复制的 Markdown：可选的思考过程放在最前面的引用块中，
+// 与正文用分隔线隔开；工具调用以代码块列在正文之后
+#[tauri::command]
+pub fn format_transcript(response: ResponseMessage, include_reasoning: bool) -> String {
+    let mut sections = Vec::new();
+    if let Some(reasoning) = response
+        .reasoning_content
+        .as_deref()
+        .map(str::trim)
+        .filter(|r| include_reasoning && !r.is_empty())
+    {
+        let quoted: Vec<String> = reasoning
+            .lines()
+            .map(|line| format!("> {}", line).trim_end().to_string())
+            .collect();
+        sections.push(format!("**Reasoning**\n\n{}", quoted.join("\n")));
+        sections.push("---".to_string());
+    }
+    let content = response.content.trim();
+    if !content.is_empty() {
+        sections.push(content.to_string());
+    }
+    for call in response.tool_calls.iter().flatten() {
+        sections.push(format!(
+            "**Tool call** `{}`\n\n```json\n{}\n```",
+            call.function.name, call.function.arguments
+        ));
+    }
+    // 只有思考过程时不需要分隔线
+    if sections.last().is_some_and(|last| last == "---") {
+        sections.pop();
+    }
+    sections.join("\n\n")
+}
+
+// 合并开头连续的多条 system 消息（基础指令、人设、上下文等）。
+// Anthropic 只接受一个顶层 system 字符串，合并结果单独返回并从消息中移除；
+// 其他服务合并为开头的一条 system 消息，第一个返回值为 None
+#[tauri::command]
+pub fn coalesce_system_messages(
+    messages: Vec<Message>,
+    provider: Provider,
+) -> (Option<String>, Vec<Message>) {
+    let leading = messages.iter().take_while(|m| m.role == "system").count();
+    if leading == 0 {
+        return (None, messages);
+    }
+    let mut rest = messages;
+    let system: Vec<Message> = rest.drain(..leading).collect();
+    let text = system
+        .iter()
+        .map(|m| message_text(&m.content))
+        .filter(|text| !text.trim().is_empty())
+        .collect::<Vec<_>>()
+        .join("\n\n");
+
+    if provider == Provider::Anthropic {
+        return (Some(text).filter(|t| !t.is_empty()), rest);
+    }
+    let pinned = system.iter().any(Message::is_pinned).then_some(true);
+    let mut merged = Vec::with_capacity(rest.len() + 1);
+    merged.push(Message {
+        role: "system".to_string(),
+        content: Value::String(text),
+        pinned,
+    });
+    merged.extend(rest);
+    (None, merged)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn message(role: &str, content: Value) -> Message {
+        Message {
+            role: role.to_string(),
+            content,
+            pinned: None,
+        }
+    }
+
+    fn text(role: &str, content: &str) -> Message {
+        message(role, Value::String(content.to_string()))
+    }
+
+    #[test]
+    fn merges_adjacent_string_messages_with_newline() {
+        let merged = merge_consecutive_roles(vec![
+            text("user", "first"),
+            text("user", "second"),
+            text("assistant", "reply"),
+        ]);
+        assert_eq!(merged.len(), 2);
+        assert_eq!(
+            merged[0].content,
+            Value::String("first\nsecond".to_string())
+        );
+        assert_eq!(merged[1].role, "assistant");
+    }
+
+    #[test]
+    fn does_not_merge_across_array_content() {
+        let parts = serde_json::json!([{ "type": "text", "text": "look" }]);
+        let merged =
+            merge_consecutive_roles(vec![text("user", "first"), message("user", parts.clone())]);
+        assert_eq!(merged.len(), 2);
+        assert_eq!(merged[1].content, parts);
+    }
+
+    #[test]
+    fn leaves_alternating_roles_unchanged() {
+        let messages = vec![
+            text("system", "be brief"),
+            text("user", "hi"),
+            text("assistant", "hello"),
+            text("user", "bye"),
+        ];
+        let merged = merge_consecutive_roles(messages.clone());
+        let contents: Vec<&Value> = merged.iter().map(|m| &m.content).collect();
+        let expected: Vec<&Value> = messages.iter().map(|m| &m.content).collect();
+        assert_eq!(contents, expected);
+    }
+
+    #[test]
+    fn hash_ignores_object_key_order() {
+        let a: Value = serde_json::from_str(
+            r#"[{"type":"image_url","image_url":{"url":"data:x","detail":"low"}}]"#,
+        )
+        .unwrap();
+        let b: Value = serde_json::from_str(
+            r#"[{"image_url":{"detail":"low","url":"data:x"},"type":"image_url"}]"#,
+        )
+        .unwrap();
+        assert_eq!(
+            conversation_hash(vec![message("user", a)]),
+            conversation_hash(vec![message("user", b)])
+        );
+    }
+
+    #[test]
+    fn hash_ignores_pinned_flag() {
+        let mut pinned = text("user", "hi");
+        pinned.pinned = Some(true);
+        assert_eq!(
+            conversation_hash(vec![text("user", "hi")]),
+            conversation_hash(vec![pinned])
+        );
+    }
+
+    #[test]
+    fn hash_changes_with_content_and_role() {
+        let base = conversation_hash(vec![text("user", "hi")]);
+        assert_ne!(base, conversation_hash(vec![text("user", "hi!")]));
+        assert_ne!(base, conversation_hash(vec![text("system", "hi")]));
+    }
+
+    #[test]
+    fn merged_message_keeps_pin() {
+        let mut pinned = text("user", "second");
+        pinned.pinned = Some(true);
+        let merged = merge_consecutive_roles(vec![text("user", "first"), pinned]);
+        assert!(merged[0].is_pinned());
+    }
+
+    fn pinned(role: &str, content: &str) -> Message {
+        Message {
+            pinned: Some(true),
+            ..text(role, content)
+        }
+    }
+
+    fn contents(messages: &[Message]) -> Vec<&str> {
+        messages.iter().filter_map(|m| m.content.as_str()).collect()
+    }
+
+    #[test]
+    fn pinned_messages_survive_aggressive_trimming() {
+        let messages = vec![
+            text("system", "You are helpful"),
+            text("user", "old question"),
+            pinned("user", "My name is Ada"),
+            text("assistant", "old answer"),
+            text("user", "What is my name?"),
+        ];
+        let trimmed = trim_messages(messages, 0);
+        assert_eq!(
+            contents(&trimmed),
+            ["You are helpful", "My name is Ada", "What is my name?"]
+        );
+        assert!(trimmed[1].is_pinned());
+    }
+
+    #[test]
+    fn trims_oldest_unpinned_turns_first() {
+        let messages = vec![
+            pinned("user", "fact"),
+            text("user", "first"),
+            text("assistant", "second"),
+            text("user", "third"),
+        ];
+        // 去掉一条非置顶消息后恰好不超过上限
+        let budget = estimate_tokens(&messages) - estimate_tokens(&messages[1..2]);
+        assert_eq!(
+            contents(&trim_messages(messages, budget)),
+            ["fact", "second", "third"]
+        );
+    }
+
+    #[test]
+    fn within_budget_is_unchanged() {
+        let messages = vec![text("user", "hi"), text("assistant", "hello")];
+        let budget = estimate_tokens(&messages);
+        assert_eq!(trim_messages(messages, budget).len(), 2);
+    }
+
+    #[test]
+    fn anthropic_system_messages_become_top_level_string() {
+        let messages = vec![
+            text("system", "base"),
+            text("system", "  "),
+            message(
+                "system",
+                serde_json::json!([{ "type": "text", "text": "persona" }]),
+            ),
+            text("user", "hi"),
+            text("system", "late"),
+        ];
+        let (system, rest) = coalesce_system_messages(messages, Provider::Anthropic);
+        assert_eq!(system.as_deref(), Some("base\n\npersona"));
+        // 对话开始后的 system 消息不参与合并
+        assert_eq!(contents(&rest), ["hi", "late"]);
+    }
+
+    #[test]
+    fn other_providers_get_one_leading_system_message() {
+        for provider in [Provider::OpenAi, Provider::Generic] {
+            let messages = vec![
+                text("system", "base"),
+                pinned("system", "persona"),
+                text("user", "hi"),
+            ];
+            let (system, merged) = coalesce_system_messages(messages, provider);
+            assert_eq!(system, None);
+            assert_eq!(contents(&merged), ["base\n\npersona", "hi"]);
+            assert_eq!(merged[0].role, "system");
+            assert!(merged[0].is_pinned());
+        }
+    }
+
+    #[test]
+    fn without_system_messages_nothing_changes() {
+        let (system, rest) =
+            coalesce_system_messages(vec![text("user", "hi")], Provider::Anthropic);
+        assert_eq!(system, None);
+        assert_eq!(contents(&rest), ["hi"]);
+
+        let (system, rest) =
+            coalesce_system_messages(vec![text("system", " ")], Provider::Anthropic);
+        assert_eq!(system, None);
+        assert!(rest.is_empty());
+    }
+}
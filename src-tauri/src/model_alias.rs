@@ -0,0 +1,226 @@
+use crate::provider::Provider;
+
+struct ModelAlias {
+    canonical: &'static str,
+    // 聚合服务（OpenRouter 等）使用的厂商前缀
+    vendor: &'static str,
+    aliases: &'static [&'static str],
+}
+
+// 常见模型的规范名称及用户常写错的形式，匹配时不区分大小写
+const ALIASES: &[ModelAlias] = &[
+    ModelAlias {
+        canonical: "gpt-4o",
+        vendor: "openai",
+        aliases: &["gpt4o", "gpt-4-o"],
+    },
+    ModelAlias {
+        canonical: "gpt-4o-mini",
+        vendor: "openai",
+        aliases: &["gpt4o-mini", "gpt-4-o-mini"],
+    },
+    ModelAlias {
+        canonical: "gpt-4.1",
+        vendor: "openai",
+        aliases: &["gpt4.1", "gpt-41"],
+    },
+    ModelAlias {
+        canonical: "gpt-4.1-mini",
+        vendor: "openai",
+        aliases: &["gpt4.1-mini", "gpt-41-mini"],
+    },
+    ModelAlias {
+        canonical: "gpt-4-turbo",
+        vendor: "openai",
+        aliases: &["gpt4-turbo"],
+    },
+    ModelAlias {
+        canonical: "gpt-3.5-turbo",
+        vendor: "openai",
+        aliases: &["gpt-35-turbo", "gpt3.5-turbo"],
+    },
+    ModelAlias {
+        canonical: "o1",
+        vendor: "openai",
+        aliases: &[],
+    },
+    ModelAlias {
+        canonical: "o1-mini",
+        vendor: "openai",
+        aliases: &[],
+    },
+    ModelAlias {
+        canonical: "o3",
+        vendor: "openai",
+        aliases: &[],
+    },
+    ModelAlias {
+        canonical: "o3-mini",
+        vendor: "openai",
+        aliases: &[],
+    },
+    ModelAlias {
+        canonical: "o4-mini",
+        vendor: "openai",
+        aliases: &[],
+    },
+    ModelAlias {
+        canonical: "claude-3-7-sonnet-latest",
+        vendor: "anthropic",
+        aliases: &["claude-3.7-sonnet", "claude-3-7-sonnet"],
+    },
+    ModelAlias {
+        canonical: "claude-3-5-sonnet-latest",
+        vendor: "anthropic",
+        aliases: &["claude-3.5-sonnet", "claude-3-5-sonnet"],
+    },
+    ModelAlias {
+        canonical: "claude-3-5-haiku-latest",
+        vendor: "anthropic",
+        aliases: &["claude-3.5-haiku", "claude-3-5-haiku"],
+    },
+    ModelAlias {
+        canonical: "claude-3-opus-latest",
+        vendor: "anthropic",
+        aliases: &["claude-3-opus"],
+    },
+    ModelAlias {
+        canonical: "gemini-2.5-pro",
+        vendor: "google",
+        aliases: &["gemini-25-pro"],
+    },
+    ModelAlias {
+        canonical: "gemini-2.5-flash",
+        vendor: "google",
+        aliases: &["gemini-25-flash"],
+    },
+    ModelAlias {
+        canonical: "gemini-2.0-flash",
+        vendor: "google",
+        aliases: &["gemini-20-flash", "gemini-2-flash"],
+    },
+    ModelAlias {
+        canonical: "gemini-1.5-pro",
+        vendor: "google",
+        aliases: &["gemini-15-pro"],
+    },
+    ModelAlias {
+        canonical: "gemini-1.5-flash",
+        vendor: "google",
+        aliases: &["gemini-15-flash"],
+    },
+    ModelAlias {
+        canonical: "deepseek-chat",
+        vendor: "deepseek",
+        aliases: &["deepseek-v3"],
+    },
+    ModelAlias {
+        canonical: "deepseek-reasoner",
+        vendor: "deepseek",
+        aliases: &["deepseek-r1"],
+    },
+];
+
+// 可以去掉的前缀；Gemini 原生接口返回的模型名带 models/
+const PREFIXES: &[&str] = &[
+    "openai/",
+    "azure/",
+    "anthropic/",
+    "google/",
+    "gemini/",
+    "models/",
+    "deepseek/",
+];
+
+fn lookup(name: &str) -> Option<&'static ModelAlias> {
+    ALIASES.iter().find(|alias| {
+        alias.canonical.eq_ignore_ascii_case(name)
+            || alias
+                .aliases
+                .iter()
+                .any(|candidate| candidate.eq_ignore_ascii_case(name))
+    })
+}
+
+// 把大小写或前缀不一致的模型名转成服务商期望的写法。
+// 直连官方接口时去掉厂商前缀；其他兼容服务（多为聚合服务）原本带前缀时换成规范前缀，
+// 不带时保持不带。不在别名表中的名称原样返回
+#[tauri::command]
+pub fn canonicalize_model(provider: Provider, input: String) -> String {
+    let trimmed = input.trim();
+    let prefix = PREFIXES.iter().find(|prefix| {
+        trimmed
+            .get(..prefix.len())
+            .is_some_and(|head| head.eq_ignore_ascii_case(prefix))
+    });
+    let (prefixed, name) = match prefix {
+        Some(prefix) => (true, &trimmed[prefix.len()..]),
+        None => (false, trimmed),
+    };
+    let Some(alias) = lookup(name) else {
+        return input;
+    };
+    match provider {
+        Provider::Generic if prefixed => format!("{}/{}", alias.vendor, alias.canonical),
+        _ => alias.canonical.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn canonical(provider: Provider, input: &str) -> String {
+        canonicalize_model(provider, input.to_string())
+    }
+
+    #[test]
+    fn fixes_case_and_common_misspellings() {
+        assert_eq!(canonical(Provider::OpenAi, "GPT4o"), "gpt-4o");
+        assert_eq!(
+            canonical(Provider::OpenAi, " gpt-35-turbo "),
+            "gpt-3.5-turbo"
+        );
+        assert_eq!(
+            canonical(Provider::Anthropic, "claude-3.5-sonnet"),
+            "claude-3-5-sonnet-latest"
+        );
+    }
+
+    #[test]
+    fn official_providers_drop_vendor_prefix() {
+        assert_eq!(
+            canonical(Provider::OpenAi, "openai/gpt-4o-mini"),
+            "gpt-4o-mini"
+        );
+        assert_eq!(
+            canonical(Provider::Gemini, "models/gemini-25-flash"),
+            "gemini-2.5-flash"
+        );
+    }
+
+    #[test]
+    fn generic_provider_keeps_prefix_only_when_given() {
+        // 聚合服务使用 google/ 而不是 gemini/ 前缀
+        assert_eq!(
+            canonical(Provider::Generic, "Gemini/gemini-2-flash"),
+            "google/gemini-2.0-flash"
+        );
+        assert_eq!(
+            canonical(Provider::Generic, "deepseek-r1"),
+            "deepseek-reasoner"
+        );
+    }
+
+    #[test]
+    fn unknown_names_are_returned_unchanged() {
+        assert_eq!(
+            canonical(Provider::OpenAi, " my-finetune "),
+            " my-finetune "
+        );
+        assert_eq!(
+            canonical(Provider::Generic, "openai/my-finetune"),
+            "openai/my-finetune"
+        );
+    }
+}
@@ -0,0 +1,198 @@
+use std::collections::HashMap;
+use std::time::Duration;
+
+use serde::Serialize;
+use tauri::{AppHandle, Manager};
+
+use crate::client::HttpClient;
+use crate::{calibration, output_limits, pricing, Message};
+
+const MODELS_TIMEOUT: Duration = Duration::from_secs(15);
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ModelFit {
+    pub model: String,
+    // 上下文窗口未知时为 None，此时 fits / headroom 也为 None
+    pub context_window: Option<u32>,
+    pub max_output_tokens: Option<u32>,
+    pub prompt_tokens: usize,
+    pub max_tokens: u32,
+    pub fits: Option<bool>,
+    // 上下文窗口减去提示词和请求的输出长度，为负表示放不下
+    pub headroom: Option<i64>,
+}
+
+impl ModelFit {
+    fn new(
+        model: String,
+        context_window: Option<u32>,
+        max_output_tokens: Option<u32>,
+        prompt_tokens: usize,
+        max_tokens: u32,
+    ) -> Self {
+        let headroom =
+            context_window.map(|window| window as i64 - prompt_tokens as i64 - max_tokens as i64);
+        // 请求的输出长度超过模型单次输出上限同样视为放不下
+        let fits = headroom.map(|headroom| {
+            headroom >= 0 && max_output_tokens.is_none_or(|limit| max_tokens <= limit)
+        });
+        ModelFit {
+            model,
+            context_window,
+            max_output_tokens,
+            prompt_tokens,
+            max_tokens,
+            fits,
+            headroom,
+        }
+    }
+}
+
+// OpenRouter / Groq / vLLM 等服务在 /models 中给出上下文长度，字段名各不相同
+fn context_from_model_entry(entry: &serde_json::Value) -> Option<u32> {
+    ["context_length", "context_window", "max_model_len"]
+        .iter()
+        .find_map(|field| entry[field].as_u64())
+        .or_else(|| entry["top_provider"]["context_length"].as_u64())
+        .and_then(|limit| u32::try_from(limit).ok())
+}
+
+// 尽力而为：服务不提供 /models 或没有上下文长度时返回空表
+async fn provider_context_windows(
+    client: &reqwest::Client,
+    base_url: &str,
+    api_key: &str,
+) -> HashMap<String, u32> {
+    let url = format!("{}/models", base_url);
+    let response = client
+        .get(&url)
+        .header("Authorization", format!("Bearer {}", api_key))
+        .timeout(MODELS_TIMEOUT)
+        .send()
+        .await;
+    let body = match response {
+        Ok(response) if response.status().is_success() => {
+            response.json::<serde_json::Value>().await.ok()
+        }
+        Ok(response) => {
+            log::warn!("{} returned {}", url, response.status());
+            None
+        }
+        Err(e) => {
+            log::warn!("Failed to list models at {}: {}", url, e);
+            None
+        }
+    };
+    body.iter()
+        .flat_map(|body| body["data"].as_array().into_iter().flatten())
+        .filter_map(|entry| {
+            Some((
+                entry["id"].as_str()?.to_string(),
+                context_from_model_entry(entry)?,
+            ))
+        })
+        .collect()
+}
+
+// 对每个候选模型估算提示词加上 max_tokens 是否放得进上下文窗口。
+// 上下文窗口优先取服务端 /models 返回的值，其次取价格表中的值；结果按候选顺序返回
+#[tauri::command]
+pub async fn suggest_model(
+    base_url: String,
+    api_key: String,
+    candidate_models: Vec<String>,
+    messages: Vec<Message>,
+    max_tokens: u32,
+    app_handle: AppHandle,
+) -> Result<Vec<ModelFit>, String> {
+    if candidate_models.is_empty() {
+        return Err("At least one candidate model is required".to_string());
+    }
+    let client = app_handle
+        .state::<HttpClient>()
+        .client_for(&format!("{}/models", base_url))?;
+    let provider_windows = provider_context_windows(&client, &base_url, &api_key).await;
+
+    Ok(candidate_models
+        .into_iter()
+        .map(|model| {
+            let prompt_tokens = calibration::count(&app_handle, &model, &messages);
+            let price = pricing::price(&app_handle, &model);
+            let context_window = provider_windows
+                .get(&model)
+                .copied()
+                .or_else(|| price.and_then(|p| p.max_input_tokens));
+            let max_output_tokens = output_limits::probed(&app_handle, &model)
+                .or_else(|| price.and_then(|p| p.max_output_tokens));
+            ModelFit::new(
+                model,
+                context_window,
+                max_output_tokens,
+                prompt_tokens,
+                max_tokens,
+            )
+        })
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn fit(context_window: Option<u32>, max_output_tokens: Option<u32>) -> ModelFit {
+        ModelFit::new(
+            "model".to_string(),
+            context_window,
+            max_output_tokens,
+            6_000,
+            2_000,
+        )
+    }
+
+    #[test]
+    fn headroom_is_window_minus_prompt_and_output() {
+        let roomy = fit(Some(8_192), None);
+        assert_eq!(roomy.headroom, Some(192));
+        assert_eq!(roomy.fits, Some(true));
+
+        let exact = fit(Some(8_000), None);
+        assert_eq!(exact.headroom, Some(0));
+        assert_eq!(exact.fits, Some(true));
+
+        let tight = fit(Some(4_096), None);
+        assert_eq!(tight.headroom, Some(-3_904));
+        assert_eq!(tight.fits, Some(false));
+    }
+
+    #[test]
+    fn output_limit_below_max_tokens_does_not_fit() {
+        let limited = fit(Some(128_000), Some(1_024));
+        assert_eq!(limited.headroom, Some(120_000));
+        assert_eq!(limited.fits, Some(false));
+        assert_eq!(fit(Some(128_000), Some(4_096)).fits, Some(true));
+    }
+
+    #[test]
+    fn unknown_window_leaves_fit_unknown() {
+        let unknown = fit(None, Some(1_024));
+        assert_eq!((unknown.headroom, unknown.fits), (None, None));
+    }
+
+    #[test]
+    fn reads_context_length_from_model_entries() {
+        assert_eq!(
+            context_from_model_entry(&json!({ "context_length": 32_768 })),
+            Some(32_768)
+        );
+        assert_eq!(
+            context_from_model_entry(&json!({ "max_model_len": 4_096 })),
+            Some(4_096)
+        );
+        assert_eq!(
+            context_from_model_entry(&json!({ "top_provider": { "context_length": 200_000 } })),
+            Some(200_000)
+        );
+        assert_eq!(context_from_model_entry(&json!({ "id": "gpt-4o" })), None);
+    }
+}
@@ -0,0 +1,65 @@
+use std::collections::HashMap;
+
+use serde::{Deserialize, Serialize};
+
+use crate::client::{self, HttpClient};
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ModerationResult {
+    pub flagged: bool,
+    pub categories: HashMap<String, bool>,
+    pub scores: HashMap<String, f32>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ModerationResponse {
+    results: Vec<ModerationEntry>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ModerationEntry {
+    #[serde(default)]
+    flagged: bool,
+    #[serde(default)]
+    categories: HashMap<String, bool>,
+    #[serde(default)]
+    category_scores: HashMap<String, f32>,
+}
+
+// 发送前用服务端的 /moderations 接口审核用户输入
+#[tauri::command]
+pub async fn moderate_content(
+    base_url: String,
+    api_key: String,
+    input: String,
+    http: tauri::State<'_, HttpClient>,
+) -> Result<ModerationResult, String> {
+    let url = format!("{}/moderations", base_url);
+    let client = http.client_for(&url)?;
+
+    let response = client
+        .post(&url)
+        .header("Content-Type", "application/json")
+        .header("Authorization", format!("Bearer {}", api_key))
+        .json(&serde_json::json!({ "input": input }))
+        .send()
+        .await
+        .map_err(|e| format!("Failed to send request: {}", e))?;
+    let response = client::check_supported(response, &base_url, "Moderation").await?;
+
+    let parsed = response
+        .json::<ModerationResponse>()
+        .await
+        .map_err(|e| format!("Failed to parse response: {}", e))?;
+    let entry = parsed
+        .results
+        .into_iter()
+        .next()
+        .ok_or_else(|| "Moderation response contained no results".to_string())?;
+
+    Ok(ModerationResult {
+        flagged: entry.flagged,
+        categories: entry.categories,
+        scores: entry.category_scores,
+    })
+}
@@ -0,0 +1,172 @@
+use std::collections::HashMap;
+use std::fs;
+use std::path::PathBuf;
+use std::sync::{OnceLock, RwLock};
+
+use regex::Regex;
+use tauri::{AppHandle, Manager};
+
+use crate::client::{self, HttpClient};
+
+// 第一次探测请求的 max_tokens，远大于目前任何模型的输出上限
+const PROBE_CEILING: u32 = 1_000_000;
+// 二分查找最多的请求次数，log2(PROBE_CEILING) 约为 20
+const MAX_SEARCH_STEPS: u32 = 24;
+
+// 模型名 -> 探测到的最大输出 token 数，启动时从磁盘加载
+#[derive(Default)]
+pub struct ProbedOutputLimits(RwLock<HashMap<String, u32>>);
+
+enum Attempt {
+    Accepted,
+    // 服务端以 4xx 拒绝，附带错误信息
+    Rejected(String),
+}
+
+fn limits_path(app_handle: &AppHandle) -> Result<PathBuf, String> {
+    let dir = app_handle
+        .path()
+        .app_data_dir()
+        .map_err(|e| format!("Failed to resolve app data dir: {}", e))?;
+    fs::create_dir_all(&dir).map_err(|e| format!("Failed to create app data dir: {}", e))?;
+    Ok(dir.join("output_limits.json"))
+}
+
+fn save(app_handle: &AppHandle, limits: &HashMap<String, u32>) -> Result<(), String> {
+    let path = limits_path(app_handle)?;
+    let text = serde_json::to_string_pretty(limits)
+        .map_err(|e| format!("Failed to serialize output limits: {}", e))?;
+    let tmp = path.with_extension("json.tmp");
+    fs::write(&tmp, text).map_err(|e| format!("Failed to write output limits: {}", e))?;
+    fs::rename(&tmp, &path).map_err(|e| format!("Failed to write output limits: {}", e))
+}
+
+pub fn init(app_handle: &AppHandle) {
+    let limits = limits_path(app_handle)
+        .ok()
+        .and_then(|path| fs::read_to_string(path).ok())
+        .and_then(|text| serde_json::from_str::<HashMap<String, u32>>(&text).ok());
+    if let Some(limits) = limits {
+        *app_handle.state::<ProbedOutputLimits>().0.write().unwrap() = limits;
+    }
+}
+
+pub fn probed(app_handle: &AppHandle, model: &str) -> Option<u32> {
+    app_handle
+        .state::<ProbedOutputLimits>()
+        .0
+        .read()
+        .unwrap()
+        .get(model)
+        .copied()
+}
+
+// 从错误信息中找出服务端声明的上限，例如
+// "This model supports at most 16384 completion tokens"、"max_tokens: 1000000 > 8192"、
+// "the valid range of max_tokens is [1, 8192]"：取小于请求值的最大数字
+fn stated_limit(message: &str, requested: u32) -> Option<u32> {
+    static NUMBER: OnceLock<Regex> = OnceLock::new();
+    NUMBER
+        .get_or_init(|| Regex::new(r"\d{1,3}(?:,\d{3})+|\d+").unwrap())
+        .find_iter(message)
+        .filter_map(|m| m.as_str().replace(',', "").parse::<u32>().ok())
+        .filter(|&n| n > 1 && n < requested)
+        .max()
+}
+
+async fn attempt(
+    client: &reqwest::Client,
+    url: &str,
+    api_key: &str,
+    model: &str,
+    max_tokens: u32,
+) -> Result<Attempt, String> {
+    let body = serde_json::json!({
+        "model": model,
+        "messages": [{ "role": "user", "content": "Reply with OK." }],
+        "max_tokens": max_tokens,
+        "stream": false,
+    });
+    let response = client
+        .post(url)
+        .header("Content-Type", "application/json")
+        .header("Authorization", format!("Bearer {}", api_key))
+        .json(&body)
+        .send()
+        .await
+        .map_err(|e| format!("Failed to send request: {}", e))?;
+    let status = response.status();
+    if status.is_success() {
+        return Ok(Attempt::Accepted);
+    }
+    // 鉴权、模型不存在、限流等错误与 max_tokens 无关，直接报错
+    let unrelated = matches!(status.as_u16(), 401 | 403 | 404 | 429);
+    if !status.is_client_error() || unrelated {
+        return Err(client::api_error(response).await);
+    }
+    Ok(Attempt::Rejected(response.text().await.unwrap_or_default()))
+}
+
+// 探测模型单次输出的最大 token 数：先请求一个极大的 max_tokens，
+// 服务端拒绝时优先采用错误信息中声明的上限（验证后），否则按接受 / 拒绝二分查找。
+// 服务端接受极大值（不限制或静默截断）时返回该值。结果按模型缓存
+#[tauri::command]
+pub async fn probe_max_output(
+    base_url: String,
+    api_key: String,
+    model: String,
+    app_handle: AppHandle,
+) -> Result<u32, String> {
+    let url = format!("{}/chat/completions", base_url);
+    let client = app_handle.state::<HttpClient>().client_for(&url)?;
+
+    // 最小的请求都被拒绝时，后续的拒绝无法归因于 max_tokens
+    if let Attempt::Rejected(message) = attempt(&client, &url, &api_key, &model, 1).await? {
+        return Err(format!("{} rejected a minimal request: {}", model, message));
+    }
+
+    // lo 为已知接受的最大值，hi 为已知拒绝的最小值
+    let mut lo = 1;
+    let mut hi = match attempt(&client, &url, &api_key, &model, PROBE_CEILING).await? {
+        Attempt::Accepted => {
+            lo = PROBE_CEILING;
+            PROBE_CEILING + 1
+        }
+        Attempt::Rejected(message) => {
+            match stated_limit(&message, PROBE_CEILING) {
+                Some(stated) => match attempt(&client, &url, &api_key, &model, stated).await? {
+                    Attempt::Accepted => {
+                        lo = stated;
+                        // 声明的上限本身被接受且再多一个就被拒绝，即为准确值
+                        match attempt(&client, &url, &api_key, &model, stated + 1).await? {
+                            Attempt::Accepted => {
+                                lo = stated + 1;
+                                PROBE_CEILING
+                            }
+                            Attempt::Rejected(_) => stated + 1,
+                        }
+                    }
+                    Attempt::Rejected(_) => stated,
+                },
+                None => PROBE_CEILING,
+            }
+        }
+    };
+
+    // 步数用尽时返回已确认可用的下界
+    let mut steps = 0;
+    while hi - lo > 1 && steps < MAX_SEARCH_STEPS {
+        let mid = lo + (hi - lo) / 2;
+        match attempt(&client, &url, &api_key, &model, mid).await? {
+            Attempt::Accepted => lo = mid,
+            Attempt::Rejected(_) => hi = mid,
+        }
+        steps += 1;
+    }
+
+    let limits = app_handle.state::<ProbedOutputLimits>();
+    let mut limits = limits.0.write().unwrap();
+    limits.insert(model, lo);
+    save(&app_handle, &limits)?;
+    Ok(lo)
+}
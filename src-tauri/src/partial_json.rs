@@ -0,0 +1,222 @@
+use serde_json::Value;
+use std::time::{Duration, Instant};
+
+// 流式解析时累积内容每增长这么多字节或每隔这么久才重新解析一次，
+// 避免每个增量都从头解析整段内容
+const REPARSE_BYTES: usize = 256;
+const REPARSE_INTERVAL: Duration = Duration::from_millis(100);
+
+// 尽力解析尚未生成完的 JSON：补全未闭合的字符串和括号，失败时回退到上一个安全截断点
+pub fn parse_partial(input: &str) -> Option<Value> {
+    // 模型常用 ```json 代码块包裹，从第一个 { 或 [ 开始解析
+    let start = input.find(['{', '['])?;
+    let input = &input[start..];
+
+    if let Ok(value) = serde_json::from_str(input) {
+        return Some(value);
+    }
+
+    let mut cut_points = cut_points(input);
+    let mut end = input.len();
+    loop {
+        if let Some(value) = close(&input[..end]).and_then(|s| serde_json::from_str(&s).ok()) {
+            return Some(value);
+        }
+        end = cut_points.pop()?;
+    }
+}
+
+// 字符串外的 , { [ 位置：在逗号前截断、在括号后截断都能得到结构完整的前缀
+fn cut_points(input: &str) -> Vec<usize> {
+    let mut points = Vec::new();
+    let mut in_string = false;
+    let mut escaped = false;
+
+    for (i, c) in input.char_indices() {
+        if in_string {
+            match c {
+                _ if escaped => escaped = false,
+                '\\' => escaped = true,
+                '"' => in_string = false,
+                _ => {}
+            }
+            continue;
+        }
+        match c {
+            '"' => in_string = true,
+            ',' => points.push(i),
+            '{' | '[' => points.push(i + 1),
+            _ => {}
+        }
+    }
+    points
+}
+
+fn close(prefix: &str) -> Option<String> {
+    let mut stack = Vec::new();
+    let mut in_string = false;
+    let mut escaped = false;
+
+    for c in prefix.chars() {
+        if in_string {
+            match c {
+                _ if escaped => escaped = false,
+                '\\' => escaped = true,
+                '"' => in_string = false,
+                _ => {}
+            }
+            continue;
+        }
+        match c {
+            '"' => in_string = true,
+            '{' => stack.push('}'),
+            '[' => stack.push(']'),
+            '}' | ']' if stack.pop() != Some(c) => return None,
+            _ => {}
+        }
+    }
+
+    let mut closed = prefix.to_string();
+    if in_string {
+        // 末尾半个转义符无法补全，直接丢掉
+        if escaped {
+            closed.pop();
+        }
+        closed.push('"');
+    }
+
+    let trimmed_len = closed.trim_end().len();
+    closed.truncate(trimmed_len);
+    if closed.ends_with(',') {
+        closed.pop();
+    } else if closed.ends_with(':') {
+        closed.push_str("null");
+    }
+
+    while let Some(c) = stack.pop() {
+        closed.push(c);
+    }
+    Some(closed)
+}
+
+// 累积流式内容并节流地解析，只在解析结果变化时返回新值
+pub struct PartialParser {
+    text: String,
+    parsed_len: usize,
+    parsed_at: Instant,
+    last: Option<Value>,
+}
+
+impl PartialParser {
+    pub fn new() -> Self {
+        Self {
+            text: String::new(),
+            parsed_len: 0,
+            parsed_at: Instant::now(),
+            last: None,
+        }
+    }
+
+    pub fn push(&mut self, delta: &str) -> Option<Value> {
+        self.text.push_str(delta);
+        if self.text.len() - self.parsed_len < REPARSE_BYTES
+            && self.parsed_at.elapsed() < REPARSE_INTERVAL
+        {
+            return None;
+        }
+        self.parse()
+    }
+
+    // 流结束时解析节流期间还没解析过的尾部内容
+    pub fn finish(&mut self) -> Option<Value> {
+        if self.parsed_len == self.text.len() {
+            return None;
+        }
+        self.parse()
+    }
+
+    fn parse(&mut self) -> Option<Value> {
+        self.parsed_len = self.text.len();
+        self.parsed_at = Instant::now();
+        let partial = parse_partial(&self.text);
+        if partial.is_none() || partial == self.last {
+            return None;
+        }
+        self.last = partial.clone();
+        partial
+    }
+}
+
+// 修复因长度截断而不完整的工具调用参数，尽量得到可用的 JSON
+#[tauri::command]
+pub fn repair_tool_arguments(partial: String) -> Result<Value, String> {
+    // 无参数的工具可能返回空字符串
+    if partial.trim().is_empty() {
+        return Ok(Value::Object(Default::default()));
+    }
+    parse_partial(&partial).ok_or_else(|| format!("Unable to repair tool arguments: {}", partial))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn repair(partial: &str) -> Result<Value, String> {
+        repair_tool_arguments(partial.to_string())
+    }
+
+    #[test]
+    fn complete_arguments_are_unchanged() {
+        assert_eq!(
+            repair(r#"{"city":"Paris"}"#),
+            Ok(json!({ "city": "Paris" }))
+        );
+    }
+
+    #[test]
+    fn closes_unterminated_string_and_object() {
+        assert_eq!(repair(r#"{"city": "Par"#), Ok(json!({ "city": "Par" })));
+        assert_eq!(repair(r#"{"q": "say \"hi"#), Ok(json!({ "q": "say \"hi" })));
+    }
+
+    #[test]
+    fn handles_trailing_comma_and_dangling_key() {
+        assert_eq!(repair(r#"{"a": 1,"#), Ok(json!({ "a": 1 })));
+        assert_eq!(repair(r#"{"a":"#), Ok(json!({ "a": null })));
+    }
+
+    #[test]
+    fn falls_back_to_last_safe_cut_point() {
+        assert_eq!(
+            repair(r#"{"a": [1, 2, {"b": tru"#),
+            Ok(json!({ "a": [1, 2, {}] }))
+        );
+    }
+
+    #[test]
+    fn empty_arguments_become_empty_object() {
+        assert_eq!(repair("  "), Ok(json!({})));
+    }
+
+    #[test]
+    fn unrecoverable_input_is_an_error() {
+        assert!(repair("not json").is_err());
+    }
+
+    #[test]
+    fn parser_reparses_in_steps_and_catches_up_on_finish() {
+        let text = format!(r#"{{"items": [{}]}}"#, vec!["\"abcdefgh\""; 200].join(", "));
+        let mut parser = PartialParser::new();
+        let mut updates = 0;
+        for c in text.chars() {
+            if parser.push(&c.to_string()).is_some() {
+                updates += 1;
+            }
+        }
+        assert!(updates < text.len() / 100, "{} updates", updates);
+        let last = parser.finish().or(parser.last.clone()).unwrap();
+        assert_eq!(last["items"].as_array().unwrap().len(), 200);
+        assert_eq!(parser.finish(), None);
+    }
+}
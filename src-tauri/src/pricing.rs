@@ -0,0 +1,165 @@
+use std::collections::HashMap;
+use std::fs;
+use std::path::PathBuf;
+use std::sync::RwLock;
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+use tauri::{AppHandle, Manager};
+
+use crate::client::HttpClient;
+
+// 社区维护的价格表（LiteLLM），每个模型给出每 token 的输入 / 输出价格（美元）
+const DEFAULT_SOURCE: &str =
+    "https://raw.githubusercontent.com/BerriAI/litellm/main/model_prices_and_context_window.json";
+const FETCH_TIMEOUT: Duration = Duration::from_secs(30);
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct ModelPrice {
+    pub input_cost_per_token: f64,
+    pub output_cost_per_token: f64,
+    // 上下文窗口和单次输出上限（token），价格表未给出时为 None
+    #[serde(default)]
+    pub max_input_tokens: Option<u32>,
+    #[serde(default)]
+    pub max_output_tokens: Option<u32>,
+}
+
+// 模型名 -> 价格，启动时从磁盘缓存加载
+#[derive(Default)]
+pub struct PricingTable(RwLock<HashMap<String, ModelPrice>>);
+
+fn cache_path(app_handle: &AppHandle) -> Result<PathBuf, String> {
+    let dir = app_handle
+        .path()
+        .app_data_dir()
+        .map_err(|e| format!("Failed to resolve app data dir: {}", e))?;
+    fs::create_dir_all(&dir).map_err(|e| format!("Failed to create app data dir: {}", e))?;
+    Ok(dir.join("pricing.json"))
+}
+
+fn load_cache(app_handle: &AppHandle) -> Result<HashMap<String, ModelPrice>, String> {
+    let path = cache_path(app_handle)?;
+    let text =
+        fs::read_to_string(&path).map_err(|e| format!("Failed to read pricing cache: {}", e))?;
+    serde_json::from_str(&text).map_err(|e| format!("Failed to parse pricing cache: {}", e))
+}
+
+fn save_cache(app_handle: &AppHandle, prices: &HashMap<String, ModelPrice>) -> Result<(), String> {
+    let path = cache_path(app_handle)?;
+    let text =
+        serde_json::to_string(prices).map_err(|e| format!("Failed to serialize pricing: {}", e))?;
+    let tmp = path.with_extension("json.tmp");
+    fs::write(&tmp, text).map_err(|e| format!("Failed to write pricing cache: {}", e))?;
+    fs::rename(&tmp, &path).map_err(|e| format!("Failed to write pricing cache: {}", e))
+}
+
+fn token_limit(value: &serde_json::Value) -> Option<u32> {
+    value.as_u64().and_then(|limit| u32::try_from(limit).ok())
+}
+
+// 只保留同时给出输入、输出价格的条目；sample_spec 等说明性条目自然被跳过
+fn parse(source: &serde_json::Value) -> HashMap<String, ModelPrice> {
+    source
+        .as_object()
+        .into_iter()
+        .flatten()
+        .filter_map(|(model, entry)| {
+            let price = ModelPrice {
+                input_cost_per_token: entry["input_cost_per_token"].as_f64()?,
+                output_cost_per_token: entry["output_cost_per_token"].as_f64()?,
+                max_input_tokens: token_limit(&entry["max_input_tokens"]),
+                max_output_tokens: token_limit(&entry["max_output_tokens"])
+                    .or_else(|| token_limit(&entry["max_tokens"])),
+            };
+            Some((model.clone(), price))
+        })
+        .collect()
+}
+
+async fn fetch(url: &str, app_handle: &AppHandle) -> Result<HashMap<String, ModelPrice>, String> {
+    let client = app_handle.state::<HttpClient>().client_for(url)?;
+    let response = client
+        .get(url)
+        .timeout(FETCH_TIMEOUT)
+        .send()
+        .await
+        .map_err(|e| format!("Failed to fetch pricing: {}", e))?;
+    if !response.status().is_success() {
+        return Err(crate::client::api_error(response).await);
+    }
+    let source: serde_json::Value = response
+        .json()
+        .await
+        .map_err(|e| format!("Failed to parse pricing: {}", e))?;
+    let prices = parse(&source);
+    if prices.is_empty() {
+        return Err(format!("No model prices found at {}", url));
+    }
+    Ok(prices)
+}
+
+// 启动时加载上次缓存的价格表，没有缓存时为空
+pub fn init(app_handle: &AppHandle) {
+    if let Ok(prices) = load_cache(app_handle) {
+        *app_handle.state::<PricingTable>().0.write().unwrap() = prices;
+    }
+}
+
+// 先查完整模型名，再去掉 openai/ 这类服务商前缀查
+pub fn price(app_handle: &AppHandle, model: &str) -> Option<ModelPrice> {
+    let table = app_handle.state::<PricingTable>();
+    let prices = table.0.read().unwrap();
+    prices.get(model).copied().or_else(|| {
+        let (_, name) = model.rsplit_once('/')?;
+        prices.get(name).copied()
+    })
+}
+
+// 拉取最新价格表并写入缓存，返回加载的模型数；离线时退回磁盘缓存
+#[tauri::command]
+pub async fn refresh_pricing(
+    source_url: Option<String>,
+    app_handle: AppHandle,
+) -> Result<usize, String> {
+    let url = source_url.unwrap_or_else(|| DEFAULT_SOURCE.to_string());
+    let prices = match fetch(&url, &app_handle).await {
+        Ok(prices) => {
+            if let Err(e) = save_cache(&app_handle, &prices) {
+                log::warn!("{}", e);
+            }
+            prices
+        }
+        Err(e) => {
+            log::warn!("{}, falling back to cached pricing", e);
+            load_cache(&app_handle).map_err(|cache_error| format!("{}; {}", e, cache_error))?
+        }
+    };
+    let count = prices.len();
+    *app_handle.state::<PricingTable>().0.write().unwrap() = prices;
+    Ok(count)
+}
+
+#[tauri::command]
+pub fn get_model_price(model: String, app_handle: AppHandle) -> Option<ModelPrice> {
+    price(&app_handle, &model)
+}
+
+// 按价格表估算一次请求的费用（美元），未知模型返回 None。
+// 输出 token 数不会超过模型的输出上限（优先取探测值），超出部分不计费
+#[tauri::command]
+pub fn estimate_cost(
+    model: String,
+    prompt_tokens: u32,
+    completion_tokens: u32,
+    app_handle: AppHandle,
+) -> Option<f64> {
+    let price = price(&app_handle, &model)?;
+    let completion_tokens = crate::output_limits::probed(&app_handle, &model)
+        .or(price.max_output_tokens)
+        .map_or(completion_tokens, |limit| completion_tokens.min(limit));
+    Some(
+        prompt_tokens as f64 * price.input_cost_per_token
+            + completion_tokens as f64 * price.output_cost_per_token,
+    )
+}
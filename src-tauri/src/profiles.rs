@@ -0,0 +1,151 @@
+use std::collections::{BTreeMap, HashMap};
+use std::fs;
+use std::path::PathBuf;
+use std::sync::Mutex;
+
+use serde::{Deserialize, Serialize};
+use tauri::{AppHandle, Manager};
+
+use crate::provider::Provider;
+use crate::ChatOptions;
+
+// 一套常用的服务配置（工作用、个人用、本地 Ollama 等），按名称保存
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ProfileConfig {
+    pub base_url: String,
+    pub model: String,
+    // 附加到请求体的参数，如 { "temperature": 0.7 }；请求中已有的字段不会被覆盖
+    #[serde(default)]
+    pub params: serde_json::Map<String, serde_json::Value>,
+    #[serde(default)]
+    pub provider: Option<Provider>,
+}
+
+// 各配置的 API key 只保存在内存中，不写入磁盘；前端启动后通过 set_profile_key 设置。
+// 定时任务等后台执行的请求按配置名称从这里取 key
+#[derive(Default)]
+pub struct ProfileKeys(Mutex<HashMap<String, String>>);
+
+pub fn api_key(app_handle: &AppHandle, name: &str) -> Result<String, String> {
+    app_handle
+        .state::<ProfileKeys>()
+        .0
+        .lock()
+        .unwrap()
+        .get(name)
+        .cloned()
+        .ok_or_else(|| format!("No API key set for profile: {}", name))
+}
+
+// 所有配置保存在应用数据目录下的 profiles.json 中
+fn profiles_path(app_handle: &AppHandle) -> Result<PathBuf, String> {
+    let dir = app_handle
+        .path()
+        .app_data_dir()
+        .map_err(|e| format!("Failed to resolve app data dir: {}", e))?;
+    fs::create_dir_all(&dir).map_err(|e| format!("Failed to create app data dir: {}", e))?;
+    Ok(dir.join("profiles.json"))
+}
+
+fn load_all(app_handle: &AppHandle) -> Result<BTreeMap<String, ProfileConfig>, String> {
+    let path = profiles_path(app_handle)?;
+    if !path.exists() {
+        return Ok(BTreeMap::new());
+    }
+    let text = fs::read_to_string(&path).map_err(|e| format!("Failed to read profiles: {}", e))?;
+    serde_json::from_str(&text).map_err(|e| format!("Failed to parse profiles: {}", e))
+}
+
+fn save_all(
+    app_handle: &AppHandle,
+    profiles: &BTreeMap<String, ProfileConfig>,
+) -> Result<(), String> {
+    let path = profiles_path(app_handle)?;
+    let text = serde_json::to_string_pretty(profiles)
+        .map_err(|e| format!("Failed to serialize profiles: {}", e))?;
+    let tmp = path.with_extension("json.tmp");
+    fs::write(&tmp, text).map_err(|e| format!("Failed to write profiles: {}", e))?;
+    fs::rename(&tmp, &path).map_err(|e| format!("Failed to write profiles: {}", e))
+}
+
+pub fn load(app_handle: &AppHandle, name: &str) -> Result<ProfileConfig, String> {
+    load_all(app_handle)?
+        .remove(name)
+        .ok_or_else(|| format!("Profile not found: {}", name))
+}
+
+// 聊天命令指定了 profile 时，用它补全未传入的 base_url / model / provider
+pub fn apply(
+    app_handle: &AppHandle,
+    base_url: &mut String,
+    model: &mut String,
+    options: &mut ChatOptions,
+) -> Result<(), String> {
+    let Some(name) = &options.profile else {
+        return Ok(());
+    };
+    let profile = load(app_handle, name)?;
+    if base_url.is_empty() {
+        *base_url = profile.base_url;
+    }
+    if model.is_empty() {
+        *model = profile.model;
+    }
+    if options.provider.is_none() {
+        options.provider = profile.provider;
+    }
+    options.profile_params = profile.params;
+    Ok(())
+}
+
+#[tauri::command]
+pub fn save_profile(
+    name: String,
+    config: ProfileConfig,
+    app_handle: AppHandle,
+) -> Result<(), String> {
+    let name = name.trim().to_string();
+    if name.is_empty() {
+        return Err("Profile name cannot be empty".to_string());
+    }
+    let mut profiles = load_all(&app_handle)?;
+    profiles.insert(name, config);
+    save_all(&app_handle, &profiles)
+}
+
+#[tauri::command]
+pub fn list_profiles(app_handle: AppHandle) -> Result<Vec<String>, String> {
+    Ok(load_all(&app_handle)?.into_keys().collect())
+}
+
+#[tauri::command]
+pub fn load_profile(name: String, app_handle: AppHandle) -> Result<ProfileConfig, String> {
+    load(&app_handle, &name)
+}
+
+#[tauri::command]
+pub fn set_profile_key(name: String, api_key: String, app_handle: AppHandle) {
+    let keys = app_handle.state::<ProfileKeys>();
+    let mut keys = keys.0.lock().unwrap();
+    if api_key.is_empty() {
+        keys.remove(&name);
+    } else {
+        keys.insert(name, api_key);
+    }
+}
+
+#[tauri::command]
+pub fn delete_profile(name: String, app_handle: AppHandle) -> Result<bool, String> {
+    let mut profiles = load_all(&app_handle)?;
+    let removed = profiles.remove(&name).is_some();
+    app_handle
+        .state::<ProfileKeys>()
+        .0
+        .lock()
+        .unwrap()
+        .remove(&name);
+    if removed {
+        save_all(&app_handle, &profiles)?;
+    }
+    Ok(removed)
+}
@@ -0,0 +1,159 @@
+use serde::{Deserialize, Serialize};
+
+// 各家 OpenAI 兼容接口在请求体细节上并不一致，按服务商区分处理
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Provider {
+    #[serde(rename = "openai")]
+    OpenAi,
+    Azure,
+    Anthropic,
+    Gemini,
+    Ollama,
+    // 其他 OpenAI 兼容服务
+    #[default]
+    Generic,
+}
+
+impl Provider {
+    // 前端未指定时根据 base_url 的主机名推断
+    pub fn detect(base_url: &str) -> Provider {
+        let Some(url) = reqwest::Url::parse(base_url).ok() else {
+            return Provider::Generic;
+        };
+        let host = url.host_str().unwrap_or_default().to_lowercase();
+
+        if host == "api.openai.com" {
+            Provider::OpenAi
+        } else if host.ends_with(".openai.azure.com") {
+            Provider::Azure
+        } else if host == "api.anthropic.com" {
+            Provider::Anthropic
+        } else if host == "generativelanguage.googleapis.com" {
+            Provider::Gemini
+        } else if url.port() == Some(11434) || host.contains("ollama") {
+            Provider::Ollama
+        } else {
+            Provider::Generic
+        }
+    }
+}
+
+// Anthropic extended thinking 要求的最小预算
+const MIN_ANTHROPIC_THINKING_BUDGET: u32 = 1024;
+
+// 用户意图“开启深度思考（可选 token 预算）”，由 apply 转成各服务商要求的请求体字段
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "lowercase")]
+pub enum ThinkingConfig {
+    Disabled,
+    Enabled { budget_tokens: Option<u32> },
+}
+
+impl ThinkingConfig {
+    pub fn new(enabled: bool, budget_tokens: Option<u32>) -> Self {
+        if enabled {
+            ThinkingConfig::Enabled { budget_tokens }
+        } else {
+            ThinkingConfig::Disabled
+        }
+    }
+
+    // 没有预算参数的服务按预算粗略映射到 low / medium / high
+    fn effort(budget_tokens: Option<u32>) -> &'static str {
+        match budget_tokens {
+            Some(budget) if budget <= 1024 => "low",
+            Some(budget) if budget <= 8192 => "medium",
+            _ => "high",
+        }
+    }
+
+    pub fn apply(
+        &self,
+        provider: Provider,
+        request_body: &mut serde_json::Value,
+    ) -> Result<(), String> {
+        match (provider, *self) {
+            // thinking: { type } 是最初对接的服务（及多数国内兼容服务）的写法
+            (Provider::Generic, ThinkingConfig::Disabled) => {
+                request_body["thinking"] = serde_json::json!({ "type": "disabled" });
+            }
+            (Provider::Generic, ThinkingConfig::Enabled { .. }) => {
+                request_body["thinking"] = serde_json::json!({ "type": "enabled" });
+            }
+            (Provider::Anthropic, ThinkingConfig::Enabled { budget_tokens }) => {
+                let budget = budget_tokens.unwrap_or(MIN_ANTHROPIC_THINKING_BUDGET);
+                if budget < MIN_ANTHROPIC_THINKING_BUDGET {
+                    return Err(format!(
+                        "Anthropic thinking budget must be at least {} tokens, got {}",
+                        MIN_ANTHROPIC_THINKING_BUDGET, budget
+                    ));
+                }
+                request_body["thinking"] = serde_json::json!({
+                    "type": "enabled",
+                    "budget_tokens": budget,
+                });
+            }
+            (
+                Provider::OpenAi | Provider::Azure | Provider::Gemini | Provider::Ollama,
+                ThinkingConfig::Enabled { budget_tokens },
+            ) => {
+                request_body["reasoning_effort"] = serde_json::json!(Self::effort(budget_tokens));
+            }
+            // 其余服务关闭思考时什么都不传；传入未知字段反而可能被拒绝
+            (_, ThinkingConfig::Disabled) => {}
+        }
+        Ok(())
+    }
+}
+
+// 预览某个服务商下思考参数对应的请求体字段，同时校验预算是否合法
+#[tauri::command]
+pub fn normalize_thinking(
+    provider: Provider,
+    config: ThinkingConfig,
+) -> Result<serde_json::Value, String> {
+    let mut fields = serde_json::json!({});
+    config.apply(provider, &mut fields)?;
+    Ok(fields)
+}
+
+// Anthropic 原生流式事件（data 中带 type 字段）中与回复内容相关的部分
+#[derive(Debug, Clone, PartialEq)]
+pub enum AnthropicEvent {
+    Text(String),
+    // extended thinking 的思考增量
+    Thinking(String),
+    StopReason(String),
+    MessageStop,
+    // message_start、ping、content_block_start 等无需处理的事件
+    Other,
+}
+
+// 不是 Anthropic 格式的数据返回 None，交给 OpenAI 格式的解析
+pub fn parse_anthropic_event(data: &str) -> Option<AnthropicEvent> {
+    let value: serde_json::Value = serde_json::from_str(data).ok()?;
+    let event_type = value["type"].as_str()?;
+    let event = match event_type {
+        "content_block_delta" => {
+            let delta = &value["delta"];
+            match delta["type"].as_str() {
+                Some("text_delta") => AnthropicEvent::Text(delta["text"].as_str()?.to_string()),
+                Some("thinking_delta" | "thinking") => {
+                    AnthropicEvent::Thinking(delta["thinking"].as_str()?.to_string())
+                }
+                _ => AnthropicEvent::Other,
+            }
+        }
+        "message_delta" => match value["delta"]["stop_reason"].as_str() {
+            Some(reason) => AnthropicEvent::StopReason(reason.to_string()),
+            None => AnthropicEvent::Other,
+        },
+        "message_stop" => AnthropicEvent::MessageStop,
+        "message_start" | "content_block_start" | "content_block_stop" | "ping" => {
+            AnthropicEvent::Other
+        }
+        _ => return None,
+    };
+    Some(event)
+}
@@ -0,0 +1,349 @@
+use std::collections::{BTreeMap, HashMap};
+use std::fs;
+use std::path::PathBuf;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use reqwest::header::HeaderMap;
+use serde::{Deserialize, Serialize};
+use tauri::{AppHandle, Manager};
+
+// OpenAI 兼容服务在响应头中返回的限流额度，前端据此显示剩余配额
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct RateLimitStatus {
+    pub limit_requests: Option<u64>,
+    pub limit_tokens: Option<u64>,
+    pub remaining_requests: Option<u64>,
+    pub remaining_tokens: Option<u64>,
+    // 原样保留，如 "1s"、"6m0s"
+    pub reset_requests: Option<String>,
+    pub reset_tokens: Option<String>,
+}
+
+impl RateLimitStatus {
+    // 响应中没有任何限流头时返回 None
+    pub fn from_headers(headers: &HeaderMap) -> Option<Self> {
+        let text = |name: &str| {
+            headers
+                .get(name)
+                .and_then(|v| v.to_str().ok())
+                .map(|v| v.trim().to_string())
+        };
+        let number = |name: &str| text(name).and_then(|v| v.parse().ok());
+
+        let status = RateLimitStatus {
+            limit_requests: number("x-ratelimit-limit-requests"),
+            limit_tokens: number("x-ratelimit-limit-tokens"),
+            remaining_requests: number("x-ratelimit-remaining-requests"),
+            remaining_tokens: number("x-ratelimit-remaining-tokens"),
+            reset_requests: text("x-ratelimit-reset-requests"),
+            reset_tokens: text("x-ratelimit-reset-tokens"),
+        };
+        (status != RateLimitStatus::default()).then_some(status)
+    }
+}
+
+// 客户端令牌桶：未被限流时不限制；收到 429 后按 Retry-After 收紧该主机的请求速率，
+// 冷却期过后每次成功请求逐步放宽。学到的速率按主机持久化，重启后不会立刻再次触发限流
+const UNLIMITED_RATE: f64 = 10.0;
+const MIN_RATE: f64 = 1.0 / 60.0;
+// 收到 429 后至少保持收紧这么久
+const COOLDOWN: Duration = Duration::from_secs(30);
+// 冷却期后每隔这么久、且期间请求成功，速率乘以 RELAX_FACTOR
+const RELAX_INTERVAL: Duration = Duration::from_secs(30);
+const RELAX_FACTOR: f64 = 1.5;
+// 没有 Retry-After 时按这个间隔收紧
+const DEFAULT_RETRY_AFTER: Duration = Duration::from_secs(5);
+
+struct Bucket {
+    // 每秒允许的请求数
+    rate: f64,
+    tokens: f64,
+    refilled_at: Instant,
+    cooldown_until: Instant,
+    adjusted_at: Instant,
+}
+
+impl Bucket {
+    fn new(rate: f64) -> Self {
+        let now = Instant::now();
+        Bucket {
+            rate,
+            tokens: 1.0,
+            refilled_at: now,
+            cooldown_until: now,
+            adjusted_at: now,
+        }
+    }
+
+    fn burst(&self) -> f64 {
+        self.rate.max(1.0)
+    }
+
+    // 预约一个令牌，返回需要等待的时间（令牌可以预支为负数，后来者依次排队）
+    fn reserve(&mut self, now: Instant) -> Duration {
+        let elapsed = now.duration_since(self.refilled_at).as_secs_f64();
+        self.tokens = (self.tokens + elapsed * self.rate).min(self.burst());
+        self.refilled_at = now;
+        self.tokens -= 1.0;
+        if self.tokens >= 0.0 {
+            Duration::ZERO
+        } else {
+            Duration::from_secs_f64(-self.tokens / self.rate)
+        }
+    }
+
+    // 速率减半，且不超过 Retry-After 所暗示的速率
+    fn tighten(&mut self, retry_after: Duration, now: Instant) {
+        let hinted = 1.0 / retry_after.as_secs_f64().max(0.001);
+        self.rate = (self.rate / 2.0).min(hinted).max(MIN_RATE);
+        // 在 Retry-After 之前不再放行
+        self.tokens = 1.0 - retry_after.as_secs_f64() * self.rate;
+        self.refilled_at = now;
+        self.cooldown_until = now + COOLDOWN.max(retry_after);
+        self.adjusted_at = now;
+    }
+
+    // 冷却期已过且距上次调整足够久时放宽，返回是否有变化
+    fn relax(&mut self, now: Instant) -> bool {
+        if now < self.cooldown_until || now.duration_since(self.adjusted_at) < RELAX_INTERVAL {
+            return false;
+        }
+        self.rate *= RELAX_FACTOR;
+        self.adjusted_at = now;
+        true
+    }
+}
+
+#[derive(Default)]
+pub struct AdaptiveLimiter(Mutex<HashMap<String, Bucket>>);
+
+fn host_of(url: &str) -> Option<String> {
+    reqwest::Url::parse(url)
+        .ok()?
+        .host_str()
+        .map(|host| host.to_lowercase())
+}
+
+// 支持 retry-after-ms（OpenAI / Azure）和以秒为单位的 retry-after
+fn retry_after(headers: &HeaderMap) -> Option<Duration> {
+    let text = |name: &str| headers.get(name).and_then(|v| v.to_str().ok());
+    text("retry-after-ms")
+        .and_then(|v| v.trim().parse::<f64>().ok())
+        .map(|ms| Duration::from_secs_f64(ms / 1000.0))
+        .or_else(|| {
+            text("retry-after")
+                .and_then(|v| v.trim().parse::<f64>().ok())
+                .map(Duration::from_secs_f64)
+        })
+}
+
+fn learned_path(app_handle: &AppHandle) -> Result<PathBuf, String> {
+    let dir = app_handle
+        .path()
+        .app_data_dir()
+        .map_err(|e| format!("Failed to resolve app data dir: {}", e))?;
+    fs::create_dir_all(&dir).map_err(|e| format!("Failed to create app data dir: {}", e))?;
+    Ok(dir.join("rate_limits.json"))
+}
+
+// 只保存被收紧过的主机
+fn persist(app_handle: &AppHandle, buckets: &HashMap<String, Bucket>) {
+    let learned: BTreeMap<&String, f64> = buckets
+        .iter()
+        .filter(|(_, bucket)| bucket.rate < UNLIMITED_RATE)
+        .map(|(host, bucket)| (host, bucket.rate))
+        .collect();
+    let result = learned_path(app_handle).and_then(|path| {
+        let text = serde_json::to_string_pretty(&learned)
+            .map_err(|e| format!("Failed to serialize rate limits: {}", e))?;
+        fs::write(&path, text).map_err(|e| format!("Failed to write rate limits: {}", e))
+    });
+    if let Err(e) = result {
+        log::warn!("{}", e);
+    }
+}
+
+// 启动时加载上次学到的速率
+pub fn init(app_handle: &AppHandle) {
+    let Ok(path) = learned_path(app_handle) else {
+        return;
+    };
+    let Some(learned) = fs::read_to_string(&path)
+        .ok()
+        .and_then(|text| serde_json::from_str::<HashMap<String, f64>>(&text).ok())
+    else {
+        return;
+    };
+    let limiter = app_handle.state::<AdaptiveLimiter>();
+    let mut buckets = limiter.0.lock().unwrap();
+    for (host, rate) in learned {
+        buckets.insert(host, Bucket::new(rate.clamp(MIN_RATE, UNLIMITED_RATE)));
+    }
+}
+
+// 发送请求前调用，必要时等待到令牌可用
+pub async fn throttle(app_handle: &AppHandle, url: &str) {
+    let Some(host) = host_of(url) else {
+        return;
+    };
+    let wait = {
+        let limiter = app_handle.state::<AdaptiveLimiter>();
+        let mut buckets = limiter.0.lock().unwrap();
+        match buckets.get_mut(&host) {
+            Some(bucket) => bucket.reserve(Instant::now()),
+            None => return,
+        }
+    };
+    if !wait.is_zero() {
+        log::info!("Throttling request to {} for {:?}", host, wait);
+        tokio::time::sleep(wait).await;
+    }
+}
+
+// 收到响应后调用：429 收紧，冷却期后成功的请求逐步放宽
+pub fn observe(
+    app_handle: &AppHandle,
+    url: &str,
+    status: reqwest::StatusCode,
+    headers: &HeaderMap,
+) {
+    let Some(host) = host_of(url) else {
+        return;
+    };
+    let limiter = app_handle.state::<AdaptiveLimiter>();
+    let mut buckets = limiter.0.lock().unwrap();
+    let now = Instant::now();
+
+    if status == reqwest::StatusCode::TOO_MANY_REQUESTS {
+        let retry_after = retry_after(headers).unwrap_or(DEFAULT_RETRY_AFTER);
+        let bucket = buckets
+            .entry(host.clone())
+            .or_insert_with(|| Bucket::new(UNLIMITED_RATE));
+        bucket.tighten(retry_after, now);
+        log::warn!(
+            "Rate limited by {}, tightening to {:.3} requests/s",
+            host,
+            bucket.rate
+        );
+        persist(app_handle, &buckets);
+        return;
+    }
+
+    if !status.is_success() {
+        return;
+    }
+    let Some(bucket) = buckets.get_mut(&host) else {
+        return;
+    };
+    if !bucket.relax(now) {
+        return;
+    }
+    if bucket.rate >= UNLIMITED_RATE {
+        buckets.remove(&host);
+    }
+    persist(app_handle, &buckets);
+}
+
+// 各主机当前学到的速率（每秒请求数），未被限流过的主机不出现
+#[tauri::command]
+pub fn get_learned_rate_limits(app_handle: AppHandle) -> BTreeMap<String, f64> {
+    let limiter = app_handle.state::<AdaptiveLimiter>();
+    let buckets = limiter.0.lock().unwrap();
+    buckets
+        .iter()
+        .map(|(host, bucket)| (host.clone(), bucket.rate))
+        .collect()
+}
+
+#[tauri::command]
+pub fn reset_learned_rate_limits(app_handle: AppHandle) {
+    let limiter = app_handle.state::<AdaptiveLimiter>();
+    let mut buckets = limiter.0.lock().unwrap();
+    buckets.clear();
+    persist(&app_handle, &buckets);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn headers(pairs: &[(&'static str, &'static str)]) -> HeaderMap {
+        pairs
+            .iter()
+            .map(|&(name, value)| (name.parse().unwrap(), value.parse().unwrap()))
+            .collect()
+    }
+
+    #[test]
+    fn retry_after_prefers_milliseconds() {
+        assert_eq!(
+            retry_after(&headers(&[
+                ("retry-after", "2"),
+                ("retry-after-ms", "1500")
+            ])),
+            Some(Duration::from_millis(1500))
+        );
+        assert_eq!(
+            retry_after(&headers(&[("retry-after", " 3 ")])),
+            Some(Duration::from_secs(3))
+        );
+        assert_eq!(retry_after(&headers(&[("retry-after", "soon")])), None);
+    }
+
+    #[test]
+    fn tightening_blocks_until_retry_after() {
+        let now = Instant::now();
+        let mut bucket = Bucket::new(UNLIMITED_RATE);
+        bucket.tighten(Duration::from_secs(10), now);
+        assert!((bucket.rate - 0.1).abs() < 1e-9);
+        let wait = bucket.reserve(now);
+        assert!((wait.as_secs_f64() - 10.0).abs() < 1e-6);
+        // 下一个请求排在其后
+        assert!(bucket.reserve(now) > wait);
+    }
+
+    #[test]
+    fn repeated_rate_limits_keep_halving_down_to_minimum() {
+        let now = Instant::now();
+        let mut bucket = Bucket::new(UNLIMITED_RATE);
+        bucket.tighten(Duration::from_millis(100), now);
+        assert_eq!(bucket.rate, UNLIMITED_RATE / 2.0);
+        bucket.tighten(Duration::from_millis(100), now);
+        assert_eq!(bucket.rate, UNLIMITED_RATE / 4.0);
+        for _ in 0..20 {
+            bucket.tighten(Duration::from_millis(100), now);
+        }
+        assert_eq!(bucket.rate, MIN_RATE);
+    }
+
+    #[test]
+    fn relaxes_gradually_after_cooldown() {
+        let start = Instant::now();
+        let mut bucket = Bucket::new(UNLIMITED_RATE);
+        bucket.tighten(Duration::from_secs(1), start);
+        let tightened = bucket.rate;
+
+        assert!(!bucket.relax(start + COOLDOWN / 2));
+        let after_cooldown = start + COOLDOWN.max(RELAX_INTERVAL);
+        assert!(bucket.relax(after_cooldown));
+        assert_eq!(bucket.rate, tightened * RELAX_FACTOR);
+        // 两次放宽之间至少间隔 RELAX_INTERVAL
+        assert!(!bucket.relax(after_cooldown + RELAX_INTERVAL / 2));
+        assert!(bucket.relax(after_cooldown + RELAX_INTERVAL));
+        assert_eq!(bucket.rate, tightened * RELAX_FACTOR * RELAX_FACTOR);
+    }
+
+    #[test]
+    fn refills_at_learned_rate() {
+        let now = Instant::now();
+        let mut bucket = Bucket::new(0.5);
+        assert_eq!(bucket.reserve(now), Duration::ZERO);
+        assert_eq!(bucket.reserve(now), Duration::from_secs(2));
+        // 两个令牌都已预支，4 秒后才轮到下一个
+        assert_eq!(
+            bucket.reserve(now + Duration::from_secs(2)),
+            Duration::from_secs(2)
+        );
+    }
+}
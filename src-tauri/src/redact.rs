@@ -0,0 +1,164 @@
+use std::collections::HashMap;
+
+use regex::Regex;
+use serde::Serialize;
+
+use crate::transform::StreamTransform;
+use crate::Message;
+
+// 内置规则，patterns 中也可以直接写正则
+const BUILTIN_PATTERNS: &[(&str, &str)] = &[
+    ("email", r"[A-Za-z0-9._%+-]+@[A-Za-z0-9.-]+\.[A-Za-z]{2,}"),
+    ("phone", r"\+?\d[\d\s().-]{6,}\d"),
+    ("ipv4", r"\b(?:\d{1,3}\.){3}\d{1,3}\b"),
+];
+
+const PLACEHOLDER_PREFIX: &str = "[REDACTED_";
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Redacted {
+    pub messages: Vec<Message>,
+    // 占位符 -> 原文，发送请求时作为 restore_redactions 传回即可在回复中还原
+    pub mapping: HashMap<String, String>,
+}
+
+fn compile(patterns: &[String]) -> Result<Vec<(String, Regex)>, String> {
+    patterns
+        .iter()
+        .map(|pattern| {
+            let (label, source) = match BUILTIN_PATTERNS.iter().find(|(name, _)| name == pattern) {
+                Some((name, source)) => (name.to_uppercase(), *source),
+                None => (String::new(), pattern.as_str()),
+            };
+            Regex::new(source)
+                .map(|regex| (label, regex))
+                .map_err(|e| format!("Invalid redaction pattern {}: {}", pattern, e))
+        })
+        .collect()
+}
+
+struct Redactor {
+    rules: Vec<(String, Regex)>,
+    mapping: HashMap<String, String>,
+    // 原文 -> 占位符，同一内容多次出现时使用同一个占位符
+    placeholders: HashMap<String, String>,
+}
+
+impl Redactor {
+    fn redact(&mut self, text: &str) -> String {
+        let mut text = text.to_string();
+        for (label, regex) in &self.rules {
+            text = regex
+                .replace_all(&text, |caps: &regex::Captures| {
+                    let original = &caps[0];
+                    if self.mapping.contains_key(original) {
+                        // 前面规则生成的占位符
+                        return original.to_string();
+                    }
+                    let next = self.mapping.len() + 1;
+                    let placeholder = self
+                        .placeholders
+                        .entry(original.to_string())
+                        .or_insert_with(|| match label.as_str() {
+                            "" => format!("{}{}]", PLACEHOLDER_PREFIX, next),
+                            label => format!("{}{}_{}]", PLACEHOLDER_PREFIX, label, next),
+                        })
+                        .clone();
+                    self.mapping
+                        .insert(placeholder.clone(), original.to_string());
+                    placeholder
+                })
+                .into_owned();
+        }
+        text
+    }
+}
+
+// 替换文本内容中的邮箱、电话等敏感信息；多模态消息只处理 text 部分
+#[tauri::command]
+pub fn redact_messages(messages: Vec<Message>, patterns: Vec<String>) -> Result<Redacted, String> {
+    let mut redactor = Redactor {
+        rules: compile(&patterns)?,
+        mapping: HashMap::new(),
+        placeholders: HashMap::new(),
+    };
+    let messages = messages
+        .into_iter()
+        .map(|message| {
+            let content = match message.content {
+                serde_json::Value::String(text) => {
+                    serde_json::Value::String(redactor.redact(&text))
+                }
+                serde_json::Value::Array(parts) => serde_json::Value::Array(
+                    parts
+                        .into_iter()
+                        .map(|mut part| {
+                            if part["type"] == "text" {
+                                if let Some(text) = part["text"].as_str() {
+                                    part["text"] = serde_json::Value::String(redactor.redact(text));
+                                }
+                            }
+                            part
+                        })
+                        .collect(),
+                ),
+                other => other,
+            };
+            Message { content, ..message }
+        })
+        .collect();
+    Ok(Redacted {
+        messages,
+        mapping: redactor.mapping,
+    })
+}
+
+pub fn restore(text: &str, mapping: &HashMap<String, String>) -> String {
+    if !text.contains(PLACEHOLDER_PREFIX) {
+        return text.to_string();
+    }
+    mapping
+        .iter()
+        .fold(text.to_string(), |text, (placeholder, original)| {
+            text.replace(placeholder, original)
+        })
+}
+
+#[tauri::command]
+pub fn restore_redactions(text: String, mapping: HashMap<String, String>) -> String {
+    restore(&text, &mapping)
+}
+
+// 流式回复中还原占位符：占位符可能被拆在多个增量里，末尾疑似占位符开头的部分先留存
+pub struct RestoreRedactions {
+    mapping: HashMap<String, String>,
+    pending: String,
+}
+
+impl RestoreRedactions {
+    pub fn new(mapping: HashMap<String, String>) -> Self {
+        RestoreRedactions {
+            mapping,
+            pending: String::new(),
+        }
+    }
+}
+
+impl StreamTransform for RestoreRedactions {
+    fn feed(&mut self, text: &str) -> String {
+        self.pending.push_str(text);
+        let hold = self.pending.rfind('[').filter(|&start| {
+            let tail = &self.pending[start..];
+            !tail.contains(']') && self.mapping.keys().any(|k| k.starts_with(tail))
+        });
+        let ready: String = match hold {
+            Some(start) => self.pending.drain(..start).collect(),
+            None => std::mem::take(&mut self.pending),
+        };
+        restore(&ready, &self.mapping)
+    }
+
+    fn finish(&mut self) -> String {
+        restore(&std::mem::take(&mut self.pending), &self.mapping)
+    }
+}
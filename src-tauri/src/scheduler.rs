@@ -0,0 +1,306 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+use tauri::{AppHandle, Emitter, Manager};
+use tokio_util::sync::CancellationToken;
+
+use crate::client::HttpClient;
+use crate::profiles;
+use crate::storage::now_millis;
+use crate::{build_request_body, send_chat_request, ChatOptions, ChatResponse, Message};
+
+// 重复执行的最短间隔，避免误设导致频繁请求
+const MIN_INTERVAL_SECS: u64 = 60;
+
+// at_unix（Unix 秒）为首次执行时间，不传表示从现在起过 interval_secs 后执行；
+// 只传 at_unix 为一次性任务
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ScheduleSpec {
+    #[serde(default)]
+    pub at_unix: Option<u64>,
+    #[serde(default)]
+    pub interval_secs: Option<u64>,
+    // 使用的服务配置名称，base_url / model 和 API key 都在执行时按名称取
+    pub profile: String,
+    #[serde(default)]
+    pub options: Option<ChatOptions>,
+    pub messages: Vec<Message>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ScheduledPrompt {
+    pub id: String,
+    pub spec: ScheduleSpec,
+    pub next_run_unix: u64,
+    pub created_at_unix: u64,
+}
+
+// 每次执行后通过 scheduled-result 事件发送
+#[derive(Debug, Serialize)]
+pub struct ScheduledResult {
+    pub schedule_id: String,
+    pub run_at_unix: u64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub response: Option<ChatResponse>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+// 定时任务表。每个任务一个后台任务，取消令牌随条目移除而触发。
+// 任务保存在应用数据目录下的 schedules.json 中（不含 API key），重启后自动恢复
+#[derive(Default)]
+pub struct Scheduler {
+    schedules: Mutex<BTreeMap<String, (ScheduledPrompt, CancellationToken)>>,
+    next_seq: AtomicU64,
+}
+
+fn now_unix() -> u64 {
+    now_millis() / 1000
+}
+
+// 首次执行时间：指定的时间已过则立即执行
+fn first_run(at_unix: Option<u64>, interval_secs: Option<u64>, now: u64) -> Result<u64, String> {
+    if let Some(interval) = interval_secs {
+        if interval < MIN_INTERVAL_SECS {
+            return Err(format!(
+                "interval_secs must be at least {}",
+                MIN_INTERVAL_SECS
+            ));
+        }
+    }
+    match (at_unix, interval_secs) {
+        (Some(at), _) => Ok(at.max(now)),
+        (None, Some(interval)) => Ok(now + interval),
+        (None, None) => Err("Either at_unix or interval_secs is required".to_string()),
+    }
+}
+
+// 执行完成后的下次执行时间；一次性任务返回 None。
+// 从完成时间起算，执行耗时较长或错过了多次执行时也只补一次
+fn next_run(interval_secs: Option<u64>, finished_at: u64) -> Option<u64> {
+    interval_secs.map(|interval| finished_at + interval)
+}
+
+fn schedules_path(app_handle: &AppHandle) -> Result<PathBuf, String> {
+    let dir = app_handle
+        .path()
+        .app_data_dir()
+        .map_err(|e| format!("Failed to resolve app data dir: {}", e))?;
+    fs::create_dir_all(&dir).map_err(|e| format!("Failed to create app data dir: {}", e))?;
+    Ok(dir.join("schedules.json"))
+}
+
+fn persist(app_handle: &AppHandle) -> Result<(), String> {
+    let scheduler = app_handle.state::<Scheduler>();
+    let schedules: Vec<ScheduledPrompt> = scheduler
+        .schedules
+        .lock()
+        .unwrap()
+        .values()
+        .map(|(schedule, _)| schedule.clone())
+        .collect();
+    let path = schedules_path(app_handle)?;
+    let text = serde_json::to_string_pretty(&schedules)
+        .map_err(|e| format!("Failed to serialize schedules: {}", e))?;
+    let tmp = path.with_extension("json.tmp");
+    fs::write(&tmp, text).map_err(|e| format!("Failed to write schedules: {}", e))?;
+    fs::rename(&tmp, &path).map_err(|e| format!("Failed to write schedules: {}", e))
+}
+
+async fn run_once(app_handle: &AppHandle, spec: &ScheduleSpec) -> Result<ChatResponse, String> {
+    let api_key = profiles::api_key(app_handle, &spec.profile)?;
+    let mut options = spec.options.clone().unwrap_or_default();
+    options.profile = Some(spec.profile.clone());
+    let mut base_url = String::new();
+    let mut model = String::new();
+    let options = ChatOptions::resolve(Some(options), &mut base_url, &mut model, app_handle)?;
+    let url = format!("{}/chat/completions", base_url);
+    let client = app_handle.state::<HttpClient>().client_for(&url)?;
+    let messages =
+        crate::attachments::resolve_attachments(spec.messages.clone(), app_handle.clone())?;
+    let request_body = build_request_body(&model, &messages, false, false, &options)?;
+    send_chat_request(&client, &url, &api_key, &request_body, &options, app_handle).await
+}
+
+fn spawn(app_handle: AppHandle, mut schedule: ScheduledPrompt, token: CancellationToken) {
+    tauri::async_runtime::spawn(async move {
+        loop {
+            let wait = schedule.next_run_unix.saturating_sub(now_unix());
+            tokio::select! {
+                _ = token.cancelled() => return,
+                _ = tokio::time::sleep(Duration::from_secs(wait)) => {}
+            }
+
+            let run_at_unix = now_unix();
+            let result = tokio::select! {
+                _ = token.cancelled() => return,
+                result = run_once(&app_handle, &schedule.spec) => result,
+            };
+            if let Err(e) = &result {
+                log::warn!("Scheduled prompt {} failed: {}", schedule.id, e);
+            }
+            let (response, error) = match result {
+                Ok(response) => (Some(response), None),
+                Err(e) => (None, Some(e)),
+            };
+            let _ = app_handle.emit(
+                "scheduled-result",
+                &ScheduledResult {
+                    schedule_id: schedule.id.clone(),
+                    run_at_unix,
+                    response,
+                    error,
+                },
+            );
+
+            let scheduler = app_handle.state::<Scheduler>();
+            {
+                let mut schedules = scheduler.schedules.lock().unwrap();
+                match next_run(schedule.spec.interval_secs, now_unix()) {
+                    Some(next_run_unix) => {
+                        schedule.next_run_unix = next_run_unix;
+                        if let Some((entry, _)) = schedules.get_mut(&schedule.id) {
+                            entry.next_run_unix = schedule.next_run_unix;
+                        }
+                    }
+                    None => {
+                        schedules.remove(&schedule.id);
+                    }
+                }
+            }
+            if let Err(e) = persist(&app_handle) {
+                log::warn!("{}", e);
+            }
+            if schedule.spec.interval_secs.is_none() {
+                return;
+            }
+        }
+    });
+}
+
+fn insert(app_handle: &AppHandle, schedule: ScheduledPrompt) {
+    let token = CancellationToken::new();
+    app_handle
+        .state::<Scheduler>()
+        .schedules
+        .lock()
+        .unwrap()
+        .insert(schedule.id.clone(), (schedule.clone(), token.clone()));
+    spawn(app_handle.clone(), schedule, token);
+}
+
+// 启动时恢复保存的任务；错过的执行时间会立即补执行一次
+pub fn init(app_handle: &AppHandle) {
+    let schedules = schedules_path(app_handle)
+        .ok()
+        .and_then(|path| fs::read_to_string(path).ok())
+        .and_then(|text| serde_json::from_str::<Vec<ScheduledPrompt>>(&text).ok())
+        .unwrap_or_default();
+    for schedule in schedules {
+        insert(app_handle, schedule);
+    }
+}
+
+#[tauri::command]
+pub fn schedule_prompt(spec: ScheduleSpec, app_handle: AppHandle) -> Result<String, String> {
+    let now = now_unix();
+    let next_run_unix = first_run(spec.at_unix, spec.interval_secs, now)?;
+    if spec.messages.is_empty() {
+        return Err("Scheduled prompt has no messages".to_string());
+    }
+    if spec.profile.trim().is_empty() {
+        return Err("Scheduled prompt has no profile".to_string());
+    }
+
+    let scheduler = app_handle.state::<Scheduler>();
+    let id = format!(
+        "schedule-{}-{}",
+        now_millis(),
+        scheduler.next_seq.fetch_add(1, Ordering::Relaxed)
+    );
+    insert(
+        &app_handle,
+        ScheduledPrompt {
+            id: id.clone(),
+            spec,
+            next_run_unix,
+            created_at_unix: now,
+        },
+    );
+    persist(&app_handle)?;
+    Ok(id)
+}
+
+#[tauri::command]
+pub fn cancel_schedule(id: String, app_handle: AppHandle) -> Result<bool, String> {
+    let removed = app_handle
+        .state::<Scheduler>()
+        .schedules
+        .lock()
+        .unwrap()
+        .remove(&id);
+    let Some((_, token)) = removed else {
+        return Ok(false);
+    };
+    token.cancel();
+    persist(&app_handle)?;
+    Ok(true)
+}
+
+#[tauri::command]
+pub fn list_schedules(app_handle: AppHandle) -> Vec<ScheduledPrompt> {
+    app_handle
+        .state::<Scheduler>()
+        .schedules
+        .lock()
+        .unwrap()
+        .values()
+        .map(|(schedule, _)| schedule.clone())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const NOW: u64 = 1_700_000_000;
+
+    #[test]
+    fn first_run_at_future_time() {
+        assert_eq!(first_run(Some(NOW + 30), None, NOW), Ok(NOW + 30));
+        assert_eq!(first_run(Some(NOW + 30), Some(3600), NOW), Ok(NOW + 30));
+    }
+
+    #[test]
+    fn missed_time_runs_immediately() {
+        assert_eq!(first_run(Some(NOW - 100), None, NOW), Ok(NOW));
+        assert_eq!(first_run(Some(NOW - 100), Some(3600), NOW), Ok(NOW));
+    }
+
+    #[test]
+    fn interval_only_waits_one_interval() {
+        assert_eq!(first_run(None, Some(3600), NOW), Ok(NOW + 3600));
+    }
+
+    #[test]
+    fn rejects_missing_time_and_short_interval() {
+        assert!(first_run(None, None, NOW).is_err());
+        assert!(first_run(None, Some(MIN_INTERVAL_SECS - 1), NOW).is_err());
+        assert!(first_run(Some(NOW), Some(1), NOW).is_err());
+        assert_eq!(
+            first_run(None, Some(MIN_INTERVAL_SECS), NOW),
+            Ok(NOW + MIN_INTERVAL_SECS)
+        );
+    }
+
+    #[test]
+    fn next_run_counts_from_completion() {
+        assert_eq!(next_run(Some(3600), NOW + 5), Some(NOW + 3605));
+        assert_eq!(next_run(None, NOW), None);
+    }
+}
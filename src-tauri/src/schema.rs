@@ -0,0 +1,69 @@
+use serde::Serialize;
+
+// instance_path / schema_path 为 JSON Pointer，如 /items/0/name
+#[derive(Debug, Clone, Serialize)]
+pub struct ValidationError {
+    pub instance_path: String,
+    pub schema_path: String,
+    pub message: String,
+}
+
+impl ValidationError {
+    fn root(message: String) -> Self {
+        ValidationError {
+            instance_path: String::new(),
+            schema_path: String::new(),
+            message,
+        }
+    }
+}
+
+// 部分模型即使要求结构化输出也会包一层 ```json 代码块
+fn strip_code_fence(content: &str) -> &str {
+    let trimmed = content.trim();
+    let Some(inner) = trimmed.strip_prefix("```") else {
+        return trimmed;
+    };
+    let inner = inner.strip_suffix("```").unwrap_or(inner);
+    // 去掉语言标记所在的第一行
+    match inner.split_once('\n') {
+        Some((_, body)) => body.trim(),
+        None => inner.trim(),
+    }
+}
+
+// 按 JSON Schema 校验结构化输出，返回所有不符合的位置，便于发现未严格遵守 schema 的服务
+#[tauri::command]
+pub fn validate_against_schema(
+    content: String,
+    schema: serde_json::Value,
+) -> Result<(), Vec<ValidationError>> {
+    // response_format 中的 json_schema 外层可以直接传入
+    let schema = match schema.get("schema") {
+        Some(inner) if schema.get("name").is_some() => inner.clone(),
+        _ => schema,
+    };
+    let validator = jsonschema::validator_for(&schema)
+        .map_err(|e| vec![ValidationError::root(format!("Invalid schema: {}", e))])?;
+    let instance: serde_json::Value =
+        serde_json::from_str(strip_code_fence(&content)).map_err(|e| {
+            vec![ValidationError::root(format!(
+                "Failed to parse content as JSON: {}",
+                e
+            ))]
+        })?;
+
+    let errors: Vec<ValidationError> = validator
+        .iter_errors(&instance)
+        .map(|error| ValidationError {
+            instance_path: error.instance_path().to_string(),
+            schema_path: error.schema_path().to_string(),
+            message: error.to_string(),
+        })
+        .collect();
+    if errors.is_empty() {
+        Ok(())
+    } else {
+        Err(errors)
+    }
+}
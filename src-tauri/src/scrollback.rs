@@ -0,0 +1,128 @@
+use std::collections::{HashMap, VecDeque};
+use std::sync::{Arc, Mutex};
+
+use serde::Serialize;
+
+use crate::emit::StreamSink;
+use crate::StreamData;
+
+// 单个流保留的最大字符数，超出后丢弃最早的内容
+const DEFAULT_MAX_CHARS: usize = 4 * 1024 * 1024;
+// 流结束后内容仍然保留，最多保留最近这么多个流
+const MAX_BUFFERS: usize = 16;
+
+#[derive(Default)]
+struct ContentBuffer {
+    text: VecDeque<char>,
+    // 已被丢弃的字符数，偏移量始终相对于回复开头
+    dropped: usize,
+    max_chars: usize,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct StreamContentInfo {
+    pub total_chars: usize,
+    // 能取到的最早偏移量，更早的内容已被丢弃
+    pub retained_from: usize,
+}
+
+// 开启 scrollback 的流把完整正文保存在 Rust 侧，前端按偏移量分段读取（虚拟滚动）
+#[derive(Default)]
+pub struct ScrollbackStore {
+    buffers: Mutex<HashMap<String, Arc<Mutex<ContentBuffer>>>>,
+    order: Mutex<VecDeque<String>>,
+}
+
+impl ScrollbackStore {
+    // 同 id 的旧内容被替换
+    pub fn open(&self, stream_id: &str, max_chars: Option<usize>) -> ScrollbackSink {
+        let buffer = Arc::new(Mutex::new(ContentBuffer {
+            max_chars: max_chars.unwrap_or(DEFAULT_MAX_CHARS).max(1),
+            ..Default::default()
+        }));
+        let mut buffers = self.buffers.lock().unwrap();
+        let mut order = self.order.lock().unwrap();
+        order.retain(|id| id != stream_id);
+        order.push_back(stream_id.to_string());
+        while order.len() > MAX_BUFFERS {
+            if let Some(oldest) = order.pop_front() {
+                buffers.remove(&oldest);
+            }
+        }
+        buffers.insert(stream_id.to_string(), buffer.clone());
+        ScrollbackSink { buffer }
+    }
+
+    fn get(&self, stream_id: &str) -> Result<Arc<Mutex<ContentBuffer>>, String> {
+        self.buffers
+            .lock()
+            .unwrap()
+            .get(stream_id)
+            .cloned()
+            .ok_or_else(|| format!("No scrollback content for stream: {}", stream_id))
+    }
+}
+
+pub struct ScrollbackSink {
+    buffer: Arc<Mutex<ContentBuffer>>,
+}
+
+impl StreamSink for ScrollbackSink {
+    fn send(&mut self, data: &StreamData) -> Result<(), String> {
+        let Some(content) = &data.content else {
+            return Ok(());
+        };
+        let mut buffer = self.buffer.lock().unwrap();
+        buffer.text.extend(content.chars());
+        let overflow = buffer.text.len().saturating_sub(buffer.max_chars);
+        if overflow > 0 {
+            buffer.text.drain(..overflow);
+            buffer.dropped += overflow;
+        }
+        Ok(())
+    }
+}
+
+// 从 from_offset（字符偏移）开始取最多 max_chars 个字符，不传 max_chars 时取到末尾
+#[tauri::command]
+pub fn get_stream_content(
+    stream_id: String,
+    from_offset: usize,
+    max_chars: Option<usize>,
+    store: tauri::State<'_, ScrollbackStore>,
+) -> Result<String, String> {
+    let buffer = store.get(&stream_id)?;
+    let buffer = buffer.lock().unwrap();
+    if from_offset < buffer.dropped {
+        return Err(format!(
+            "Content before offset {} is no longer retained",
+            buffer.dropped
+        ));
+    }
+    Ok(buffer
+        .text
+        .iter()
+        .skip(from_offset - buffer.dropped)
+        .take(max_chars.unwrap_or(usize::MAX))
+        .collect())
+}
+
+#[tauri::command]
+pub fn get_stream_content_info(
+    stream_id: String,
+    store: tauri::State<'_, ScrollbackStore>,
+) -> Result<StreamContentInfo, String> {
+    let buffer = store.get(&stream_id)?;
+    let buffer = buffer.lock().unwrap();
+    Ok(StreamContentInfo {
+        total_chars: buffer.dropped + buffer.text.len(),
+        retained_from: buffer.dropped,
+    })
+}
+
+#[tauri::command]
+pub fn release_stream_content(stream_id: String, store: tauri::State<'_, ScrollbackStore>) -> bool {
+    let removed = store.buffers.lock().unwrap().remove(&stream_id).is_some();
+    store.order.lock().unwrap().retain(|id| *id != stream_id);
+    removed
+}
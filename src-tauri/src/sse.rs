@@ -0,0 +1,174 @@
+// 按 SSE 规范解析事件流：跨网络分片缓存半行，多行 data 以 \n 拼接，空行结束一个事件
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct SseEvent {
+    pub event: Option<String>,
+    pub data: String,
+}
+
+#[derive(Default)]
+pub struct SseParser {
+    buffer: Vec<u8>,
+    event: Option<String>,
+    data: Vec<String>,
+}
+
+impl SseParser {
+    pub fn feed(&mut self, chunk: &[u8]) -> Vec<SseEvent> {
+        self.buffer.extend_from_slice(chunk);
+
+        let mut events = Vec::new();
+        // 按字节切行，避免多字节字符被分片截断后解码出错
+        while let Some(pos) = self.buffer.iter().position(|&b| b == b'\n') {
+            let line_bytes: Vec<u8> = self.buffer.drain(..=pos).collect();
+            let line = String::from_utf8_lossy(&line_bytes[..pos]);
+            let line = line.strip_suffix('\r').unwrap_or(&line).to_string();
+            if let Some(event) = self.process_line(&line) {
+                events.push(event);
+            }
+        }
+        events
+    }
+
+    // 流结束时取出尚未以空行结束的事件
+    pub fn finish(&mut self) -> Option<SseEvent> {
+        if !self.buffer.is_empty() {
+            let line_bytes = std::mem::take(&mut self.buffer);
+            let line = String::from_utf8_lossy(&line_bytes).to_string();
+            if let Some(event) = self.process_line(line.strip_suffix('\r').unwrap_or(&line)) {
+                return Some(event);
+            }
+        }
+        self.dispatch()
+    }
+
+    fn process_line(&mut self, line: &str) -> Option<SseEvent> {
+        // 部分服务或转码代理会在流开头加 BOM 或多余空白，匹配字段前先去掉
+        let line = line.trim_start_matches(|c: char| c == '\u{feff}' || c.is_whitespace());
+        if line.is_empty() {
+            return self.dispatch();
+        }
+        // 冒号开头的是注释（常用作心跳）
+        if line.starts_with(':') {
+            return None;
+        }
+
+        let (field, value) = match line.split_once(':') {
+            Some((field, value)) => (field, value.strip_prefix(' ').unwrap_or(value)),
+            None => (line, ""),
+        };
+        match field {
+            "data" => self.data.push(value.to_string()),
+            "event" => self.event = Some(value.to_string()),
+            _ => {}
+        }
+        None
+    }
+
+    fn dispatch(&mut self) -> Option<SseEvent> {
+        let event = self.event.take();
+        if self.data.is_empty() {
+            return None;
+        }
+        Some(SseEvent {
+            event,
+            data: std::mem::take(&mut self.data).join("\n"),
+        })
+    }
+}
+
+// 原样切出每一行（不做字段解析），用于调试时查看服务端实际发送的内容
+#[derive(Default)]
+pub struct SseLineSplitter {
+    buffer: Vec<u8>,
+}
+
+impl SseLineSplitter {
+    pub fn feed(&mut self, chunk: &[u8]) -> Vec<String> {
+        self.buffer.extend_from_slice(chunk);
+
+        let mut lines = Vec::new();
+        while let Some(pos) = self.buffer.iter().position(|&b| b == b'\n') {
+            let line_bytes: Vec<u8> = self.buffer.drain(..=pos).collect();
+            let line = String::from_utf8_lossy(&line_bytes[..pos]);
+            lines.push(line.strip_suffix('\r').unwrap_or(&line).to_string());
+        }
+        lines
+    }
+
+    // 流结束时取出最后一个没有换行的半行
+    pub fn finish(&mut self) -> Option<String> {
+        if self.buffer.is_empty() {
+            return None;
+        }
+        let line_bytes = std::mem::take(&mut self.buffer);
+        let line = String::from_utf8_lossy(&line_bytes);
+        Some(line.strip_suffix('\r').unwrap_or(&line).to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn joins_multiple_data_lines_into_one_event() {
+        let mut parser = SseParser::default();
+        let events = parser.feed(b"data: {\"a\":\ndata: 1}\n\n");
+        assert_eq!(
+            events,
+            vec![SseEvent {
+                event: None,
+                data: "{\"a\":\n1}".to_string(),
+            }]
+        );
+        let value: serde_json::Value = serde_json::from_str(&events[0].data).unwrap();
+        assert_eq!(value["a"], 1);
+    }
+
+    #[test]
+    fn event_split_across_chunks() {
+        let mut parser = SseParser::default();
+        assert!(parser.feed(b"event: delta\nda").is_empty());
+        assert!(parser.feed(b"ta: hel").is_empty());
+        let events = parser.feed(b"lo\n\n");
+        assert_eq!(
+            events,
+            vec![SseEvent {
+                event: Some("delta".to_string()),
+                data: "hello".to_string(),
+            }]
+        );
+    }
+
+    #[test]
+    fn skips_comment_lines() {
+        let mut parser = SseParser::default();
+        let events = parser.feed(b": ping\n\n: keep-alive\ndata: x\n\n");
+        assert_eq!(events.len(), 1);
+        assert_eq!(events[0].data, "x");
+    }
+
+    #[test]
+    fn handles_crlf_line_endings() {
+        let mut parser = SseParser::default();
+        let events = parser.feed(b"data: a\r\ndata: b\r\n\r\ndata: c\r\n\r\n");
+        let data: Vec<&str> = events.iter().map(|e| e.data.as_str()).collect();
+        assert_eq!(data, vec!["a\nb", "c"]);
+    }
+
+    #[test]
+    fn tolerates_bom_and_leading_whitespace() {
+        let mut parser = SseParser::default();
+        let events = parser.feed(b"\xEF\xBB\xBF  data: first\n\n\tdata: second\n\n");
+        let data: Vec<&str> = events.iter().map(|e| e.data.as_str()).collect();
+        assert_eq!(data, vec!["first", "second"]);
+    }
+
+    #[test]
+    fn finish_flushes_unterminated_event() {
+        let mut parser = SseParser::default();
+        assert!(parser.feed(b"data: tail").is_empty());
+        assert_eq!(parser.finish().map(|e| e.data), Some("tail".to_string()));
+        assert_eq!(parser.finish(), None);
+    }
+}
@@ -0,0 +1,269 @@
+use std::convert::Infallible;
+use std::net::SocketAddr;
+use std::sync::Mutex;
+use std::time::Duration;
+
+use futures_util::stream;
+use hyper::service::{make_service_fn, service_fn};
+use hyper::{Body, Method, Request, Response, Server, StatusCode};
+use tauri::{AppHandle, EventId, Listener, Manager};
+use tokio::sync::broadcast;
+use tokio_util::sync::CancellationToken;
+
+// 每个订阅者最多积压这么多条，读得太慢时丢弃最早的并发送 lagged 注释
+const CHANNEL_CAPACITY: usize = 1024;
+// 空闲时定期发送注释行，避免被客户端或代理判定为超时
+const HEARTBEAT_INTERVAL: Duration = Duration::from_secs(15);
+
+// 前端以外的来源（例如浏览器中打开的其他网页）不能读取对话内容
+const TOKEN_HEADER: &str = "X-LightFlow-Token";
+// 打包后的 webview 来源：macOS/Linux 为 tauri://localhost，Windows 为 http(s)://tauri.localhost
+const APP_ORIGINS: &[&str] = &[
+    "tauri://localhost",
+    "http://tauri.localhost",
+    "https://tauri.localhost",
+];
+
+struct Running {
+    port: u16,
+    listener: EventId,
+    shutdown: CancellationToken,
+}
+
+// 本地 SSE 网关：把前端收到的 stream-chunk 数据原样转发给 http://127.0.0.1:<port>/events 的订阅者
+#[derive(Default)]
+pub struct LocalSseServer(Mutex<Option<Running>>);
+
+fn events_body(
+    receiver: broadcast::Receiver<String>,
+    shutdown: CancellationToken,
+) -> impl futures_util::Stream<Item = Result<String, Infallible>> {
+    stream::unfold(
+        (receiver, shutdown),
+        |(mut receiver, shutdown)| async move {
+            let frame = tokio::select! {
+                _ = shutdown.cancelled() => return None,
+                _ = tokio::time::sleep(HEARTBEAT_INTERVAL) => ": ping\n\n".to_string(),
+                message = receiver.recv() => match message {
+                    Ok(data) => format!("data: {}\n\n", data),
+                    Err(broadcast::error::RecvError::Lagged(skipped)) => {
+                        format!(": lagged {}\n\n", skipped)
+                    }
+                    Err(broadcast::error::RecvError::Closed) => return None,
+                },
+            };
+            Some((Ok(frame), (receiver, shutdown)))
+        },
+    )
+}
+
+// 每次启动生成的随机令牌
+fn new_token() -> Result<String, String> {
+    let mut bytes = [0u8; 32];
+    getrandom::getrandom(&mut bytes)
+        .map_err(|e| format!("Failed to generate local SSE server token: {}", e))?;
+    Ok(bytes.iter().map(|b| format!("{:02x}", b)).collect())
+}
+
+// 逐字节比较全部内容，耗时与令牌在第几位不同无关
+fn same_token(a: &str, b: &str) -> bool {
+    a.len() == b.len()
+        && a.bytes()
+            .zip(b.bytes())
+            .fold(0, |diff, (x, y)| diff | (x ^ y))
+            == 0
+}
+
+// EventSource 无法设置请求头，因此令牌也可以放在 ?token= 中
+fn request_token(request: &Request<Body>) -> Option<&str> {
+    let header = request
+        .headers()
+        .get(TOKEN_HEADER)
+        .and_then(|v| v.to_str().ok());
+    header.or_else(|| {
+        request
+            .uri()
+            .query()?
+            .split('&')
+            .find_map(|pair| pair.strip_prefix("token="))
+    })
+}
+
+// 浏览器发起的请求总会带 Origin，不是应用自身的一律拒绝；命令行等非浏览器客户端只需令牌
+fn authorize(request: &Request<Body>, token: &str, origins: &[String]) -> Result<(), StatusCode> {
+    let origin = request
+        .headers()
+        .get(hyper::header::ORIGIN)
+        .map(|v| v.to_str().unwrap_or_default());
+    if origin.is_some_and(|origin| !origins.iter().any(|allowed| allowed == origin)) {
+        return Err(StatusCode::FORBIDDEN);
+    }
+    match request_token(request) {
+        Some(given) if same_token(given, token) => Ok(()),
+        _ => Err(StatusCode::UNAUTHORIZED),
+    }
+}
+
+fn status_response(status: StatusCode) -> Response<Body> {
+    let mut response = Response::new(Body::from(status.canonical_reason().unwrap_or_default()));
+    *response.status_mut() = status;
+    response
+}
+
+async fn handle(
+    request: Request<Body>,
+    sender: broadcast::Sender<String>,
+    shutdown: CancellationToken,
+    token: std::sync::Arc<str>,
+    origins: std::sync::Arc<Vec<String>>,
+) -> Result<Response<Body>, Infallible> {
+    if request.method() != Method::GET || request.uri().path() != "/events" {
+        return Ok(status_response(StatusCode::NOT_FOUND));
+    }
+    if let Err(status) = authorize(&request, &token, &origins) {
+        return Ok(status_response(status));
+    }
+    let body = Body::wrap_stream(events_body(sender.subscribe(), shutdown));
+    let response = Response::builder()
+        .header("Content-Type", "text/event-stream")
+        .header("Cache-Control", "no-cache")
+        .body(body);
+    Ok(response.unwrap_or_else(|_| Response::new(Body::empty())))
+}
+
+// 只监听本机回环地址。返回访问令牌，订阅时放在 ?token= 或 X-LightFlow-Token 请求头中
+#[tauri::command]
+pub async fn start_local_sse_server(port: u16, app_handle: AppHandle) -> Result<String, String> {
+    let state = app_handle.state::<LocalSseServer>();
+    if let Some(running) = state.0.lock().unwrap().as_ref() {
+        return Err(format!(
+            "Local SSE server is already running on port {}",
+            running.port
+        ));
+    }
+
+    let addr = SocketAddr::from(([127, 0, 0, 1], port));
+    let builder = Server::try_bind(&addr)
+        .map_err(|e| format!("Failed to bind local SSE server to {}: {}", addr, e))?;
+
+    let token = new_token()?;
+    let mut origins: Vec<String> = APP_ORIGINS.iter().map(|o| o.to_string()).collect();
+    // 开发模式下前端由 devUrl 提供
+    if cfg!(debug_assertions) {
+        if let Some(dev_url) = &app_handle.config().build.dev_url {
+            origins.push(dev_url.origin().ascii_serialization());
+        }
+    }
+
+    let (sender, _) = broadcast::channel::<String>(CHANNEL_CAPACITY);
+    let shutdown = CancellationToken::new();
+    let make_service = {
+        let sender = sender.clone();
+        let shutdown = shutdown.clone();
+        let token: std::sync::Arc<str> = token.clone().into();
+        let origins = std::sync::Arc::new(origins);
+        make_service_fn(move |_| {
+            let sender = sender.clone();
+            let shutdown = shutdown.clone();
+            let token = token.clone();
+            let origins = origins.clone();
+            async move {
+                Ok::<_, Infallible>(service_fn(move |request| {
+                    handle(
+                        request,
+                        sender.clone(),
+                        shutdown.clone(),
+                        token.clone(),
+                        origins.clone(),
+                    )
+                }))
+            }
+        })
+    };
+    let server = builder
+        .serve(make_service)
+        .with_graceful_shutdown(shutdown.clone().cancelled_owned());
+
+    let listener = app_handle.listen_any("stream-chunk", move |event| {
+        // 没有订阅者时发送失败，直接忽略
+        let _ = sender.send(event.payload().to_string());
+    });
+
+    let mut running = state.0.lock().unwrap();
+    if running.is_some() {
+        app_handle.unlisten(listener);
+        shutdown.cancel();
+        return Err("Local SSE server is already running".to_string());
+    }
+    *running = Some(Running {
+        port,
+        listener,
+        shutdown,
+    });
+    tauri::async_runtime::spawn(async move {
+        if let Err(e) = server.await {
+            log::warn!("Local SSE server stopped: {}", e);
+        }
+    });
+    log::info!("Local SSE server listening on http://{}/events", addr);
+    Ok(token)
+}
+
+// 断开所有订阅者并释放端口，返回之前是否在运行
+#[tauri::command]
+pub fn stop_local_sse_server(app_handle: AppHandle) -> bool {
+    let running = app_handle
+        .state::<LocalSseServer>()
+        .0
+        .lock()
+        .unwrap()
+        .take();
+    let Some(running) = running else {
+        return false;
+    };
+    app_handle.unlisten(running.listener);
+    running.shutdown.cancel();
+    true
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TOKEN: &str = "secret";
+
+    fn check(uri: &str, headers: &[(&str, &str)]) -> Result<(), StatusCode> {
+        let mut request = Request::builder().uri(uri);
+        for (name, value) in headers {
+            request = request.header(*name, *value);
+        }
+        let origins = vec!["tauri://localhost".to_string()];
+        authorize(&request.body(Body::empty()).unwrap(), TOKEN, &origins)
+    }
+
+    #[test]
+    fn requires_matching_token() {
+        assert_eq!(check("/events", &[]), Err(StatusCode::UNAUTHORIZED));
+        assert_eq!(
+            check("/events?token=wrong", &[]),
+            Err(StatusCode::UNAUTHORIZED)
+        );
+        assert_eq!(check("/events?a=1&token=secret", &[]), Ok(()));
+        assert_eq!(check("/events", &[(TOKEN_HEADER, "secret")]), Ok(()));
+    }
+
+    #[test]
+    fn rejects_foreign_origins_even_with_token() {
+        assert_eq!(
+            check(
+                "/events?token=secret",
+                &[("Origin", "https://evil.example")]
+            ),
+            Err(StatusCode::FORBIDDEN)
+        );
+        assert_eq!(
+            check("/events?token=secret", &[("Origin", "tauri://localhost")]),
+            Ok(())
+        );
+    }
+}
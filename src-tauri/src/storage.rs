@@ -0,0 +1,695 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::PathBuf;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use serde::{Deserialize, Serialize};
+use tauri::{AppHandle, Manager};
+
+use crate::model_alias::canonicalize_model;
+use crate::provider::Provider;
+use crate::Message;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Conversation {
+    pub id: String,
+    pub title: String,
+    pub messages: Vec<Message>,
+    pub created_at: u64,
+    pub updated_at: u64,
+    #[serde(default)]
+    pub tags: Vec<String>,
+    // 最后一条助手消息因取消或程序退出而未生成完，可以继续生成
+    #[serde(default)]
+    pub resumable: bool,
+    // 锁定后，带该会话 id 的聊天请求使用其他模型 / 服务商会被拒绝（除非 override_lock）
+    #[serde(default)]
+    pub locked_model: Option<String>,
+    #[serde(default)]
+    pub locked_provider: Option<Provider>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ConversationMeta {
+    pub id: String,
+    pub title: String,
+    pub message_count: usize,
+    pub created_at: u64,
+    pub updated_at: u64,
+    pub tags: Vec<String>,
+    pub locked_model: Option<String>,
+    pub locked_provider: Option<Provider>,
+}
+
+// 导出文件格式：所有会话连同元数据打包成一个 JSON
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ConversationArchive {
+    pub version: u32,
+    pub exported_at: u64,
+    pub conversations: Vec<serde_json::Value>,
+}
+
+#[derive(Debug, Default, Serialize)]
+pub struct ImportSummary {
+    pub imported: usize,
+    // id 冲突且内容不同，以新 id 导入
+    pub renamed: usize,
+    // id 冲突且内容相同，视为重复跳过
+    pub skipped: usize,
+    // 格式损坏无法解析的条目
+    pub invalid: usize,
+}
+
+pub fn now_millis() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_millis() as u64)
+        .unwrap_or(0)
+}
+
+impl From<&Conversation> for ConversationMeta {
+    fn from(conversation: &Conversation) -> Self {
+        ConversationMeta {
+            id: conversation.id.clone(),
+            title: conversation.title.clone(),
+            message_count: conversation.messages.len(),
+            created_at: conversation.created_at,
+            updated_at: conversation.updated_at,
+            tags: conversation.tags.clone(),
+            locked_model: conversation.locked_model.clone(),
+            locked_provider: conversation.locked_provider,
+        }
+    }
+}
+
+// 会话以 <id>.json 的形式保存在应用数据目录下
+fn conversations_dir(app_handle: &AppHandle) -> Result<PathBuf, String> {
+    let dir = app_handle
+        .path()
+        .app_data_dir()
+        .map_err(|e| format!("Failed to resolve app data dir: {}", e))?
+        .join("conversations");
+    fs::create_dir_all(&dir).map_err(|e| format!("Failed to create conversations dir: {}", e))?;
+    Ok(dir)
+}
+
+// id 会拼进文件名，只允许安全字符，防止路径穿越
+fn valid_id(id: &str) -> bool {
+    !id.is_empty()
+        && id
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+}
+
+fn conversation_path(app_handle: &AppHandle, id: &str) -> Result<PathBuf, String> {
+    if !valid_id(id) {
+        return Err(format!("Invalid conversation id: {}", id));
+    }
+    Ok(conversations_dir(app_handle)?.join(format!("{}.json", id)))
+}
+
+pub fn load(app_handle: &AppHandle, id: &str) -> Result<Conversation, String> {
+    let path = conversation_path(app_handle, id)?;
+    let text = fs::read_to_string(&path)
+        .map_err(|e| format!("Failed to read conversation {}: {}", id, e))?;
+    serde_json::from_str(&text).map_err(|e| format!("Failed to parse conversation {}: {}", id, e))
+}
+
+pub fn save(app_handle: &AppHandle, conversation: &Conversation) -> Result<(), String> {
+    let path = conversation_path(app_handle, &conversation.id)?;
+    let text = serde_json::to_string_pretty(conversation)
+        .map_err(|e| format!("Failed to serialize conversation: {}", e))?;
+    // 先写临时文件再重命名，避免写到一半损坏原文件
+    let tmp = path.with_extension("json.tmp");
+    fs::write(&tmp, text).map_err(|e| format!("Failed to write conversation: {}", e))?;
+    fs::rename(&tmp, &path).map_err(|e| format!("Failed to write conversation: {}", e))
+}
+
+pub fn load_all(app_handle: &AppHandle) -> Result<Vec<Conversation>, String> {
+    let dir = conversations_dir(app_handle)?;
+    let entries =
+        fs::read_dir(&dir).map_err(|e| format!("Failed to read conversations dir: {}", e))?;
+
+    let mut conversations = Vec::new();
+    for entry in entries.flatten() {
+        let path = entry.path();
+        if path.extension().and_then(|e| e.to_str()) != Some("json") {
+            continue;
+        }
+        // 单个文件损坏不影响其他会话的读取
+        match fs::read_to_string(&path)
+            .ok()
+            .and_then(|text| serde_json::from_str::<Conversation>(&text).ok())
+        {
+            Some(conversation) => conversations.push(conversation),
+            None => log::warn!("Skipping unreadable conversation file {:?}", path),
+        }
+    }
+    conversations.sort_by_key(|c| std::cmp::Reverse(c.updated_at));
+    Ok(conversations)
+}
+
+// 覆盖会话前保留一份副本，放在单独的目录里，不会出现在会话列表中
+pub fn backup(app_handle: &AppHandle, conversation: &Conversation) -> Result<PathBuf, String> {
+    // 复用 id 校验
+    conversation_path(app_handle, &conversation.id)?;
+    let dir = app_handle
+        .path()
+        .app_data_dir()
+        .map_err(|e| format!("Failed to resolve app data dir: {}", e))?
+        .join("conversation_backups");
+    fs::create_dir_all(&dir).map_err(|e| format!("Failed to create backups dir: {}", e))?;
+    let path = dir.join(format!("{}-{}.json", conversation.id, now_millis()));
+    let text = serde_json::to_string_pretty(conversation)
+        .map_err(|e| format!("Failed to serialize conversation: {}", e))?;
+    fs::write(&path, text).map_err(|e| format!("Failed to write backup: {}", e))?;
+    Ok(path)
+}
+
+pub fn exists(app_handle: &AppHandle, id: &str) -> Result<bool, String> {
+    Ok(conversation_path(app_handle, id)?.exists())
+}
+
+#[tauri::command]
+pub async fn save_conversation(
+    conversation: Conversation,
+    app_handle: AppHandle,
+) -> Result<(), String> {
+    save(&app_handle, &conversation)
+}
+
+#[tauri::command]
+pub async fn load_conversation(id: String, app_handle: AppHandle) -> Result<Conversation, String> {
+    load(&app_handle, &id)
+}
+
+#[tauri::command]
+pub async fn list_conversations(app_handle: AppHandle) -> Result<Vec<ConversationMeta>, String> {
+    Ok(load_all(&app_handle)?
+        .iter()
+        .map(ConversationMeta::from)
+        .collect())
+}
+
+fn set_pinned(app_handle: &AppHandle, id: &str, index: usize, pinned: bool) -> Result<(), String> {
+    let mut conversation = load(app_handle, id)?;
+    let count = conversation.messages.len();
+    let message = conversation.messages.get_mut(index).ok_or_else(|| {
+        format!(
+            "Message index {} out of range for conversation {} ({} messages)",
+            index, id, count
+        )
+    })?;
+    message.pinned = pinned.then_some(true);
+    conversation.updated_at = now_millis();
+    save(app_handle, &conversation)
+}
+
+#[tauri::command]
+pub async fn pin_message(
+    conversation_id: String,
+    index: usize,
+    app_handle: AppHandle,
+) -> Result<(), String> {
+    set_pinned(&app_handle, &conversation_id, index, true)
+}
+
+#[tauri::command]
+pub async fn unpin_message(
+    conversation_id: String,
+    index: usize,
+    app_handle: AppHandle,
+) -> Result<(), String> {
+    set_pinned(&app_handle, &conversation_id, index, false)
+}
+
+#[tauri::command]
+pub async fn delete_conversation(id: String, app_handle: AppHandle) -> Result<(), String> {
+    let path = conversation_path(&app_handle, &id)?;
+    fs::remove_file(&path).map_err(|e| format!("Failed to delete conversation {}: {}", id, e))
+}
+
+#[tauri::command]
+pub async fn export_all_conversations(
+    path: String,
+    app_handle: AppHandle,
+) -> Result<String, String> {
+    let conversations = load_all(&app_handle)?
+        .iter()
+        .map(serde_json::to_value)
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|e| format!("Failed to serialize conversation: {}", e))?;
+
+    let archive = ConversationArchive {
+        version: 1,
+        exported_at: now_millis(),
+        conversations,
+    };
+    let text = serde_json::to_string_pretty(&archive)
+        .map_err(|e| format!("Failed to serialize archive: {}", e))?;
+    fs::write(&path, text).map_err(|e| format!("Failed to write archive {}: {}", path, e))?;
+    Ok(path)
+}
+
+// 微调数据中的一条消息：只保留 role 和 content，去掉 pinned 等本地字段，
+// 助手回复中的 <think> 思考内容也一并去掉
+fn finetuning_message(message: &Message) -> Option<serde_json::Value> {
+    if !matches!(message.role.as_str(), "system" | "user" | "assistant") {
+        return None;
+    }
+    let content = match &message.content {
+        serde_json::Value::String(text) if message.role == "assistant" => {
+            serde_json::Value::String(crate::think::extract_think_tags(text).content)
+        }
+        content => content.clone(),
+    };
+    let empty = match &content {
+        serde_json::Value::String(text) => text.trim().is_empty(),
+        serde_json::Value::Array(parts) => parts.is_empty(),
+        _ => true,
+    };
+    (!empty).then(|| serde_json::json!({ "role": message.role, "content": content }))
+}
+
+// 按 OpenAI 微调格式导出，每个会话一行 {"messages": [...]}；
+// 缺少用户或助手消息的会话跳过，返回实际写入的条数
+#[tauri::command]
+pub async fn export_finetuning_jsonl(
+    conversation_ids: Vec<String>,
+    path: String,
+    app_handle: AppHandle,
+) -> Result<usize, String> {
+    let mut lines = Vec::new();
+    for id in &conversation_ids {
+        let conversation = load(&app_handle, id)?;
+        let messages: Vec<serde_json::Value> = conversation
+            .messages
+            .iter()
+            .filter_map(finetuning_message)
+            .collect();
+        let has_role = |role: &str| messages.iter().any(|m| m["role"] == role);
+        if !has_role("user") || !has_role("assistant") {
+            log::warn!(
+                "Skipping conversation {}: fine-tuning examples need a user and an assistant message",
+                id
+            );
+            continue;
+        }
+        let line = serde_json::to_string(&serde_json::json!({ "messages": messages }))
+            .map_err(|e| format!("Failed to serialize conversation {}: {}", id, e))?;
+        lines.push(line);
+    }
+
+    let mut text = lines.join("\n");
+    if !text.is_empty() {
+        text.push('\n');
+    }
+    fs::write(&path, text).map_err(|e| format!("Failed to write {}: {}", path, e))?;
+    Ok(lines.len())
+}
+
+// 除 id 外内容完全相同
+fn same_content(a: &Conversation, b: &Conversation) -> bool {
+    let value = |c: &Conversation| {
+        serde_json::to_value(Conversation {
+            id: String::new(),
+            ..c.clone()
+        })
+        .ok()
+    };
+    value(a) == value(b)
+}
+
+// 导入逻辑与存储位置无关：load 读取已有会话（不存在时为 None），save 写入。
+// id 冲突时依次检查 {id} 与 {id}-imported-N，内容与其中任何一个相同都视为重复，
+// 因此重复导入同一份归档不会产生新的副本
+fn import_archive(
+    path: &str,
+    text: &str,
+    load: impl Fn(&str) -> Result<Option<Conversation>, String>,
+    mut save: impl FnMut(&Conversation) -> Result<(), String>,
+) -> Result<ImportSummary, String> {
+    let archive: ConversationArchive =
+        serde_json::from_str(text).map_err(|e| format!("Invalid archive {}: {}", path, e))?;
+
+    let mut summary = ImportSummary::default();
+
+    'conversations: for value in archive.conversations {
+        let Ok(mut conversation) = serde_json::from_value::<Conversation>(value) else {
+            summary.invalid += 1;
+            continue;
+        };
+        if !valid_id(&conversation.id) {
+            summary.invalid += 1;
+            continue;
+        }
+
+        let base_id = conversation.id.clone();
+        let mut candidate = base_id.clone();
+        let mut n = 0;
+        while let Some(existing) = load(&candidate)? {
+            if same_content(&existing, &conversation) {
+                summary.skipped += 1;
+                continue 'conversations;
+            }
+            n += 1;
+            candidate = format!("{}-imported-{}", base_id, n);
+        }
+        if n == 0 {
+            summary.imported += 1;
+        } else {
+            conversation.id = candidate;
+            summary.renamed += 1;
+        }
+        save(&conversation)?;
+    }
+
+    Ok(summary)
+}
+
+#[tauri::command]
+pub async fn import_all_conversations(
+    path: String,
+    app_handle: AppHandle,
+) -> Result<ImportSummary, String> {
+    let text =
+        fs::read_to_string(&path).map_err(|e| format!("Failed to read archive {}: {}", path, e))?;
+    import_archive(
+        &path,
+        &text,
+        |id| match exists(&app_handle, id)? {
+            true => load(&app_handle, id).map(Some),
+            false => Ok(None),
+        },
+        |conversation| save(&app_handle, conversation),
+    )
+}
+
+// 标签统一为小写并合并多余空白，去重后保持输入顺序
+fn normalize_tags(tags: Vec<String>) -> Vec<String> {
+    let mut normalized: Vec<String> = Vec::new();
+    for tag in tags {
+        let tag = tag
+            .split_whitespace()
+            .collect::<Vec<_>>()
+            .join(" ")
+            .to_lowercase();
+        if !tag.is_empty() && !normalized.contains(&tag) {
+            normalized.push(tag);
+        }
+    }
+    normalized
+}
+
+#[tauri::command]
+pub async fn set_conversation_tags(
+    id: String,
+    tags: Vec<String>,
+    app_handle: AppHandle,
+) -> Result<Vec<String>, String> {
+    let mut conversation = load(&app_handle, &id)?;
+    conversation.tags = normalize_tags(tags);
+    save(&app_handle, &conversation)?;
+    Ok(conversation.tags)
+}
+
+#[tauri::command]
+pub async fn get_conversations_by_tag(
+    tag: String,
+    app_handle: AppHandle,
+) -> Result<Vec<ConversationMeta>, String> {
+    let Some(tag) = normalize_tags(vec![tag]).pop() else {
+        return Ok(Vec::new());
+    };
+    Ok(load_all(&app_handle)?
+        .iter()
+        .filter(|c| c.tags.contains(&tag))
+        .map(ConversationMeta::from)
+        .collect())
+}
+
+// 会话锁定了模型 / 服务商时检查本次请求是否一致；会话不存在时不检查。
+// 模型名按别名表规范化后比较，大小写或前缀不同不算切换
+pub fn check_model_lock(
+    app_handle: &AppHandle,
+    id: &str,
+    model: &str,
+    provider: Provider,
+) -> Result<(), String> {
+    if !exists(app_handle, id)? {
+        return Ok(());
+    }
+    let conversation = load(app_handle, id)?;
+    if let Some(locked) = &conversation.locked_model {
+        let locked_name = canonicalize_model(provider, locked.clone());
+        if locked_name != canonicalize_model(provider, model.to_string()) {
+            return Err(format!(
+                "Conversation {} is locked to model {}, but {} was requested; set override_lock to switch",
+                id, locked, model
+            ));
+        }
+    }
+    if let Some(locked) = conversation.locked_provider.filter(|&p| p != provider) {
+        return Err(format!(
+            "Conversation {} is locked to provider {:?}, but {:?} was requested; set override_lock to switch",
+            id, locked, provider
+        ));
+    }
+    Ok(())
+}
+
+// 锁定会话使用的模型，provider 不传时只锁定模型
+#[tauri::command]
+pub async fn lock_conversation_model(
+    id: String,
+    model: String,
+    provider: Option<Provider>,
+    app_handle: AppHandle,
+) -> Result<(), String> {
+    if model.trim().is_empty() {
+        return Err("Locked model cannot be empty".to_string());
+    }
+    let mut conversation = load(&app_handle, &id)?;
+    conversation.locked_model = Some(model);
+    conversation.locked_provider = provider;
+    conversation.updated_at = now_millis();
+    save(&app_handle, &conversation)
+}
+
+#[tauri::command]
+pub async fn unlock_conversation_model(id: String, app_handle: AppHandle) -> Result<(), String> {
+    let mut conversation = load(&app_handle, &id)?;
+    conversation.locked_model = None;
+    conversation.locked_provider = None;
+    conversation.updated_at = now_millis();
+    save(&app_handle, &conversation)
+}
+
+// 返回所有标签及其使用次数，按标签名排序
+#[tauri::command]
+pub async fn list_all_tags(app_handle: AppHandle) -> Result<Vec<(String, usize)>, String> {
+    let mut counts = BTreeMap::new();
+    for conversation in load_all(&app_handle)? {
+        for tag in conversation.tags {
+            *counts.entry(tag).or_insert(0) += 1;
+        }
+    }
+    Ok(counts.into_iter().collect())
+}
+
+// 两个会话（通常是同一会话的不同分支）的差异：公共前缀之后各自不同的消息
+#[derive(Debug, Clone, Serialize)]
+pub struct ConversationDiff {
+    pub common_prefix_len: usize,
+    pub only_in_a: Vec<Message>,
+    pub only_in_b: Vec<Message>,
+}
+
+impl ConversationDiff {
+    fn between(a: &[Message], b: &[Message]) -> Self {
+        let common_prefix_len = a
+            .iter()
+            .zip(b)
+            .take_while(|(x, y)| x.role == y.role && x.content == y.content)
+            .count();
+
+        ConversationDiff {
+            common_prefix_len,
+            only_in_a: a[common_prefix_len..].to_vec(),
+            only_in_b: b[common_prefix_len..].to_vec(),
+        }
+    }
+}
+
+#[tauri::command]
+pub async fn diff_conversations(
+    id_a: String,
+    id_b: String,
+    app_handle: AppHandle,
+) -> Result<ConversationDiff, String> {
+    let a = load(&app_handle, &id_a)?.messages;
+    let b = load(&app_handle, &id_b)?.messages;
+    Ok(ConversationDiff::between(&a, &b))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn text(role: &str, content: &str) -> Message {
+        Message {
+            role: role.to_string(),
+            content: serde_json::Value::String(content.to_string()),
+            pinned: None,
+        }
+    }
+
+    fn contents(messages: &[Message]) -> Vec<&str> {
+        messages.iter().filter_map(|m| m.content.as_str()).collect()
+    }
+
+    #[test]
+    fn branches_diverge_after_common_prefix() {
+        let a = vec![
+            text("user", "hi"),
+            text("assistant", "hello"),
+            text("user", "tell me a joke"),
+        ];
+        let b = vec![
+            text("user", "hi"),
+            text("assistant", "hello"),
+            text("user", "tell me a story"),
+            text("assistant", "once upon a time"),
+        ];
+        let diff = ConversationDiff::between(&a, &b);
+        assert_eq!(diff.common_prefix_len, 2);
+        assert_eq!(contents(&diff.only_in_a), ["tell me a joke"]);
+        assert_eq!(
+            contents(&diff.only_in_b),
+            ["tell me a story", "once upon a time"]
+        );
+    }
+
+    #[test]
+    fn prefix_branch_has_nothing_of_its_own() {
+        let a = vec![text("user", "hi")];
+        let b = vec![text("user", "hi"), text("assistant", "hello")];
+        let diff = ConversationDiff::between(&a, &b);
+        assert_eq!(diff.common_prefix_len, 1);
+        assert!(diff.only_in_a.is_empty());
+        assert_eq!(contents(&diff.only_in_b), ["hello"]);
+    }
+
+    #[test]
+    fn different_role_breaks_prefix() {
+        let a = vec![text("system", "be brief"), text("user", "hi")];
+        let b = vec![text("user", "be brief"), text("user", "hi")];
+        let diff = ConversationDiff::between(&a, &b);
+        assert_eq!(diff.common_prefix_len, 0);
+        assert_eq!(diff.only_in_a.len(), 2);
+        assert_eq!(diff.only_in_b.len(), 2);
+    }
+
+    #[test]
+    fn pinned_flag_does_not_count_as_difference() {
+        let mut pinned = text("user", "hi");
+        pinned.pinned = Some(true);
+        let diff = ConversationDiff::between(&[pinned], &[text("user", "hi")]);
+        assert_eq!(diff.common_prefix_len, 1);
+    }
+
+    fn conversation(id: &str, reply: &str) -> Conversation {
+        Conversation {
+            id: id.to_string(),
+            title: "Greeting".to_string(),
+            messages: vec![text("user", "hi"), text("assistant", reply)],
+            created_at: 1,
+            updated_at: 2,
+            tags: Vec::new(),
+            resumable: false,
+            locked_model: None,
+            locked_provider: None,
+        }
+    }
+
+    fn archive(conversations: Vec<serde_json::Value>) -> String {
+        serde_json::to_string(&ConversationArchive {
+            version: 1,
+            exported_at: 0,
+            conversations,
+        })
+        .unwrap()
+    }
+
+    // 在内存中模拟会话目录
+    fn import(
+        store: &mut BTreeMap<String, Conversation>,
+        text: &str,
+    ) -> Result<ImportSummary, String> {
+        let existing = store.clone();
+        let mut saved = Vec::new();
+        let summary = import_archive(
+            "archive.json",
+            text,
+            |id| Ok(existing.get(id).cloned()),
+            |c| {
+                saved.push(c.clone());
+                Ok(())
+            },
+        )?;
+        for c in saved {
+            store.insert(c.id.clone(), c);
+        }
+        Ok(summary)
+    }
+
+    #[test]
+    fn colliding_ids_are_renamed_once() {
+        let mut store = BTreeMap::new();
+        store.insert("c1".to_string(), conversation("c1", "hello"));
+        let text = archive(vec![
+            serde_json::to_value(conversation("c1", "hello")).unwrap(),
+            serde_json::to_value(conversation("c1", "hey there")).unwrap(),
+            serde_json::to_value(conversation("c2", "hi")).unwrap(),
+        ]);
+
+        let first = import(&mut store, &text).unwrap();
+        assert_eq!(
+            (first.imported, first.renamed, first.skipped, first.invalid),
+            (1, 1, 1, 0)
+        );
+        assert_eq!(store["c1-imported-1"].messages[1].content, "hey there");
+
+        // 再次导入同一份归档，已导入的副本被识别为重复
+        let again = import(&mut store, &text).unwrap();
+        assert_eq!((again.imported, again.renamed, again.skipped), (0, 0, 3));
+        assert_eq!(
+            store.keys().collect::<Vec<_>>(),
+            ["c1", "c1-imported-1", "c2"]
+        );
+    }
+
+    #[test]
+    fn corrupted_entries_are_counted_not_imported() {
+        let mut store = BTreeMap::new();
+        let text = archive(vec![
+            serde_json::json!({ "id": "broken", "title": 5 }),
+            serde_json::to_value(conversation("../escape", "hi")).unwrap(),
+            serde_json::to_value(conversation("ok", "hi")).unwrap(),
+        ]);
+        let summary = import(&mut store, &text).unwrap();
+        assert_eq!((summary.imported, summary.invalid), (1, 2));
+        assert_eq!(store.keys().collect::<Vec<_>>(), ["ok"]);
+    }
+
+    #[test]
+    fn truncated_archive_is_rejected() {
+        let text = archive(vec![serde_json::to_value(conversation("c1", "hi")).unwrap()]);
+        let error = import(&mut BTreeMap::new(), &text[..text.len() / 2]).unwrap_err();
+        assert!(
+            error.starts_with("Invalid archive archive.json: "),
+            "{}",
+            error
+        );
+    }
+}
@@ -0,0 +1,315 @@
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use tokio_util::sync::{CancellationToken, DropGuard};
+
+use crate::emit::{Acked, RecentChunks};
+use crate::StreamData;
+
+// 流结束后保留其缓冲这么久（或直到 release_stream），供订阅较晚的前端补取
+const FINISHED_GRACE: Duration = Duration::from_secs(60);
+
+/// 正在进行的流式请求注册表，按 stream_id（即交给前端的句柄）管理取消令牌。
+///
+/// 所有权模型：注册表中的每一项持有该流取消令牌的 `DropGuard`，
+/// 条目被移除（前端调用 `release_stream`、同 id 被新流替换）时令牌随之取消，
+/// 因此只要条目不在注册表里，对应请求就不会继续运行。
+/// 流自然结束后条目再保留 `FINISHED_GRACE`，期间仍可用 `get_stream_buffer` 补取。
+/// 前端可以先用 `acquire_stream_handle` 领取句柄，再作为 `stream_id` 传给流式命令；
+/// 用完后调用 `release_stream` 归还，即使流还在进行也会被中止。
+#[derive(Default)]
+pub struct StreamRegistry {
+    streams: Mutex<HashMap<String, StreamEntry>>,
+    next_seq: AtomicU64,
+}
+
+struct StreamEntry {
+    seq: u64,
+    token: CancellationToken,
+    // 是否已有命令在使用该句柄；仅领取未使用的句柄不算活跃流
+    active: bool,
+    // 流结束的时间；结束后条目暂时保留，只用于补取缓冲
+    finished_at: Option<Instant>,
+    recent: RecentChunks,
+    acked: Acked,
+    _cancel_on_drop: DropGuard,
+}
+
+impl StreamEntry {
+    fn is_running(&self) -> bool {
+        self.active && self.finished_at.is_none()
+    }
+
+    fn new(seq: u64, token: CancellationToken, active: bool) -> Self {
+        StreamEntry {
+            seq,
+            _cancel_on_drop: token.clone().drop_guard(),
+            token,
+            active,
+            finished_at: None,
+            recent: RecentChunks::default(),
+            acked: Acked::default(),
+        }
+    }
+}
+
+// 命令一侧持有的注册凭证，离开作用域时自动注销
+pub struct StreamGuard<'a> {
+    registry: &'a StreamRegistry,
+    id: String,
+    seq: u64,
+    pub token: CancellationToken,
+    pub recent: RecentChunks,
+    pub acked: Acked,
+}
+
+impl StreamRegistry {
+    fn next_seq(&self) -> u64 {
+        self.next_seq.fetch_add(1, Ordering::Relaxed)
+    }
+
+    // 清理超过保留时间的已结束条目
+    fn streams(&self) -> std::sync::MutexGuard<'_, HashMap<String, StreamEntry>> {
+        let mut streams = self.streams.lock().unwrap();
+        streams.retain(|_, entry| {
+            entry
+                .finished_at
+                .is_none_or(|finished| finished.elapsed() < FINISHED_GRACE)
+        });
+        streams
+    }
+
+    // 预先生成一个句柄，供前端在发起流式请求前持有
+    pub fn acquire(&self) -> String {
+        let seq = self.next_seq();
+        let id = format!("stream-{}", seq);
+        self.streams().insert(
+            id.clone(),
+            StreamEntry::new(seq, CancellationToken::new(), false),
+        );
+        id
+    }
+
+    // 注册一个流；未指定 id 时自动生成。
+    // 若 id 是领取后尚未使用的句柄则沿用其令牌，否则替换同 id 的旧流（旧流会被取消）
+    pub fn register(&self, stream_id: Option<String>) -> StreamGuard<'_> {
+        let seq = self.next_seq();
+        let id = stream_id.unwrap_or_else(|| format!("stream-{}", seq));
+
+        let mut streams = self.streams();
+        // 沿用领取的令牌时先解除旧条目的 DropGuard，否则替换旧条目会把这个令牌取消掉；
+        // 被替换的活跃旧流则随旧条目的释放而取消
+        let token = match streams.remove(&id) {
+            Some(entry) if !entry.active => {
+                entry._cancel_on_drop.disarm();
+                entry.token
+            }
+            _ => CancellationToken::new(),
+        };
+        let entry = StreamEntry::new(seq, token.clone(), true);
+        let recent = entry.recent.clone();
+        let acked = entry.acked.clone();
+        streams.insert(id.clone(), entry);
+
+        StreamGuard {
+            registry: self,
+            id,
+            seq,
+            token,
+            recent,
+            acked,
+        }
+    }
+
+    pub fn cancel(&self, stream_id: &str) -> bool {
+        match self.streams().get(stream_id) {
+            Some(entry) => {
+                entry.token.cancel();
+                true
+            }
+            None => false,
+        }
+    }
+
+    pub fn recent(&self, stream_id: &str) -> Option<Vec<StreamData>> {
+        let streams = self.streams();
+        let entry = streams.get(stream_id)?;
+        let recent = entry.recent.lock().unwrap();
+        Some(recent.iter().cloned().collect())
+    }
+
+    // 前端处理完 seq 及之前的所有事件；确认可能乱序到达，只前进不后退
+    pub fn ack(&self, stream_id: &str, seq: u64) -> bool {
+        match self.streams().get(stream_id) {
+            Some(entry) => {
+                entry.acked.fetch_max(seq + 1, Ordering::Relaxed);
+                true
+            }
+            None => false,
+        }
+    }
+
+    // 移除条目即取消对应请求
+    pub fn release(&self, stream_id: &str) -> bool {
+        self.streams().remove(stream_id).is_some()
+    }
+
+    pub fn cancel_all(&self) -> usize {
+        let streams = self.streams();
+        for entry in streams.values() {
+            entry.token.cancel();
+        }
+        streams.values().filter(|entry| entry.is_running()).count()
+    }
+
+    // 是否还有正在运行的流
+    pub fn is_empty(&self) -> bool {
+        !self
+            .streams
+            .lock()
+            .unwrap()
+            .values()
+            .any(StreamEntry::is_running)
+    }
+
+    // 只标记自己注册的那一项，避免误改同 id 的新流
+    fn unregister(&self, stream_id: &str, seq: u64) {
+        let mut streams = self.streams();
+        if let Some(entry) = streams.get_mut(stream_id).filter(|entry| entry.seq == seq) {
+            entry.finished_at = Some(Instant::now());
+        }
+    }
+}
+
+impl StreamGuard<'_> {
+    pub fn id(&self) -> &str {
+        &self.id
+    }
+}
+
+impl Drop for StreamGuard<'_> {
+    fn drop(&mut self) {
+        self.registry.unregister(&self.id, self.seq);
+    }
+}
+
+#[tauri::command]
+pub fn acquire_stream_handle(registry: tauri::State<'_, StreamRegistry>) -> String {
+    registry.acquire()
+}
+
+#[tauri::command]
+pub fn release_stream(handle: String, registry: tauri::State<'_, StreamRegistry>) -> bool {
+    registry.release(&handle)
+}
+
+// 取回流最近发送过的数据（有上限），用于前端订阅晚于流开始时补齐
+#[tauri::command]
+pub fn get_stream_buffer(
+    stream_id: String,
+    registry: tauri::State<'_, StreamRegistry>,
+) -> Result<Vec<StreamData>, String> {
+    registry
+        .recent(&stream_id)
+        .ok_or_else(|| format!("Unknown stream: {}", stream_id))
+}
+
+// 设置了 event_queue_capacity 时，前端处理完 stream-chunk 后用其 seq 确认
+#[tauri::command]
+pub fn ack_stream_chunk(
+    stream_id: String,
+    seq: u64,
+    registry: tauri::State<'_, StreamRegistry>,
+) -> bool {
+    registry.ack(&stream_id, seq)
+}
+
+#[tauri::command]
+pub fn cancel_stream(stream_id: String, registry: tauri::State<'_, StreamRegistry>) -> bool {
+    registry.cancel(&stream_id)
+}
+
+#[tauri::command]
+pub fn cancel_all_streams(registry: tauri::State<'_, StreamRegistry>) -> usize {
+    registry.cancel_all()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn acquired_handle_is_not_cancelled_on_register() {
+        let registry = StreamRegistry::default();
+        let handle = registry.acquire();
+        let guard = registry.register(Some(handle.clone()));
+        assert!(!guard.token.is_cancelled());
+        assert!(!registry.is_empty());
+    }
+
+    #[test]
+    fn releasing_acquired_handle_cancels_running_stream() {
+        let registry = StreamRegistry::default();
+        let handle = registry.acquire();
+        let guard = registry.register(Some(handle.clone()));
+        assert!(registry.release(&handle));
+        assert!(guard.token.is_cancelled());
+    }
+
+    #[test]
+    fn registering_same_id_cancels_previous_stream() {
+        let registry = StreamRegistry::default();
+        let first = registry.register(Some("chat".to_string()));
+        let second = registry.register(Some("chat".to_string()));
+        assert!(first.token.is_cancelled());
+        assert!(!second.token.is_cancelled());
+        // 旧流的凭证释放时不能注销新流
+        drop(first);
+        assert!(!registry.is_empty());
+        drop(second);
+        assert!(registry.is_empty());
+    }
+
+    #[test]
+    fn ack_only_moves_forward() {
+        let registry = StreamRegistry::default();
+        let guard = registry.register(Some("s".to_string()));
+        assert!(registry.ack("s", 4));
+        assert!(registry.ack("s", 2));
+        assert_eq!(guard.acked.load(Ordering::Relaxed), 5);
+        assert!(!registry.ack("unknown", 0));
+    }
+
+    #[test]
+    fn finished_stream_buffer_stays_until_released() {
+        let registry = StreamRegistry::default();
+        let guard = registry.register(Some("fast".to_string()));
+        guard.recent.lock().unwrap().push_back(StreamData {
+            content: Some("hi".to_string()),
+            ..Default::default()
+        });
+        drop(guard);
+
+        // 流已结束，但订阅较晚的前端仍能补取
+        assert!(registry.is_empty());
+        assert_eq!(registry.recent("fast").unwrap().len(), 1);
+        assert!(registry.release("fast"));
+        assert!(registry.recent("fast").is_none());
+    }
+
+    #[test]
+    fn finished_stream_buffer_expires_after_grace() {
+        let registry = StreamRegistry::default();
+        drop(registry.register(Some("old".to_string())));
+        registry
+            .streams
+            .lock()
+            .unwrap()
+            .get_mut("old")
+            .unwrap()
+            .finished_at = Instant::now().checked_sub(FINISHED_GRACE);
+        assert!(registry.recent("old").is_none());
+    }
+}
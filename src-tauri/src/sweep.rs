@@ -0,0 +1,51 @@
+use futures_util::stream::{self, StreamExt, TryStreamExt};
+use tauri::Manager;
+
+use crate::client::HttpClient;
+use crate::{build_request_body, send_chat_request, ChatOptions, Message, ResponseMessage};
+
+// 同时进行的请求数上限，避免一次扫描触发服务端限流
+const MAX_CONCURRENT: usize = 4;
+
+// 用同一组消息在不同 temperature 下各请求一次，结果按传入顺序与参数一一对应
+#[tauri::command]
+pub async fn parameter_sweep(
+    mut base_url: String,
+    api_key: String,
+    mut model: String,
+    messages: Vec<Message>,
+    temperatures: Vec<f32>,
+    app_handle: tauri::AppHandle,
+) -> Result<Vec<(f32, ResponseMessage)>, String> {
+    let options = ChatOptions::resolve(None, &mut base_url, &mut model, &app_handle)?;
+    let url = format!("{}/chat/completions", base_url);
+    let client = app_handle.state::<HttpClient>().client_for(&url)?;
+    let messages = crate::attachments::resolve_attachments(messages, app_handle.clone())?;
+
+    let requests = temperatures.into_iter().map(|temperature| {
+        let options = ChatOptions {
+            temperature: Some(temperature),
+            ..options.clone()
+        };
+        let (client, url, api_key, model, messages, app_handle) =
+            (&client, &url, &api_key, &model, &messages, &app_handle);
+        async move {
+            let request_body = build_request_body(model, messages, false, false, &options)?;
+            let response =
+                send_chat_request(client, url, api_key, &request_body, &options, app_handle)
+                    .await?;
+            let message = response
+                .choices
+                .into_iter()
+                .next()
+                .map(|choice| choice.message)
+                .ok_or_else(|| format!("No choices returned at temperature {}", temperature))?;
+            Ok::<_, String>((temperature, message))
+        }
+    });
+
+    stream::iter(requests)
+        .buffered(MAX_CONCURRENT)
+        .try_collect()
+        .await
+}
@@ -0,0 +1,227 @@
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::fs;
+use std::path::PathBuf;
+
+use serde::{Deserialize, Serialize};
+use tauri::{AppHandle, Manager};
+
+use crate::Message;
+
+// 可重复使用的提示模板。内容中的 {{变量}} 在渲染时替换；
+// 需要字面量 {{ 或 }} 时写成 \{{ / \}}
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TemplateSpec {
+    #[serde(default)]
+    pub system: Option<String>,
+    #[serde(default)]
+    pub messages: Vec<Message>,
+    #[serde(default)]
+    pub variables: Vec<String>,
+}
+
+// 所有模板保存在应用数据目录下的 templates.json 中
+fn templates_path(app_handle: &AppHandle) -> Result<PathBuf, String> {
+    let dir = app_handle
+        .path()
+        .app_data_dir()
+        .map_err(|e| format!("Failed to resolve app data dir: {}", e))?;
+    fs::create_dir_all(&dir).map_err(|e| format!("Failed to create app data dir: {}", e))?;
+    Ok(dir.join("templates.json"))
+}
+
+fn load_all(app_handle: &AppHandle) -> Result<BTreeMap<String, TemplateSpec>, String> {
+    let path = templates_path(app_handle)?;
+    if !path.exists() {
+        return Ok(BTreeMap::new());
+    }
+    let text = fs::read_to_string(&path).map_err(|e| format!("Failed to read templates: {}", e))?;
+    serde_json::from_str(&text).map_err(|e| format!("Failed to parse templates: {}", e))
+}
+
+fn save_all(
+    app_handle: &AppHandle,
+    templates: &BTreeMap<String, TemplateSpec>,
+) -> Result<(), String> {
+    let path = templates_path(app_handle)?;
+    let text = serde_json::to_string_pretty(templates)
+        .map_err(|e| format!("Failed to serialize templates: {}", e))?;
+    let tmp = path.with_extension("json.tmp");
+    fs::write(&tmp, text).map_err(|e| format!("Failed to write templates: {}", e))?;
+    fs::rename(&tmp, &path).map_err(|e| format!("Failed to write templates: {}", e))
+}
+
+// 逐个处理占位符：lookup 返回 None 时原样保留占位符
+fn substitute(
+    text: &str,
+    lookup: &mut impl FnMut(&str) -> Option<String>,
+) -> Result<String, String> {
+    let mut out = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(pos) = rest.find(['\\', '{']) {
+        out.push_str(&rest[..pos]);
+        let tail = &rest[pos..];
+        if let Some(after) = tail.strip_prefix("\\{{") {
+            out.push_str("{{");
+            rest = after;
+        } else if let Some(after) = tail.strip_prefix("\\}}") {
+            out.push_str("}}");
+            rest = after;
+        } else if let Some(after) = tail.strip_prefix("{{") {
+            let end = after
+                .find("}}")
+                .ok_or_else(|| "Unclosed {{ placeholder in template".to_string())?;
+            let name = after[..end].trim();
+            if name.is_empty() {
+                return Err("Empty placeholder {{}} in template".to_string());
+            }
+            match lookup(name) {
+                Some(value) => out.push_str(&value),
+                None => out.push_str(&tail[..end + 4]),
+            }
+            rest = &after[end + 2..];
+        } else {
+            out.push_str(&tail[..1]);
+            rest = &tail[1..];
+        }
+    }
+    out.push_str(rest);
+    Ok(out)
+}
+
+// 字符串内容和多模态数组中的 text 部分都会替换
+fn map_content(
+    content: &serde_json::Value,
+    f: &mut impl FnMut(&str) -> Result<String, String>,
+) -> Result<serde_json::Value, String> {
+    match content {
+        serde_json::Value::String(text) => Ok(serde_json::Value::String(f(text)?)),
+        serde_json::Value::Array(parts) => parts
+            .iter()
+            .map(|part| {
+                let mut part = part.clone();
+                if part["type"] == "text" {
+                    if let Some(text) = part["text"].as_str() {
+                        part["text"] = serde_json::Value::String(f(text)?);
+                    }
+                }
+                Ok(part)
+            })
+            .collect::<Result<Vec<_>, String>>()
+            .map(serde_json::Value::Array),
+        other => Ok(other.clone()),
+    }
+}
+
+fn template_messages(spec: &TemplateSpec) -> Vec<Message> {
+    spec.system
+        .iter()
+        .map(|system| Message {
+            role: "system".to_string(),
+            content: serde_json::Value::String(system.clone()),
+            pinned: None,
+        })
+        .chain(spec.messages.iter().cloned())
+        .collect()
+}
+
+// 保存前检查占位符语法，并确认用到的变量都已声明
+fn validate(spec: &TemplateSpec) -> Result<(), String> {
+    let declared: BTreeSet<&str> = spec.variables.iter().map(String::as_str).collect();
+    let mut undeclared = BTreeSet::new();
+    for message in template_messages(spec) {
+        map_content(&message.content, &mut |text| {
+            substitute(text, &mut |name| {
+                if !declared.contains(name) {
+                    undeclared.insert(name.to_string());
+                }
+                Some(String::new())
+            })
+        })?;
+    }
+    if !undeclared.is_empty() {
+        return Err(format!(
+            "Template uses undeclared variables: {}",
+            undeclared.into_iter().collect::<Vec<_>>().join(", ")
+        ));
+    }
+    Ok(())
+}
+
+pub fn render(
+    spec: &TemplateSpec,
+    values: &HashMap<String, String>,
+) -> Result<Vec<Message>, String> {
+    let mut missing = BTreeSet::new();
+    let messages = template_messages(spec)
+        .into_iter()
+        .map(|message| {
+            let content = map_content(&message.content, &mut |text| {
+                substitute(text, &mut |name| {
+                    let value = values.get(name).cloned();
+                    if value.is_none() {
+                        missing.insert(name.to_string());
+                    }
+                    value
+                })
+            })?;
+            Ok(Message { content, ..message })
+        })
+        .collect::<Result<Vec<_>, String>>()?;
+    if !missing.is_empty() {
+        return Err(format!(
+            "Missing values for template variables: {}",
+            missing.into_iter().collect::<Vec<_>>().join(", ")
+        ));
+    }
+    Ok(messages)
+}
+
+#[tauri::command]
+pub fn save_template(
+    name: String,
+    spec: TemplateSpec,
+    app_handle: AppHandle,
+) -> Result<(), String> {
+    let name = name.trim().to_string();
+    if name.is_empty() {
+        return Err("Template name cannot be empty".to_string());
+    }
+    validate(&spec)?;
+    let mut templates = load_all(&app_handle)?;
+    templates.insert(name, spec);
+    save_all(&app_handle, &templates)
+}
+
+#[tauri::command]
+pub fn render_template(
+    name: String,
+    values: HashMap<String, String>,
+    app_handle: AppHandle,
+) -> Result<Vec<Message>, String> {
+    let spec = load_all(&app_handle)?
+        .remove(&name)
+        .ok_or_else(|| format!("Template not found: {}", name))?;
+    render(&spec, &values)
+}
+
+#[tauri::command]
+pub fn list_templates(app_handle: AppHandle) -> Result<Vec<String>, String> {
+    Ok(load_all(&app_handle)?.into_keys().collect())
+}
+
+#[tauri::command]
+pub fn load_template(name: String, app_handle: AppHandle) -> Result<TemplateSpec, String> {
+    load_all(&app_handle)?
+        .remove(&name)
+        .ok_or_else(|| format!("Template not found: {}", name))
+}
+
+#[tauri::command]
+pub fn delete_template(name: String, app_handle: AppHandle) -> Result<bool, String> {
+    let mut templates = load_all(&app_handle)?;
+    let removed = templates.remove(&name).is_some();
+    if removed {
+        save_all(&app_handle, &templates)?;
+    }
+    Ok(removed)
+}
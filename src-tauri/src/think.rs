@@ -0,0 +1,183 @@
+const OPEN_TAGS: [&str; 2] = ["<think>", "<thinking>"];
+const CLOSE_TAGS: [&str; 2] = ["</think>", "</thinking>"];
+
+// 部分本地模型把思考过程以 <think>...</think> 写在 content 里，这里将其拆到 reasoning_content。
+// 流式时标签可能被分片截断，疑似标签开头的尾部先留着，等下一个分片再判断
+#[derive(Default)]
+pub struct ThinkTagSplitter {
+    pending: String,
+    in_think: bool,
+    // 思考块结束后紧跟的空行不属于正文
+    trim_next: bool,
+}
+
+// 按原始顺序排列的片段：同一个分片里可能先是思考内容、后是正文（或相反）
+#[derive(Debug, Clone, PartialEq)]
+pub enum Segment {
+    Content(String),
+    Reasoning(String),
+}
+
+#[derive(Debug, Default, PartialEq)]
+pub struct Split {
+    pub content: String,
+    pub reasoning: String,
+}
+
+fn find_tag(text: &str, tags: &[&str]) -> Option<(usize, usize)> {
+    tags.iter()
+        .filter_map(|tag| text.find(tag).map(|pos| (pos, tag.len())))
+        .min_by_key(|&(pos, _)| pos)
+}
+
+// 末尾可能是某个标签前半截的位置
+fn partial_tag_start(text: &str, tags: &[&str]) -> Option<usize> {
+    let pos = text.rfind('<')?;
+    let tail = &text[pos..];
+    tags.iter().any(|tag| tag.starts_with(tail)).then_some(pos)
+}
+
+impl ThinkTagSplitter {
+    pub fn feed(&mut self, text: &str) -> Vec<Segment> {
+        self.pending.push_str(text);
+        let mut segments = Vec::new();
+
+        loop {
+            let tags: &[&str] = if self.in_think {
+                &CLOSE_TAGS
+            } else {
+                &OPEN_TAGS
+            };
+            match find_tag(&self.pending, tags) {
+                Some((pos, len)) => {
+                    let before = self.pending[..pos].to_string();
+                    self.pending.drain(..pos + len);
+                    self.push(&mut segments, &before);
+                    self.in_think = !self.in_think;
+                    self.trim_next = !self.in_think;
+                }
+                None => {
+                    let keep = partial_tag_start(&self.pending, tags).unwrap_or(self.pending.len());
+                    let ready: String = self.pending.drain(..keep).collect();
+                    self.push(&mut segments, &ready);
+                    return segments;
+                }
+            }
+        }
+    }
+
+    // 流结束时输出留存的内容；未闭合的思考块按思考内容处理
+    pub fn finish(&mut self) -> Vec<Segment> {
+        let rest = std::mem::take(&mut self.pending);
+        let mut segments = Vec::new();
+        self.push(&mut segments, &rest);
+        segments
+    }
+
+    fn push(&mut self, segments: &mut Vec<Segment>, text: &str) {
+        let text = if self.trim_next && !self.in_think {
+            text.trim_start()
+        } else {
+            text
+        };
+        if text.is_empty() {
+            return;
+        }
+        if self.in_think {
+            segments.push(Segment::Reasoning(text.to_string()));
+        } else {
+            self.trim_next = false;
+            segments.push(Segment::Content(text.to_string()));
+        }
+    }
+}
+
+// 一次性处理完整回复
+pub fn extract_think_tags(text: &str) -> Split {
+    let mut splitter = ThinkTagSplitter::default();
+    let mut segments = splitter.feed(text);
+    segments.extend(splitter.finish());
+
+    let mut split = Split::default();
+    for segment in segments {
+        match segment {
+            Segment::Content(text) => split.content.push_str(&text),
+            Segment::Reasoning(text) => split.reasoning.push_str(&text),
+        }
+    }
+    split
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn split_stream(chunks: &[&str]) -> Split {
+        let mut splitter = ThinkTagSplitter::default();
+        let mut segments: Vec<Segment> = chunks.iter().flat_map(|c| splitter.feed(c)).collect();
+        segments.extend(splitter.finish());
+        let mut split = Split::default();
+        for segment in segments {
+            match segment {
+                Segment::Content(text) => split.content.push_str(&text),
+                Segment::Reasoning(text) => split.reasoning.push_str(&text),
+            }
+        }
+        split
+    }
+
+    #[test]
+    fn tags_split_across_chunks() {
+        let split = split_stream(&["Hi <thi", "nk>step one", ", step two</th", "ink>\n\nAnswer"]);
+        assert_eq!(split.reasoning, "step one, step two");
+        assert_eq!(split.content, "Hi Answer");
+    }
+
+    #[test]
+    fn char_by_char_matches_whole_text() {
+        let text = "<thinking>plan</thinking>\nResult: a < b";
+        let chars: Vec<String> = text.chars().map(String::from).collect();
+        let chunks: Vec<&str> = chars.iter().map(String::as_str).collect();
+        assert_eq!(split_stream(&chunks), extract_think_tags(text));
+        assert_eq!(
+            extract_think_tags(text),
+            Split {
+                content: "Result: a < b".to_string(),
+                reasoning: "plan".to_string(),
+            }
+        );
+    }
+
+    #[test]
+    fn unclosed_think_block_is_reasoning() {
+        let split = split_stream(&["<think>still thinking", " when cut off"]);
+        assert_eq!(split.reasoning, "still thinking when cut off");
+        assert!(split.content.is_empty());
+    }
+
+    #[test]
+    fn segments_keep_order_within_a_chunk() {
+        let mut splitter = ThinkTagSplitter::default();
+        assert_eq!(
+            splitter.feed("<think>a</think>b"),
+            vec![
+                Segment::Reasoning("a".to_string()),
+                Segment::Content("b".to_string())
+            ]
+        );
+    }
+
+    #[test]
+    fn trailing_partial_tag_is_held_back() {
+        let mut splitter = ThinkTagSplitter::default();
+        assert_eq!(
+            splitter.feed("text <thi"),
+            vec![Segment::Content("text ".to_string())]
+        );
+        // 不是标签时在流结束时原样输出
+        assert_eq!(
+            splitter.finish(),
+            vec![Segment::Content("<thi".to_string())]
+        );
+    }
+}
@@ -0,0 +1,77 @@
+use std::collections::VecDeque;
+use std::time::{Duration, Instant};
+
+use serde::Serialize;
+
+// 按最近一段时间的生成速度估算剩余时间
+const WINDOW: Duration = Duration::from_secs(10);
+// 重新计算并上报的间隔
+const REPORT_INTERVAL: Duration = Duration::from_secs(1);
+
+#[derive(Debug, Clone, Serialize)]
+pub struct StreamEta {
+    pub tokens_generated: u32,
+    pub tokens_per_second: f64,
+    pub eta_seconds: Option<f64>,
+}
+
+pub struct ThroughputTracker {
+    max_tokens: u32,
+    samples: VecDeque<(Instant, u32)>,
+    total: u32,
+    last_report: Instant,
+}
+
+impl ThroughputTracker {
+    pub fn new(max_tokens: u32) -> Self {
+        ThroughputTracker {
+            max_tokens,
+            samples: VecDeque::new(),
+            total: 0,
+            last_report: Instant::now(),
+        }
+    }
+
+    // 记录新生成的 token 数；到了上报间隔时返回最新估算
+    pub fn record(&mut self, tokens: u32) -> Option<StreamEta> {
+        let now = Instant::now();
+        self.total += tokens;
+        self.samples.push_back((now, tokens));
+        while self
+            .samples
+            .front()
+            .is_some_and(|&(at, _)| now.duration_since(at) > WINDOW)
+        {
+            self.samples.pop_front();
+        }
+
+        if now.duration_since(self.last_report) < REPORT_INTERVAL {
+            return None;
+        }
+        self.last_report = now;
+        Some(self.estimate(now))
+    }
+
+    fn estimate(&self, now: Instant) -> StreamEta {
+        let tokens: u32 = self.samples.iter().map(|&(_, n)| n).sum();
+        let elapsed = self
+            .samples
+            .front()
+            .map(|&(at, _)| now.duration_since(at).as_secs_f64())
+            .unwrap_or(0.0);
+        let tokens_per_second = if elapsed > 0.0 {
+            tokens as f64 / elapsed
+        } else {
+            0.0
+        };
+        // 速度未知时不给出估算
+        let eta_seconds = (tokens_per_second > 0.0)
+            .then(|| self.max_tokens.saturating_sub(self.total) as f64 / tokens_per_second);
+
+        StreamEta {
+            tokens_generated: self.total,
+            tokens_per_second,
+            eta_seconds,
+        }
+    }
+}
@@ -0,0 +1,178 @@
+use std::collections::BTreeMap;
+
+use serde::{Deserialize, Serialize};
+
+use crate::{FunctionCall, ToolCall};
+
+// 流式分片中 delta.tool_calls 的原始格式：同一轮的多个调用按 index 交错下发，
+// id 和 name 只在该调用的第一个分片中出现
+#[derive(Debug, Deserialize)]
+pub struct WireToolCallDelta {
+    #[serde(default)]
+    pub index: u32,
+    #[serde(default)]
+    pub id: Option<String>,
+    #[serde(default)]
+    pub function: Option<WireFunctionDelta>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+pub struct WireFunctionDelta {
+    #[serde(default)]
+    pub name: Option<String>,
+    #[serde(default)]
+    pub arguments: Option<String>,
+}
+
+// 发给前端的工具调用增量，前端按 index 拼接 arguments
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ToolCallDelta {
+    pub index: u32,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub id: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    #[serde(default)]
+    pub arguments: String,
+}
+
+impl From<&WireToolCallDelta> for ToolCallDelta {
+    fn from(wire: &WireToolCallDelta) -> Self {
+        let function = wire.function.as_ref();
+        ToolCallDelta {
+            index: wire.index,
+            id: wire.id.clone().filter(|id| !id.is_empty()),
+            name: function
+                .and_then(|f| f.name.clone())
+                .filter(|name| !name.is_empty()),
+            arguments: function
+                .and_then(|f| f.arguments.clone())
+                .unwrap_or_default(),
+        }
+    }
+}
+
+// 合并批量发送时的相邻增量：同一 index 的后续分片直接拼接 arguments
+pub fn merge(target: &mut Vec<ToolCallDelta>, deltas: Vec<ToolCallDelta>) {
+    for delta in deltas {
+        match target.last_mut() {
+            Some(last)
+                if last.index == delta.index && delta.id.is_none() && delta.name.is_none() =>
+            {
+                last.arguments.push_str(&delta.arguments);
+            }
+            _ => target.push(delta),
+        }
+    }
+}
+
+// 按 index 累积各个工具调用，流结束时得到完整的调用列表
+#[derive(Debug, Default)]
+pub struct ToolCallAccumulator {
+    calls: BTreeMap<u32, ToolCall>,
+}
+
+impl ToolCallAccumulator {
+    pub fn push(&mut self, delta: &ToolCallDelta) {
+        let call = self.calls.entry(delta.index).or_insert_with(|| ToolCall {
+            id: String::new(),
+            call_type: "function".to_string(),
+            function: FunctionCall {
+                name: String::new(),
+                arguments: String::new(),
+            },
+        });
+        if let Some(id) = &delta.id {
+            call.id = id.clone();
+        }
+        if let Some(name) = &delta.name {
+            call.function.name = name.clone();
+        }
+        call.function.arguments.push_str(&delta.arguments);
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.calls.is_empty()
+    }
+
+    // 按 index 顺序返回
+    pub fn finish(self) -> Vec<ToolCall> {
+        self.calls.into_values().collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    // 两个调用的参数分片交错到达，第二个调用先开始
+    fn interleaved_stream() -> Vec<ToolCallDelta> {
+        let chunks = json!([
+            [{ "index": 1, "id": "call_b", "function": { "name": "get_time", "arguments": "" } }],
+            [{ "index": 0, "id": "call_a", "function": { "name": "get_weather", "arguments": "{\"ci" } }],
+            [{ "index": 1, "function": { "arguments": "{\"tz\":" } }],
+            [{ "index": 0, "function": { "arguments": "ty\":\"Paris\"}" } }],
+            [{ "index": 1, "function": { "arguments": "\"UTC\"}" } }],
+        ]);
+        serde_json::from_value::<Vec<Vec<WireToolCallDelta>>>(chunks)
+            .unwrap()
+            .iter()
+            .flatten()
+            .map(ToolCallDelta::from)
+            .collect()
+    }
+
+    #[test]
+    fn accumulates_interleaved_calls_by_index() {
+        let mut accumulator = ToolCallAccumulator::default();
+        for delta in &interleaved_stream() {
+            accumulator.push(delta);
+        }
+        let calls = accumulator.finish();
+        assert_eq!(calls.len(), 2);
+        assert_eq!(calls[0].id, "call_a");
+        assert_eq!(calls[0].function.name, "get_weather");
+        assert_eq!(calls[0].function.arguments, r#"{"city":"Paris"}"#);
+        assert_eq!(calls[1].id, "call_b");
+        assert_eq!(calls[1].function.name, "get_time");
+        assert_eq!(calls[1].function.arguments, r#"{"tz":"UTC"}"#);
+        assert!(calls.iter().all(|call| call.call_type == "function"));
+    }
+
+    #[test]
+    fn empty_id_and_name_are_dropped() {
+        let wire: WireToolCallDelta = serde_json::from_value(json!({
+            "index": 0,
+            "id": "",
+            "function": { "name": "", "arguments": "{}" },
+        }))
+        .unwrap();
+        let delta = ToolCallDelta::from(&wire);
+        assert_eq!((delta.id, delta.name), (None, None));
+        assert_eq!(delta.arguments, "{}");
+    }
+
+    #[test]
+    fn merge_joins_only_adjacent_fragments_of_same_call() {
+        let mut merged = Vec::new();
+        merge(&mut merged, interleaved_stream());
+        // 交错的分片不能跨过其他调用合并
+        assert_eq!(merged.len(), 5);
+
+        let fragment = |arguments: &str| ToolCallDelta {
+            index: 0,
+            id: None,
+            name: None,
+            arguments: arguments.to_string(),
+        };
+        let mut merged = vec![ToolCallDelta {
+            id: Some("call_a".to_string()),
+            name: Some("get_weather".to_string()),
+            ..fragment("{")
+        }];
+        merge(&mut merged, vec![fragment("\"city\""), fragment(":1}")]);
+        assert_eq!(merged.len(), 1);
+        assert_eq!(merged[0].arguments, r#"{"city":1}"#);
+    }
+}
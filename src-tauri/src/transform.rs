@@ -0,0 +1,554 @@
+// 在发送前对流式正文做实时变换。增量可能在单词或标记中间断开，
+// 实现需要把尚无法判断的尾部留到下一次 feed
+pub trait StreamTransform: Send {
+    fn feed(&mut self, text: &str) -> String;
+
+    // 流结束时输出留存的内容并复位
+    fn finish(&mut self) -> String;
+}
+
+// 按名称选择内置变换；none 或未指定时返回 None
+pub fn by_name(name: Option<&str>) -> Result<Option<Box<dyn StreamTransform>>, String> {
+    match name.unwrap_or("none") {
+        "none" => Ok(None),
+        "strip_markdown" => Ok(Some(Box::<StripMarkdown>::default())),
+        "mask_profanity" => Ok(Some(Box::<MaskProfanity>::default())),
+        other => Err(format!("Unknown stream transform: {}", other)),
+    }
+}
+
+// 依次应用多个变换，前一个的输出作为后一个的输入
+struct Chain(Vec<Box<dyn StreamTransform>>);
+
+impl StreamTransform for Chain {
+    fn feed(&mut self, text: &str) -> String {
+        self.0
+            .iter_mut()
+            .fold(text.to_string(), |text, transform| transform.feed(&text))
+    }
+
+    fn finish(&mut self) -> String {
+        self.0.iter_mut().fold(String::new(), |rest, transform| {
+            let mut text = transform.feed(&rest);
+            text.push_str(&transform.finish());
+            text
+        })
+    }
+}
+
+pub fn chain(mut transforms: Vec<Box<dyn StreamTransform>>) -> Option<Box<dyn StreamTransform>> {
+    match transforms.len() {
+        0 => None,
+        1 => transforms.pop(),
+        _ => Some(Box::new(Chain(transforms))),
+    }
+}
+
+// 中日韩文字没有空格分词，每个字都可以单独输出
+fn is_cjk(c: char) -> bool {
+    matches!(
+        c,
+        '\u{3040}'..='\u{30FF}'
+            | '\u{3400}'..='\u{4DBF}'
+            | '\u{4E00}'..='\u{9FFF}'
+            | '\u{AC00}'..='\u{D7AF}'
+            | '\u{F900}'..='\u{FAFF}'
+    )
+}
+
+fn is_word_char(c: char) -> bool {
+    c.is_alphanumeric() && !is_cjk(c)
+}
+
+// 只在单词边界（空白、标点、中日韩文字）处输出，末尾未写完的单词留到下一个增量，
+// 避免前端渲染半个单词造成闪烁
+#[derive(Default)]
+pub struct WordBoundary {
+    pending: String,
+}
+
+impl StreamTransform for WordBoundary {
+    fn feed(&mut self, text: &str) -> String {
+        self.pending.push_str(text);
+        let split = self
+            .pending
+            .char_indices()
+            .rev()
+            .take_while(|&(_, c)| is_word_char(c))
+            .last()
+            .map_or(self.pending.len(), |(i, _)| i);
+        self.pending.drain(..split).collect()
+    }
+
+    fn finish(&mut self) -> String {
+        std::mem::take(&mut self.pending)
+    }
+}
+
+// 会破坏渲染或复制粘贴的不可见字符：零宽空格、字词连接符、BOM、双向文本控制符
+fn is_invisible(c: char) -> bool {
+    matches!(
+        c,
+        '\u{200B}' | '\u{2060}' | '\u{FEFF}' | '\u{202A}'..='\u{202E}' | '\u{2066}'..='\u{2069}'
+    )
+}
+
+// 去掉控制字符和不可见字符，保留换行和制表符；零宽连接符（emoji 组合需要）不受影响。
+// 按字符处理，任意切分的增量分别清理与整体清理结果一致
+pub fn sanitize(text: &str) -> String {
+    text.chars()
+        .filter(|&c| matches!(c, '\n' | '\r' | '\t') || !(c.is_control() || is_invisible(c)))
+        .collect()
+}
+
+// 链接文字超过这个长度仍未闭合时按普通文本输出，避免无限留存
+const MAX_LINK_BYTES: usize = 300;
+
+// 行首可能构成标记（标题、引用、列表、代码围栏、分隔线）的字符
+fn is_line_marker(c: char) -> bool {
+    matches!(
+        c,
+        ' ' | '\t' | '#' | '>' | '-' | '*' | '+' | '_' | '`' | '~' | '.' | ')'
+    ) || c.is_ascii_digit()
+}
+
+fn is_inline_special(c: char) -> bool {
+    matches!(c, '\n' | '`' | '*' | '_' | '~' | '!' | '[')
+}
+
+// Markdown 转纯文本：去掉强调、行内代码、标题、引用和代码围栏标记，链接只保留文字
+#[derive(Default)]
+pub struct StripMarkdown {
+    pending: String,
+    // 默认在行首
+    mid_line: bool,
+    in_code_block: bool,
+    in_inline_code: bool,
+    // 上一个已处理的输入字符，用于判断 * 和 _ 是否为强调标记
+    prev: Option<char>,
+}
+
+impl StripMarkdown {
+    fn drain(&mut self, eof: bool) -> String {
+        let mut pending = std::mem::take(&mut self.pending);
+        let mut out = String::new();
+        let mut pos = 0;
+        while pos < pending.len() {
+            let rest = &pending[pos..];
+            let Some((used, text)) = self.step(rest, eof) else {
+                break;
+            };
+            if let Some(last) = rest[..used].chars().last() {
+                self.prev = Some(last);
+            }
+            out.push_str(&text);
+            pos += used;
+        }
+        pending.drain(..pos);
+        self.pending = pending;
+        out
+    }
+
+    // 处理 rest 开头的一段，返回 (消耗的字节数, 输出)；需要更多输入才能判断时返回 None
+    fn step(&mut self, rest: &str, eof: bool) -> Option<(usize, String)> {
+        if !self.mid_line {
+            return self.line_start(rest, eof);
+        }
+
+        let first = rest.chars().next()?;
+        if first == '\n' {
+            self.mid_line = false;
+            self.in_inline_code = false;
+            return Some((1, "\n".to_string()));
+        }
+
+        if self.in_code_block {
+            let end = rest.find('\n').unwrap_or(rest.len());
+            return Some((end, rest[..end].to_string()));
+        }
+
+        if self.in_inline_code && first != '`' {
+            let end = rest.find(['`', '\n']).unwrap_or(rest.len());
+            return Some((end, rest[..end].to_string()));
+        }
+
+        match first {
+            '`' => {
+                let run = rest.len() - rest.trim_start_matches('`').len();
+                if run == rest.len() && !eof {
+                    return None;
+                }
+                self.in_inline_code = !self.in_inline_code;
+                Some((run, String::new()))
+            }
+            '*' | '_' | '~' => {
+                let run = rest.len() - rest.trim_start_matches(first).len();
+                let next = rest[run..].chars().next();
+                if next.is_none() && !eof {
+                    return None;
+                }
+                let literal = if first == '~' {
+                    // 单个 ~ 常用于表示“约”，只把 ~~ 当作删除线
+                    run < 2
+                } else {
+                    // 中文里 **粗体** 常紧贴文字，* 只看 ASCII 字母数字
+                    let alnum = |c: Option<char>| {
+                        c.is_some_and(|c| {
+                            if first == '*' {
+                                c.is_ascii_alphanumeric()
+                            } else {
+                                c.is_alphanumeric()
+                            }
+                        })
+                    };
+                    let space = |c: Option<char>| c.is_none_or(char::is_whitespace);
+                    // snake_case、2*3 这类两侧都是字母数字，或 a * b 这类两侧都是空白
+                    (alnum(self.prev) && alnum(next)) || (space(self.prev) && space(next))
+                };
+                let text = if literal { &rest[..run] } else { "" };
+                Some((run, text.to_string()))
+            }
+            '!' => {
+                if rest.len() == 1 && !eof {
+                    return None;
+                }
+                if !rest[1..].starts_with('[') {
+                    return Some((1, "!".to_string()));
+                }
+                // 图片 ![说明](地址) 只保留说明文字
+                match self.link(&rest[1..], eof) {
+                    Some(Some((used, text))) => Some((used + 1, text)),
+                    Some(None) => Some((1, "!".to_string())),
+                    None => None,
+                }
+            }
+            '[' => match self.link(rest, eof) {
+                Some(Some(link)) => Some(link),
+                Some(None) => Some((1, "[".to_string())),
+                None => None,
+            },
+            _ => {
+                let end = rest.find(is_inline_special).unwrap_or(rest.len());
+                Some((end, rest[..end].to_string()))
+            }
+        }
+    }
+
+    // [文字](地址)：外层 None 表示需要更多输入，内层 None 表示不是链接
+    fn link(&self, rest: &str, eof: bool) -> Option<Option<(usize, String)>> {
+        let incomplete = |scanned: &str| {
+            if eof || scanned.contains('\n') || scanned.len() > MAX_LINK_BYTES {
+                Some(None)
+            } else {
+                None
+            }
+        };
+        let Some(close) = rest.find(']') else {
+            return incomplete(rest);
+        };
+        if rest[..close].contains('\n') {
+            return Some(None);
+        }
+        let after = &rest[close + 1..];
+        match after.chars().next() {
+            None if !eof => None,
+            Some('(') => match after.find(')') {
+                Some(end) if !after[..end].contains('\n') => {
+                    Some(Some((close + 1 + end + 1, rest[1..close].to_string())))
+                }
+                Some(_) => Some(None),
+                None => incomplete(after),
+            },
+            _ => Some(None),
+        }
+    }
+
+    fn line_start(&mut self, rest: &str, eof: bool) -> Option<(usize, String)> {
+        let prefix_end = rest
+            .find(|c: char| !is_line_marker(c))
+            .unwrap_or(rest.len());
+        if prefix_end == rest.len() && !eof {
+            return None;
+        }
+        let line_end = rest.find('\n');
+        let prefix = &rest[..prefix_end];
+        let indent_len = prefix.len() - prefix.trim_start().len();
+        let (indent, marker) = prefix.split_at(indent_len);
+
+        // 代码围栏所在行（含语言标记）整行去掉
+        if marker.starts_with("```") || marker.starts_with("~~~") {
+            let Some(line_end) = line_end else {
+                if !eof {
+                    return None;
+                }
+                self.in_code_block = !self.in_code_block;
+                return Some((rest.len(), String::new()));
+            };
+            self.in_code_block = !self.in_code_block;
+            return Some((line_end + 1, String::new()));
+        }
+
+        self.mid_line = true;
+        if self.in_code_block {
+            return Some((0, String::new()));
+        }
+
+        // 分隔线 --- / *** / ___
+        let rule = marker.trim_end();
+        if rule.len() >= 3
+            && rule.chars().all(|c| matches!(c, '-' | '*' | '_' | ' '))
+            && rule.chars().all(|c| c == ' ' || rule.starts_with(c))
+            && line_end == Some(prefix_end)
+        {
+            self.mid_line = false;
+            return Some((prefix_end + 1, String::new()));
+        }
+
+        let mut stripped = marker;
+        while let Some(inner) = stripped.strip_prefix('>') {
+            stripped = inner.trim_start_matches(' ');
+        }
+        let hashes = stripped.len() - stripped.trim_start_matches('#').len();
+        if (1..=6).contains(&hashes) && stripped[hashes..].starts_with(' ') {
+            stripped = stripped[hashes..].trim_start_matches(' ');
+        }
+        let output = match stripped.as_bytes() {
+            [b'-' | b'*' | b'+', b' ', ..] => format!("{}• ", indent),
+            _ => {
+                // 未识别的部分交给行内处理（如 **粗体** 开头的行）
+                let used = indent_len + (marker.len() - stripped.len());
+                return Some((used, indent.to_string()));
+            }
+        };
+        let bullet_len = 1 + stripped[1..].len() - stripped[1..].trim_start_matches(' ').len();
+        let used = indent_len + (marker.len() - stripped.len()) + bullet_len;
+        Some((used, output))
+    }
+}
+
+impl StreamTransform for StripMarkdown {
+    fn feed(&mut self, text: &str) -> String {
+        self.pending.push_str(text);
+        self.drain(false)
+    }
+
+    fn finish(&mut self) -> String {
+        let rest = self.drain(true);
+        *self = StripMarkdown::default();
+        rest
+    }
+}
+
+const PROFANITY: &[&str] = &[
+    "fuck",
+    "shit",
+    "bitch",
+    "bastard",
+    "asshole",
+    "cunt",
+    "dick",
+    "piss",
+    "crap",
+    "damn",
+    "motherfucker",
+    "bullshit",
+];
+
+fn is_profane(word: &str) -> bool {
+    let word = word.to_lowercase();
+    PROFANITY.iter().any(|bad| {
+        word.strip_prefix(bad).is_some_and(|suffix| {
+            matches!(
+                suffix,
+                "" | "s" | "es" | "ed" | "er" | "ers" | "ing" | "in" | "y"
+            )
+        })
+    })
+}
+
+// 把脏话替换为等长的 *；单词可能被拆在两个增量里，末尾未结束的单词留到下次判断
+#[derive(Default)]
+pub struct MaskProfanity {
+    pending: String,
+}
+
+impl MaskProfanity {
+    fn mask(text: &str) -> String {
+        let mut out = String::with_capacity(text.len());
+        let mut word = String::new();
+        for c in text.chars() {
+            if c.is_alphabetic() {
+                word.push(c);
+                continue;
+            }
+            Self::push_word(&mut out, &mut word);
+            out.push(c);
+        }
+        Self::push_word(&mut out, &mut word);
+        out
+    }
+
+    fn push_word(out: &mut String, word: &mut String) {
+        if is_profane(word) {
+            out.push_str(&"*".repeat(word.chars().count()));
+        } else {
+            out.push_str(word);
+        }
+        word.clear();
+    }
+}
+
+impl StreamTransform for MaskProfanity {
+    fn feed(&mut self, text: &str) -> String {
+        self.pending.push_str(text);
+        let split = self
+            .pending
+            .char_indices()
+            .rev()
+            .take_while(|(_, c)| c.is_alphabetic())
+            .last()
+            .map_or(self.pending.len(), |(i, _)| i);
+        let ready: String = self.pending.drain(..split).collect();
+        Self::mask(&ready)
+    }
+
+    fn finish(&mut self) -> String {
+        Self::mask(&std::mem::take(&mut self.pending))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn run(transform: &mut dyn StreamTransform, chunks: &[&str]) -> String {
+        let mut out: String = chunks.iter().map(|chunk| transform.feed(chunk)).collect();
+        out.push_str(&transform.finish());
+        out
+    }
+
+    // 在每个字符边界切成两段，结果都应与整段输入一致
+    fn assert_split_invariant<T: StreamTransform + Default>(text: &str, expected: &str) {
+        assert_eq!(run(&mut T::default(), &[text]), expected);
+        for (i, _) in text.char_indices().skip(1) {
+            let (a, b) = text.split_at(i);
+            assert_eq!(run(&mut T::default(), &[a, b]), expected, "split at {}", i);
+        }
+        let chars: Vec<String> = text.chars().map(String::from).collect();
+        let chunks: Vec<&str> = chars.iter().map(String::as_str).collect();
+        assert_eq!(run(&mut T::default(), &chunks), expected, "char by char");
+    }
+
+    #[test]
+    fn strip_markdown_inline_markup() {
+        assert_split_invariant::<StripMarkdown>(
+            "Use **bold**, _italic_, ~~gone~~ and `code` in snake_case or 2*3.",
+            "Use bold, italic, gone and code in snake_case or 2*3.",
+        );
+    }
+
+    #[test]
+    fn strip_markdown_links_and_images() {
+        assert_split_invariant::<StripMarkdown>(
+            "See [the docs](https://example.com) ![logo](a.png) [not a link] here",
+            "See the docs logo [not a link] here",
+        );
+    }
+
+    #[test]
+    fn strip_markdown_block_markers() {
+        assert_split_invariant::<StripMarkdown>(
+            "# Title\n> quoted\n- item\n---\n```rust\nlet *x* = 1;\n```\ndone",
+            "Title\nquoted\n• item\nlet *x* = 1;\ndone",
+        );
+    }
+
+    #[test]
+    fn mask_profanity_across_chunks() {
+        assert_split_invariant::<MaskProfanity>(
+            "What the fuck, this is bullshit. Classic assessment.",
+            "What the ****, this is ********. Classic assessment.",
+        );
+    }
+
+    #[test]
+    fn chain_applies_in_order_and_flushes_each_stage() {
+        let mut chained = chain(vec![
+            Box::<StripMarkdown>::default(),
+            Box::<MaskProfanity>::default(),
+        ])
+        .unwrap();
+        assert_eq!(
+            run(chained.as_mut(), &["**da", "mn** it [sh", "it](x)"]),
+            "**** it ****"
+        );
+    }
+
+    #[test]
+    fn by_name_selects_builtin_transforms() {
+        assert!(by_name(None).unwrap().is_none());
+        assert!(by_name(Some("none")).unwrap().is_none());
+        assert!(by_name(Some("strip_markdown")).unwrap().is_some());
+        assert!(by_name(Some("mask_profanity")).unwrap().is_some());
+        assert_eq!(
+            by_name(Some("uppercase")).err(),
+            Some("Unknown stream transform: uppercase".to_string())
+        );
+    }
+
+    #[test]
+    fn sanitize_strips_control_and_invisible_characters() {
+        assert_eq!(
+            sanitize("a\u{0}b\u{7}c\u{1b}[0m\u{200B}d\u{FEFF}e\u{202E}f"),
+            "abc[0mdef"
+        );
+        // 换行、制表符保留
+        assert_eq!(sanitize("line1\r\n\tline2\n"), "line1\r\n\tline2\n");
+    }
+
+    #[test]
+    fn sanitize_keeps_multibyte_text_and_emoji_joiners() {
+        let family = "👨\u{200D}👩\u{200D}👧";
+        assert_eq!(
+            sanitize(&format!("中文\u{200B}{}é", family)),
+            format!("中文{}é", family)
+        );
+    }
+
+    #[test]
+    fn sanitize_per_delta_matches_whole_text() {
+        let text = "你好\u{200B}，\u{0}世界 👋🏽\u{FEFF}!";
+        let whole = sanitize(text);
+        for (i, _) in text.char_indices() {
+            let (a, b) = text.split_at(i);
+            assert_eq!(sanitize(a) + &sanitize(b), whole, "split at {}", i);
+        }
+    }
+
+    #[test]
+    fn word_boundary_holds_partial_trailing_word() {
+        let mut words = WordBoundary::default();
+        assert_eq!(words.feed("Hel"), "");
+        assert_eq!(words.feed("lo, wor"), "Hello, ");
+        assert_eq!(words.feed("ld and"), "world ");
+        assert_eq!(words.finish(), "and");
+        // finish 之后重新开始
+        assert_eq!(words.feed("x"), "");
+    }
+
+    #[test]
+    fn word_boundary_emits_cjk_immediately() {
+        let mut words = WordBoundary::default();
+        assert_eq!(words.feed("你好wor"), "你好");
+        assert_eq!(words.feed("ld世界"), "world世界");
+        assert_eq!(words.finish(), "");
+    }
+
+    #[test]
+    fn word_boundary_split_invariant() {
+        assert_split_invariant::<WordBoundary>(
+            "Streaming deltas split mid-word, e.g. \"inter\" + \"national\".",
+            "Streaming deltas split mid-word, e.g. \"inter\" + \"national\".",
+        );
+    }
+}